@@ -1,4 +1,11 @@
-use etf_tool::etf::ETF;
+use etf_tool::classification::{AssetClass, DistributionPolicy, Hedging, Replication};
+use etf_tool::etf::{ETF, HoldingsBasis};
+use etf_tool::identifiers::Identifiers;
+use etf_tool::money::Aum;
+use etf_tool::performance::{Performance, Period, Returns};
+use etf_tool::provider::HoldingsStatus;
+use std::collections::BTreeMap;
+use chrono::NaiveDate;
 use color_eyre::Result;
 
 fn main() -> Result<()> {
@@ -8,16 +15,28 @@ fn main() -> Result<()> {
     let mut etf = ETF {
         name: "Invesco S&P 500 UCITS ETF".to_string(),
         isin: "IE00B23D9570".to_string(),
-        asset_class: "Equity".to_string(),
+        identifiers: Identifiers::default(),
+        listings: Vec::new(),
+        asset_class: Some(AssetClass::Equity),
         ter: 0.05,
         currency: "USD".to_string(),
-        aum: "1000000000".to_string(),
+        aum: Aum::parse("1000000000", Some("EUR"), 1.0),
         currency_exposure: "USD".to_string(),
-        distribution_policy: "Accumulating".to_string(),
-        launch_date: "2010-05-20".to_string(),
-        performance_1y: Some(15.0),
-        performance_ytd: Some(8.5),
+        distribution_policy: Some(DistributionPolicy::Accumulating),
+        replication: Some(Replication::SyntheticSwap),
+        hedging: Some(Hedging::Unhedged),
+        launch_date: NaiveDate::from_ymd_opt(2010, 5, 20),
+        performance: Performance {
+            fund: Returns {
+                cumulative: BTreeMap::from([(Period::OneYear, 15.0), (Period::YearToDate, 8.5)]),
+                discrete: Vec::new(),
+            },
+            index: Returns::default(),
+        },
         holdings: Vec::new(),
+        as_of: None,
+        holdings_as_of: None,
+        holdings_basis: HoldingsBasis::Portfolio,
         issuer: "Invesco".to_string(),
         product_url: None,
     };
@@ -25,12 +44,16 @@ fn main() -> Result<()> {
     println!("Loading holdings for {} ({})", etf.name, etf.isin);
     
     // Load holdings
-    etf.load_holdings()?;
+    if etf.load_holdings()? == HoldingsStatus::Unsupported {
+        println!("Holdings are not supported for issuer {}", etf.issuer);
+        return Ok(());
+    }
     
     // Print first 10 holdings
     println!("First 10 holdings:");
-    for (i, (isin, weight)) in etf.holdings.iter().take(10).enumerate() {
-        println!("{}. ISIN: {}, Weight: {:.2}%", i + 1, isin, weight);
+    for (i, holding) in etf.holdings.iter().take(10).enumerate() {
+        let isin = holding.isin.as_deref().unwrap_or("-");
+        println!("{}. {} (ISIN: {}), Weight: {:.2}%", i + 1, holding.name, isin, holding.weight);
     }
     
     println!("Total holdings: {}", etf.holdings.len());
@@ -0,0 +1,313 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use crate::etf::ETF;
+use crate::identifiers;
+use crate::provider::{HoldingsStatus, ProviderRegistry};
+
+const DEFAULT_CONCURRENCY: usize = 4;
+
+#[derive(Debug, Clone)]
+pub struct BulkOptions {
+    pub concurrency: usize,                         // Funds loaded at the same time per issuer
+    pub issuer_concurrency: HashMap<String, usize>, // Overrides of `concurrency` by issuer name
+}
+
+impl Default for BulkOptions {
+    fn default() -> Self {
+        Self {
+            concurrency: DEFAULT_CONCURRENCY,
+            issuer_concurrency: HashMap::new(),
+        }
+    }
+}
+
+impl BulkOptions {
+    pub fn with_issuer_concurrency(mut self, issuer: &str, concurrency: usize) -> Self {
+        self.issuer_concurrency.insert(issuer.to_string(), concurrency);
+        self
+    }
+
+    fn concurrency_for(&self, issuer: &str) -> usize {
+        self.issuer_concurrency.get(issuer).copied().unwrap_or(self.concurrency).max(1)
+    }
+}
+
+// Shared flag stopping a bulk load. Funds already being loaded finish; the rest are skipped.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FundOutcome {
+    Loaded(usize), // Number of holdings loaded
+    Unsupported,   // The issuer does not provide holdings
+    Failed(String),
+    Cancelled,     // Skipped because the load was cancelled
+}
+
+#[derive(Debug, Clone)]
+pub struct FundReport {
+    pub isin: String,
+    pub name: String,
+    pub issuer: String,
+    pub outcome: FundOutcome,
+    pub issues: Vec<String>, // Holdings with malformed identifiers
+    pub elapsed: Duration,
+}
+
+impl FundReport {
+    pub fn is_ok(&self) -> bool {
+        matches!(self.outcome, FundOutcome::Loaded(_) | FundOutcome::Unsupported)
+    }
+}
+
+// Sent after each fund, from the worker thread that loaded it
+#[derive(Debug, Clone)]
+pub struct Progress<'a> {
+    pub done: usize,
+    pub total: usize,
+    pub fund: &'a FundReport,
+}
+
+// Outcome of a bulk load, one report per fund in the order the funds were given
+#[derive(Debug, Clone, Default)]
+pub struct BulkReport {
+    pub funds: Vec<FundReport>,
+    pub elapsed: Duration,
+}
+
+impl BulkReport {
+    pub fn loaded(&self) -> usize {
+        self.funds.iter().filter(|fund| matches!(fund.outcome, FundOutcome::Loaded(_))).count()
+    }
+
+    pub fn unsupported(&self) -> usize {
+        self.funds.iter().filter(|fund| fund.outcome == FundOutcome::Unsupported).count()
+    }
+
+    pub fn cancelled(&self) -> usize {
+        self.funds.iter().filter(|fund| fund.outcome == FundOutcome::Cancelled).count()
+    }
+
+    pub fn failures(&self) -> impl Iterator<Item = &FundReport> {
+        self.funds.iter().filter(|fund| matches!(fund.outcome, FundOutcome::Failed(_)))
+    }
+}
+
+// Load the holdings of `etfs` through the providers of `registry`.
+// Each issuer gets its own pool of workers, so a slow issuer does not hold up the others.
+// Failures are recorded in the report; they never stop the other funds.
+pub fn load_holdings<F>(
+    registry: &ProviderRegistry,
+    etfs: &mut [ETF],
+    options: &BulkOptions,
+    cancel: &CancelToken,
+    progress: F,
+) -> BulkReport
+where
+    F: Fn(&Progress) + Sync,
+{
+    let started = Instant::now();
+    let total = etfs.len();
+
+    let mut queues: BTreeMap<String, VecDeque<(usize, &mut ETF)>> = BTreeMap::new();
+    for (index, etf) in etfs.iter_mut().enumerate() {
+        queues.entry(etf.issuer.clone()).or_default().push_back((index, etf));
+    }
+
+    let reports: Mutex<Vec<Option<FundReport>>> = Mutex::new(vec![None; total]);
+    let done = AtomicUsize::new(0);
+
+    std::thread::scope(|scope| {
+        for (issuer, queue) in queues {
+            let workers = options.concurrency_for(&issuer).min(queue.len());
+            let queue = Arc::new(Mutex::new(queue));
+
+            for _ in 0..workers {
+                let queue = queue.clone();
+                let (reports, done, progress) = (&reports, &done, &progress);
+
+                scope.spawn(move || loop {
+                    let next = queue.lock().unwrap_or_else(|e| e.into_inner()).pop_front();
+                    let Some((index, etf)) = next else { break };
+
+                    let fund_started = Instant::now();
+                    let outcome = if cancel.is_cancelled() {
+                        FundOutcome::Cancelled
+                    } else {
+                        match registry.load_holdings(etf) {
+                            Ok(HoldingsStatus::Loaded(count)) => FundOutcome::Loaded(count),
+                            Ok(HoldingsStatus::Unsupported) => FundOutcome::Unsupported,
+                            Err(e) => FundOutcome::Failed(format!("{:#}", e)),
+                        }
+                    };
+
+                    let issues = match outcome {
+                        FundOutcome::Loaded(_) => identifiers::check_holdings(etf),
+                        _ => Vec::new(),
+                    };
+                    let report = FundReport {
+                        isin: etf.isin.clone(),
+                        name: etf.name.clone(),
+                        issuer: etf.issuer.clone(),
+                        outcome,
+                        issues,
+                        elapsed: fund_started.elapsed(),
+                    };
+                    let done = done.fetch_add(1, Ordering::Relaxed) + 1;
+                    progress(&Progress { done, total, fund: &report });
+                    reports.lock().unwrap_or_else(|e| e.into_inner())[index] = Some(report);
+                });
+            }
+        }
+    });
+
+    BulkReport {
+        funds: reports.into_inner().unwrap_or_else(|e| e.into_inner()).into_iter().flatten().collect(),
+        elapsed: started.elapsed(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use color_eyre::{Result, eyre::eyre};
+    use crate::etf::Holding;
+    use crate::provider::{Capabilities, IssuerProvider};
+
+    // Loads one holding per fund after a short wait, recording how many loads overlap
+    #[derive(Default)]
+    struct FakeProvider {
+        name: String,
+        failing: Option<String>,       // ISIN whose load fails
+        cancel: Option<CancelToken>,   // Cancelled by the first load
+        active: AtomicUsize,
+        max_active: Arc<AtomicUsize>,
+    }
+
+    impl FakeProvider {
+        fn new(name: &str) -> Self {
+            Self { name: name.to_string(), ..Self::default() }
+        }
+    }
+
+    impl IssuerProvider for FakeProvider {
+        fn name(&self) -> &str {
+            &self.name
+        }
+
+        fn capabilities(&self) -> Capabilities {
+            Capabilities { list_etfs: false, holdings: true, price_history: false }
+        }
+
+        fn list_etfs(&self) -> Result<Vec<ETF>> {
+            Ok(Vec::new())
+        }
+
+        fn load_holdings(&self, etf: &mut ETF) -> Result<HoldingsStatus> {
+            let active = self.active.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_active.fetch_max(active, Ordering::SeqCst);
+            std::thread::sleep(Duration::from_millis(20));
+            self.active.fetch_sub(1, Ordering::SeqCst);
+
+            if let Some(cancel) = &self.cancel {
+                cancel.cancel();
+            }
+            if self.failing.as_deref() == Some(etf.isin.as_str()) {
+                return Err(eyre!("HTTP status 500"));
+            }
+            etf.holdings = vec![Holding { name: "Cash".to_string(), weight: 100.0, ..Holding::default() }];
+            Ok(HoldingsStatus::Loaded(1))
+        }
+    }
+
+    fn funds(funds: &[(&str, &str)]) -> Vec<ETF> {
+        funds.iter()
+            .map(|(isin, issuer)| ETF { issuer: issuer.to_string(), ..ETF::for_test(isin, Vec::new()) })
+            .collect()
+    }
+
+    fn isins(report: &BulkReport) -> Vec<&str> {
+        report.funds.iter().map(|fund| fund.isin.as_str()).collect()
+    }
+
+    #[test]
+    fn limits_the_loads_of_each_issuer() {
+        let (slow, fast) = (FakeProvider::new("Slow"), FakeProvider::new("Fast"));
+        let (slow_max, fast_max) = (slow.max_active.clone(), fast.max_active.clone());
+        let mut registry = ProviderRegistry::new();
+        registry.register(slow);
+        registry.register(fast);
+
+        let mut etfs = funds(&[
+            ("S1", "Slow"), ("S2", "Slow"), ("S3", "Slow"), ("S4", "Slow"), ("S5", "Slow"),
+            ("F1", "Fast"), ("F2", "Fast"), ("F3", "Fast"), ("F4", "Fast"), ("F5", "Fast"),
+        ]);
+        let options = BulkOptions { concurrency: 3, ..BulkOptions::default() }.with_issuer_concurrency("Slow", 1);
+        let progress = AtomicUsize::new(0);
+        let report = load_holdings(&registry, &mut etfs, &options, &CancelToken::new(), |update| {
+            assert_eq!(update.total, 10);
+            progress.fetch_add(1, Ordering::SeqCst);
+        });
+
+        assert_eq!(report.loaded(), 10);
+        assert_eq!(progress.into_inner(), 10);
+        assert_eq!(slow_max.load(Ordering::SeqCst), 1);
+        assert!(fast_max.load(Ordering::SeqCst) <= 3);
+    }
+
+    #[test]
+    fn reports_every_fund_in_input_order() {
+        let mut registry = ProviderRegistry::new();
+        registry.register(FakeProvider { failing: Some("A2".to_string()), ..FakeProvider::new("A") });
+        registry.register(FakeProvider::new("B"));
+
+        let mut etfs = funds(&[("B1", "B"), ("A1", "A"), ("A2", "A"), ("C1", "C"), ("A3", "A"), ("B2", "B")]);
+        let report = load_holdings(&registry, &mut etfs, &BulkOptions::default(), &CancelToken::new(), |_| {});
+
+        assert_eq!(isins(&report), ["B1", "A1", "A2", "C1", "A3", "B2"]);
+        let outcomes: Vec<&FundOutcome> = report.funds.iter().map(|fund| &fund.outcome).collect();
+        assert_eq!(outcomes, [
+            &FundOutcome::Loaded(1),
+            &FundOutcome::Loaded(1),
+            &FundOutcome::Failed("HTTP status 500".to_string()),
+            &FundOutcome::Unsupported,
+            &FundOutcome::Loaded(1),
+            &FundOutcome::Loaded(1),
+        ]);
+        assert_eq!(report.failures().count(), 1);
+        assert!(etfs[2].holdings.is_empty());
+        assert_eq!(etfs[4].holdings.len(), 1);
+    }
+
+    #[test]
+    fn cancelling_skips_the_funds_not_started() {
+        let cancel = CancelToken::new();
+        let mut registry = ProviderRegistry::new();
+        registry.register(FakeProvider { cancel: Some(cancel.clone()), ..FakeProvider::new("A") });
+
+        let mut etfs = funds(&[("A1", "A"), ("A2", "A"), ("A3", "A")]);
+        let options = BulkOptions { concurrency: 1, ..BulkOptions::default() };
+        let report = load_holdings(&registry, &mut etfs, &options, &cancel, |_| {});
+
+        assert_eq!(report.loaded(), 1);
+        assert_eq!(report.cancelled(), 2);
+        assert_eq!(report.funds[0].outcome, FundOutcome::Loaded(1));
+        assert!(etfs[1].holdings.is_empty());
+    }
+}
@@ -0,0 +1,282 @@
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use color_eyre::{Result, eyre::WrapErr};
+use serde::{Deserialize, Serialize};
+use crate::http::{self, HttpClient};
+use crate::replay::HttpMode;
+use crate::utils;
+
+const DEFAULT_DIR: &str = "cache";
+const DEFAULT_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(Debug, Clone)]
+pub struct CacheConfig {
+    pub dir: PathBuf,
+    pub max_age: Duration, // Files older than this are downloaded again
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::from(DEFAULT_DIR),
+            max_age: DEFAULT_MAX_AGE,
+        }
+    }
+}
+
+impl CacheConfig {
+    // Defaults overridden by ETF_TOOL_CACHE_DIR and ETF_TOOL_CACHE_MAX_AGE_HOURS.
+    // When recording or replaying, every file is fetched through the transport, so it is
+    // recorded or served from the fixtures, into a cache kept next to them.
+    pub fn from_env() -> Self {
+        let mut config = Self::default();
+        if let Ok(dir) = std::env::var("ETF_TOOL_CACHE_DIR") {
+            config.dir = PathBuf::from(dir);
+        }
+        if let Some(max_age) = std::env::var("ETF_TOOL_CACHE_MAX_AGE_HOURS").ok().and_then(|h| max_age_hours(&h)) {
+            config.max_age = max_age;
+        }
+        if let HttpMode::Record(fixtures) | HttpMode::Replay(fixtures) = HttpMode::from_env() {
+            config.dir = fixtures.join("cache");
+            config.max_age = Duration::ZERO;
+        }
+        config
+    }
+}
+
+// Maximum age given in hours. Negative ages are zero, ages too long for a `Duration` never
+// expire, and text that is not a finite number is ignored.
+fn max_age_hours(text: &str) -> Option<Duration> {
+    let hours = text.trim().parse::<f64>().ok().filter(|hours| hours.is_finite())?;
+    Some(Duration::try_from_secs_f64(hours.max(0.0) * 3600.0).unwrap_or(Duration::MAX))
+}
+
+// Stored next to each cached file as `<file>.meta.json`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheEntry {
+    pub source_url: String,
+    pub downloaded_at: u64, // Seconds since the Unix epoch
+}
+
+impl CacheEntry {
+    pub fn age(&self) -> Duration {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        now.saturating_sub(Duration::from_secs(self.downloaded_at))
+    }
+}
+
+// Local copies of issuer files, downloaded again once they are older than `max_age`
+#[derive(Debug, Clone, Default)]
+pub struct Cache {
+    config: CacheConfig,
+}
+
+impl Cache {
+    pub fn new(config: CacheConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn config(&self) -> &CacheConfig {
+        &self.config
+    }
+
+    pub fn path(&self, name: &str) -> PathBuf {
+        self.config.dir.join(name)
+    }
+
+    fn metadata_path(&self, name: &str) -> PathBuf {
+        self.config.dir.join(format!("{}.meta.json", name))
+    }
+
+    pub fn entry(&self, name: &str) -> Option<CacheEntry> {
+        let text = std::fs::read_to_string(self.metadata_path(name)).ok()?;
+        serde_json::from_str(&text).ok()
+    }
+
+    // A file is stale if it is missing, was downloaded from another URL or is too old
+    pub fn is_stale(&self, name: &str, url: &str) -> bool {
+        if !self.path(name).exists() {
+            return true;
+        }
+        match self.entry(name) {
+            Some(entry) => entry.source_url != url || entry.age() >= self.config.max_age,
+            None => true,
+        }
+    }
+
+    // Path to an up-to-date copy of `url`, downloading it only when the cached copy is stale.
+    // If the download fails, the last good copy is used.
+    pub fn fetch(&self, name: &str, url: &str) -> Result<PathBuf> {
+        self.fetch_with(&http::client(), name, url)
+    }
+
+    fn fetch_with(&self, client: &HttpClient, name: &str, url: &str) -> Result<PathBuf> {
+        if !self.is_stale(name, url) {
+            return Ok(self.path(name));
+        }
+
+        match self.download_with(client, name, url) {
+            Ok(path) => Ok(path),
+            Err(e) if self.path(name).exists() => {
+                utils::log(&format!("Failed to refresh {}, using the cached copy: {:#}", name, e));
+                Ok(self.path(name))
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    // Download `url` into the cache, replacing the cached copy only once the download succeeded
+    pub fn download(&self, name: &str, url: &str) -> Result<PathBuf> {
+        self.download_with(&http::client(), name, url)
+    }
+
+    fn download_with(&self, client: &HttpClient, name: &str, url: &str) -> Result<PathBuf> {
+        std::fs::create_dir_all(&self.config.dir)
+            .wrap_err_with(|| format!("Failed to create cache directory {}", self.config.dir.display()))?;
+
+        let path = self.path(name);
+        // Keep the extension so the partial file is read the same way
+        let partial = self.config.dir.join(format!("partial-{}", name));
+
+        utils::log(&format!("Downloading {} from {}", name, url));
+        if let Err(e) = client.download(url, &partial) {
+            let _ = std::fs::remove_file(&partial);
+            return Err(e.wrap_err(format!("Failed to download {}", url)));
+        }
+        std::fs::rename(&partial, &path).wrap_err("Failed to move download into the cache")?;
+
+        let entry = CacheEntry {
+            source_url: url.to_string(),
+            downloaded_at: SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs(),
+        };
+        std::fs::write(self.metadata_path(name), serde_json::to_string_pretty(&entry)?)
+            .wrap_err("Failed to write cache metadata")?;
+
+        Ok(path)
+    }
+}
+
+static CACHE: RwLock<Option<Arc<Cache>>> = RwLock::new(None);
+
+// Cache used by the issuer loaders. Configured from the environment unless `configure` was called.
+pub fn global() -> Arc<Cache> {
+    if let Some(cache) = CACHE.read().unwrap_or_else(|e| e.into_inner()).as_ref() {
+        return cache.clone();
+    }
+    CACHE.write().unwrap_or_else(|e| e.into_inner())
+        .get_or_insert_with(|| Arc::new(Cache::new(CacheConfig::from_env())))
+        .clone()
+}
+
+pub fn configure(config: CacheConfig) {
+    *CACHE.write().unwrap_or_else(|e| e.into_inner()) = Some(Arc::new(Cache::new(config)));
+}
+
+// Name of the cached copy of a file
+pub fn file_name(path: &str) -> String {
+    Path::new(path)
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| path.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{CannedTransport, HttpConfig};
+
+    const URL: &str = "https://issuer.example/funds.xlsx";
+    const NAME: &str = "funds.xlsx";
+
+    fn cache(test: &str) -> Cache {
+        Cache::new(CacheConfig { dir: utils::test_dir(test), max_age: Duration::from_secs(3600) })
+    }
+
+    fn client(status: u16, body: &str) -> HttpClient {
+        let config = HttpConfig { max_retries: 0, min_host_interval: Duration::ZERO, ..HttpConfig::default() };
+        HttpClient::with_transport(config, Arc::new(CannedTransport::new().with(URL, status, body)))
+    }
+
+    // A cached copy of `url` downloaded `age` ago
+    fn store(cache: &Cache, url: &str, age: Duration) {
+        std::fs::write(cache.path(NAME), "cached").unwrap();
+        let downloaded_at = SystemTime::now().duration_since(UNIX_EPOCH).unwrap() - age;
+        let entry = CacheEntry { source_url: url.to_string(), downloaded_at: downloaded_at.as_secs() };
+        std::fs::write(cache.metadata_path(NAME), serde_json::to_string(&entry).unwrap()).unwrap();
+    }
+
+    fn files(cache: &Cache) -> Vec<String> {
+        let mut files: Vec<String> = std::fs::read_dir(&cache.config.dir).unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        files.sort();
+        files
+    }
+
+    #[test]
+    fn copies_are_stale_when_old_missing_or_from_another_url() {
+        let cache = cache("stale");
+        assert!(cache.is_stale(NAME, URL));
+
+        store(&cache, URL, Duration::from_secs(60));
+        assert!(!cache.is_stale(NAME, URL));
+        assert!(cache.is_stale(NAME, "https://issuer.example/other.xlsx"));
+
+        store(&cache, URL, Duration::from_secs(2 * 3600));
+        assert!(cache.is_stale(NAME, URL));
+
+        store(&cache, URL, Duration::from_secs(60));
+        std::fs::remove_file(cache.metadata_path(NAME)).unwrap();
+        assert!(cache.is_stale(NAME, URL));
+        std::fs::remove_dir_all(&cache.config.dir).unwrap();
+    }
+
+    #[test]
+    fn downloads_stale_copies_only() {
+        let cache = cache("download");
+        store(&cache, URL, Duration::from_secs(60));
+        let path = cache.fetch_with(&client(200, "fresh"), NAME, URL).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "cached");
+
+        store(&cache, URL, Duration::from_secs(2 * 3600));
+        let path = cache.fetch_with(&client(200, "fresh"), NAME, URL).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "fresh");
+        assert!(!cache.is_stale(NAME, URL));
+        std::fs::remove_dir_all(&cache.config.dir).unwrap();
+    }
+
+    #[test]
+    fn failed_downloads_keep_the_last_good_copy() {
+        let cache = cache("failed");
+        store(&cache, "https://issuer.example/old.xlsx", Duration::ZERO);
+        let path = cache.fetch_with(&client(500, "error"), NAME, URL).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "cached");
+        assert_eq!(files(&cache), ["funds.xlsx", "funds.xlsx.meta.json"]);
+
+        // Without a copy, the error is returned and nothing is left behind
+        std::fs::remove_file(cache.path(NAME)).unwrap();
+        assert!(cache.fetch_with(&client(404, ""), NAME, URL).is_err());
+        assert_eq!(files(&cache), ["funds.xlsx.meta.json"]);
+        std::fs::remove_dir_all(&cache.config.dir).unwrap();
+    }
+
+    #[test]
+    fn reads_max_age_hours() {
+        assert_eq!(max_age_hours("12"), Some(Duration::from_secs(12 * 3600)));
+        assert_eq!(max_age_hours(" 0.5 "), Some(Duration::from_secs(1800)));
+        assert_eq!(max_age_hours("-3"), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn caps_max_age_instead_of_overflowing() {
+        assert_eq!(max_age_hours("1e300"), Some(Duration::MAX));
+    }
+
+    #[test]
+    fn ignores_invalid_max_age() {
+        assert_eq!(max_age_hours("inf"), None);
+        assert_eq!(max_age_hours("NaN"), None);
+        assert_eq!(max_age_hours("soon"), None);
+    }
+}
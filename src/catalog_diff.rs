@@ -0,0 +1,302 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use color_eyre::{Result, eyre::eyre};
+use serde::{Deserialize, Serialize};
+use crate::classification::DistributionPolicy;
+use crate::etf::ETF;
+use crate::money::Money;
+use crate::provider::IssuerReport;
+use crate::store::{Snapshot, SnapshotKind, Store, StoredCatalog};
+
+// Which changes are worth reporting
+#[derive(Debug, Clone, PartialEq)]
+pub struct CatalogDiffConfig {
+    pub aum_move: f64, // Smallest AUM change reported, in percent either way
+}
+
+impl Default for CatalogDiffConfig {
+    fn default() -> Self {
+        Self { aum_move: 25.0 }
+    }
+}
+
+impl CatalogDiffConfig {
+    pub fn with_aum_move(mut self, percent: f64) -> Self {
+        self.aum_move = percent;
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ChangeKind {
+    Launch,  // ISIN new in the later catalog
+    Closure, // ISIN gone from the later catalog: closed, merged or delisted
+    Ter { before: f64, after: f64 },
+    Distribution { before: Option<DistributionPolicy>, after: Option<DistributionPolicy> },
+    Aum { before: Money, after: Money },
+}
+
+impl ChangeKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Launch => "Launch",
+            Self::Closure => "Closure",
+            Self::Ter { after, before } if after < before => "TER cut",
+            Self::Ter { .. } => "TER rise",
+            Self::Distribution { .. } => "Distribution",
+            Self::Aum { .. } => "AUM move",
+        }
+    }
+}
+
+// "0.20% -> 0.12%", "Accumulating -> Distributing", "1.20bn USD -> 2.00bn USD (+66.7%)"
+impl fmt::Display for ChangeKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let policy = |policy: &Option<DistributionPolicy>| policy.as_ref().map_or("N/A".to_string(), |policy| policy.to_string());
+        match self {
+            Self::Launch | Self::Closure => Ok(()),
+            Self::Ter { before, after } => write!(f, "{:.2}% -> {:.2}%", before, after),
+            Self::Distribution { before, after } => write!(f, "{} -> {}", policy(before), policy(after)),
+            Self::Aum { before, after } => {
+                write!(f, "{} -> {} ({:+.1}%)", before, after, (after.amount / before.amount - 1.0) * 100.0)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CatalogChange {
+    pub isin: String,
+    pub name: String,
+    pub issuer: String,
+    pub kind: ChangeKind,
+}
+
+// What changed between two catalogs, launches first, then closures, TER, distribution and AUM changes
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CatalogDiff {
+    pub from: Option<Snapshot>,
+    pub to: Option<Snapshot>,
+    pub changes: Vec<CatalogChange>,
+    pub skipped_issuers: Vec<String>, // Failed to load in one of the catalogs, so left out
+}
+
+impl CatalogDiff {
+    pub fn count(&self, label: &str) -> usize {
+        self.changes.iter().filter(|change| change.kind.label() == label).count()
+    }
+
+    // "2 launches, 1 closure, 3 TER cuts", or "no changes"
+    pub fn summary(&self) -> String {
+        let counts = [
+            ("Launch", "launch", "launches"),
+            ("Closure", "closure", "closures"),
+            ("TER cut", "TER cut", "TER cuts"),
+            ("TER rise", "TER rise", "TER rises"),
+            ("Distribution", "distribution policy change", "distribution policy changes"),
+            ("AUM move", "AUM move", "AUM moves"),
+        ];
+        let parts: Vec<String> = counts.iter()
+            .filter_map(|(label, one, many)| match self.count(label) {
+                0 => None,
+                1 => Some(format!("1 {}", one)),
+                count => Some(format!("{} {}", count, many)),
+            })
+            .collect();
+        if parts.is_empty() { "no changes".to_string() } else { parts.join(", ") }
+    }
+}
+
+fn change(etf: &ETF, kind: ChangeKind) -> CatalogChange {
+    CatalogChange { isin: etf.isin.clone(), name: etf.name.clone(), issuer: etf.issuer.clone(), kind }
+}
+
+// Changes of a fund listed in both catalogs. A TER of 0 means the issuer published none.
+fn fund_changes(before: &ETF, after: &ETF, config: &CatalogDiffConfig) -> Vec<CatalogChange> {
+    let mut changes = Vec::new();
+    if before.ter > 0.0 && after.ter > 0.0 && (before.ter - after.ter).abs() > 1e-6 {
+        changes.push(change(after, ChangeKind::Ter { before: before.ter, after: after.ter }));
+    }
+    if before.distribution_policy.is_some() && after.distribution_policy.is_some()
+        && before.distribution_policy != after.distribution_policy
+    {
+        changes.push(change(after, ChangeKind::Distribution {
+            before: before.distribution_policy.clone(),
+            after: after.distribution_policy.clone(),
+        }));
+    }
+    // Amounts in different currencies (a change of reporting currency) are not compared
+    if let (Some(old), Some(new)) = (&before.aum.value, &after.aum.value) {
+        let moved = old.currency == new.currency && old.amount > 0.0
+            && ((new.amount / old.amount - 1.0) * 100.0).abs() >= config.aum_move;
+        if moved {
+            changes.push(change(after, ChangeKind::Aum { before: old.clone(), after: new.clone() }));
+        }
+    }
+    changes
+}
+
+fn order(kind: &ChangeKind) -> u8 {
+    match kind {
+        ChangeKind::Launch => 0,
+        ChangeKind::Closure => 1,
+        ChangeKind::Ter { .. } => 2,
+        ChangeKind::Distribution { .. } => 3,
+        ChangeKind::Aum { .. } => 4,
+    }
+}
+
+// Compare two fund lists, matched by ISIN
+pub fn diff(before: &[ETF], after: &[ETF], config: &CatalogDiffConfig) -> CatalogDiff {
+    let old: HashMap<&str, &ETF> = before.iter().map(|etf| (etf.isin.as_str(), etf)).collect();
+    let new: HashSet<&str> = after.iter().map(|etf| etf.isin.as_str()).collect();
+
+    let mut changes = Vec::new();
+    for etf in after {
+        match old.get(etf.isin.as_str()) {
+            Some(previous) => changes.extend(fund_changes(previous, etf, config)),
+            None => changes.push(change(etf, ChangeKind::Launch)),
+        }
+    }
+    for etf in before.iter().filter(|etf| !new.contains(etf.isin.as_str())) {
+        changes.push(change(etf, ChangeKind::Closure));
+    }
+    changes.sort_by(|a, b| order(&a.kind).cmp(&order(&b.kind)).then_with(|| a.issuer.cmp(&b.issuer)).then_with(|| a.name.cmp(&b.name)));
+
+    CatalogDiff { changes, ..CatalogDiff::default() }
+}
+
+fn failed_issuers(reports: &[IssuerReport]) -> impl Iterator<Item = &str> {
+    reports.iter().filter(|report| !report.is_ok()).map(|report| report.issuer.as_str())
+}
+
+// Compare two stored catalogs. Issuers that failed to load in either are left out, so their
+// funds don't show up as closures or launches.
+pub fn diff_stored(before: &StoredCatalog, after: &StoredCatalog, config: &CatalogDiffConfig) -> CatalogDiff {
+    let mut skipped: Vec<String> = failed_issuers(&before.reports).chain(failed_issuers(&after.reports)).map(str::to_string).collect();
+    skipped.sort();
+    skipped.dedup();
+
+    let kept = |etfs: &[ETF]| -> Vec<ETF> {
+        etfs.iter().filter(|etf| !skipped.contains(&etf.issuer)).cloned().collect()
+    };
+    let changes = diff(&kept(&before.etfs), &kept(&after.etfs), config).changes;
+    CatalogDiff {
+        from: Some(before.snapshot.clone()),
+        to: Some(after.snapshot.clone()),
+        changes,
+        skipped_issuers: skipped,
+    }
+}
+
+// Compare the catalogs stored in snapshots `from` and `to`. `to` defaults to the latest catalog
+// and `from` to the one stored before `to`.
+pub fn diff_snapshots(store: &Store, from: Option<i64>, to: Option<i64>, config: &CatalogDiffConfig) -> Result<CatalogDiff> {
+    // Only the fund lists are compared, so holdings are not read
+    let to = match to {
+        Some(id) => id,
+        None => store.latest_snapshot(SnapshotKind::Catalog)?.ok_or_else(|| eyre!("No catalog stored yet"))?.id,
+    };
+    let from = match from {
+        Some(id) => id,
+        None => store.previous_catalog(to)?
+            .ok_or_else(|| eyre!("No catalog stored before snapshot {} to compare with", to))?
+            .id,
+    };
+    Ok(diff_stored(&store.catalog_funds(from)?, &store.catalog_funds(to)?, config))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::money::Aum;
+
+    fn fund(isin: &str, issuer: &str, ter: f64) -> ETF {
+        ETF { issuer: issuer.to_string(), ter, ..ETF::for_test(isin, Vec::new()) }
+    }
+
+    fn with_aum(etf: ETF, amount: f64, currency: &str) -> ETF {
+        ETF { aum: Aum { raw: amount.to_string(), value: Some(Money::new(amount, currency)) }, ..etf }
+    }
+
+    fn report(issuer: &str, error: Option<&str>) -> IssuerReport {
+        IssuerReport { issuer: issuer.to_string(), etf_count: 0, as_of: None, issues: Vec::new(), error: error.map(str::to_string) }
+    }
+
+    fn changes(diff: &CatalogDiff) -> Vec<(&str, &str)> {
+        diff.changes.iter().map(|change| (change.isin.as_str(), change.kind.label())).collect()
+    }
+
+    #[test]
+    fn finds_launches_and_closures() {
+        let before = [fund("IE00B4L5Y983", "iShares", 0.2), fund("IE00B5BMR087", "iShares", 0.07)];
+        let after = [fund("IE00B5BMR087", "iShares", 0.07), fund("IE00B3YCGJ38", "Invesco", 0.05)];
+
+        let diff = diff(&before, &after, &CatalogDiffConfig::default());
+        assert_eq!(changes(&diff), [("IE00B3YCGJ38", "Launch"), ("IE00B4L5Y983", "Closure")]);
+        assert_eq!(diff.summary(), "1 launch, 1 closure");
+    }
+
+    #[test]
+    fn ter_changes_ignore_unpublished_ters() {
+        let before = [fund("IE00B4L5Y983", "iShares", 0.2), fund("IE00B5BMR087", "iShares", 0.07), fund("IE00B3YCGJ38", "Invesco", 0.0)];
+        let after = [fund("IE00B4L5Y983", "iShares", 0.12), fund("IE00B5BMR087", "iShares", 0.0), fund("IE00B3YCGJ38", "Invesco", 0.05)];
+
+        let diff = diff(&before, &after, &CatalogDiffConfig::default());
+        assert_eq!(changes(&diff), [("IE00B4L5Y983", "TER cut")]);
+        assert_eq!(diff.changes[0].kind.to_string(), "0.20% -> 0.12%");
+        assert_eq!(diff.summary(), "1 TER cut");
+    }
+
+    #[test]
+    fn aum_moves_are_compared_in_one_currency() {
+        let before = [
+            with_aum(fund("IE00B4L5Y983", "iShares", 0.2), 1.2e9, "USD"),
+            with_aum(fund("IE00B5BMR087", "iShares", 0.07), 1.0e9, "USD"),
+            with_aum(fund("IE00B3YCGJ38", "Invesco", 0.05), 1.0e9, "USD"),
+        ];
+        let after = [
+            with_aum(fund("IE00B4L5Y983", "iShares", 0.2), 2.0e9, "USD"),
+            with_aum(fund("IE00B5BMR087", "iShares", 0.07), 1.1e9, "USD"),
+            // Reported in another currency: not a move
+            with_aum(fund("IE00B3YCGJ38", "Invesco", 0.05), 2.0e9, "EUR"),
+        ];
+
+        let diff = diff(&before, &after, &CatalogDiffConfig::default());
+        assert_eq!(changes(&diff), [("IE00B4L5Y983", "AUM move")]);
+        assert_eq!(diff.changes[0].kind.to_string(), "1.20bn USD -> 2.00bn USD (+66.7%)");
+
+        let diff = super::diff(&before, &after, &CatalogDiffConfig::default().with_aum_move(10.0));
+        assert_eq!(diff.summary(), "2 AUM moves");
+    }
+
+    #[test]
+    fn distribution_changes_need_both_policies() {
+        let policy = |isin: &str, policy: Option<DistributionPolicy>| ETF { distribution_policy: policy, ..fund(isin, "iShares", 0.2) };
+        let before = [policy("IE00B4L5Y983", Some(DistributionPolicy::Accumulating)), policy("IE00B5BMR087", None)];
+        let after = [policy("IE00B4L5Y983", Some(DistributionPolicy::Distributing)), policy("IE00B5BMR087", Some(DistributionPolicy::Distributing))];
+
+        let diff = diff(&before, &after, &CatalogDiffConfig::default());
+        assert_eq!(changes(&diff), [("IE00B4L5Y983", "Distribution")]);
+        assert_eq!(diff.changes[0].kind.to_string(), "Accumulating -> Distributing");
+        assert_eq!(CatalogDiff::default().summary(), "no changes");
+    }
+
+    #[test]
+    fn leaves_out_issuers_that_failed_to_load() {
+        let mut store = Store::open_in_memory().unwrap();
+        let first = store.save_catalog(
+            &[fund("IE00B4L5Y983", "iShares", 0.2), fund("IE00B3YCGJ38", "Invesco", 0.05)],
+            &[report("iShares", None), report("Invesco", None)],
+        ).unwrap();
+        store.save_catalog(&[fund("IE00B3YCGJ38", "Invesco", 0.04)], &[report("iShares", Some("HTTP status 500")), report("Invesco", None)]).unwrap();
+
+        // iShares funds are not closures: the issuer failed to load
+        let diff = diff_snapshots(&store, None, None, &CatalogDiffConfig::default()).unwrap();
+        assert_eq!(diff.from.as_ref().map(|snapshot| snapshot.id), Some(first.id));
+        assert_eq!(diff.skipped_issuers, ["iShares"]);
+        assert_eq!(changes(&diff), [("IE00B3YCGJ38", "TER cut")]);
+
+        assert!(diff_snapshots(&store, None, Some(first.id), &CatalogDiffConfig::default()).is_err());
+    }
+}
@@ -0,0 +1,257 @@
+use std::fmt;
+use serde::{Deserialize, Serialize};
+use crate::utils;
+
+// Lowercase words of a label, for matching issuer wording: "Fixed-Income" -> "fixed income"
+fn normalize(text: &str) -> String {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum DistributionPolicy {
+    Accumulating,
+    Distributing,
+    Other(String), // Issuer wording that matched none of the above
+}
+
+impl DistributionPolicy {
+    // None for an empty cell
+    pub fn parse(text: &str) -> Option<Self> {
+        let text = utils::non_empty(text)?;
+        Some(match normalize(&text).as_str() {
+            "accumulating" | "accumulation" | "acc" | "capitalizing" | "capitalising" | "capitalisation"
+            | "reinvested" | "reinvesting" => Self::Accumulating,
+            "distributing" | "distribution" | "dist" | "distributed" | "income" | "inc" | "monthly"
+            | "quarterly" | "semi annual" | "semi annually" | "annual" | "annually" => Self::Distributing,
+            _ => Self::Other(text),
+        })
+    }
+}
+
+impl fmt::Display for DistributionPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Accumulating => write!(f, "Accumulating"),
+            Self::Distributing => write!(f, "Distributing"),
+            Self::Other(text) => write!(f, "{}", text),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum AssetClass {
+    Equity,
+    FixedIncome,
+    Commodity, // Including precious metals
+    MoneyMarket,
+    MultiAsset,
+    RealEstate,
+    Crypto,
+    Other(String),
+}
+
+impl AssetClass {
+    pub fn parse(text: &str) -> Option<Self> {
+        let text = utils::non_empty(text)?;
+        Some(match normalize(&text).as_str() {
+            "equity" | "equities" | "stocks" | "shares" => Self::Equity,
+            "fixed income" | "bond" | "bonds" => Self::FixedIncome,
+            "commodity" | "commodities" | "precious metals" | "precious metal" => Self::Commodity,
+            "money market" | "cash" => Self::MoneyMarket,
+            "multi asset" | "multi assets" | "mixed assets" => Self::MultiAsset,
+            "real estate" | "property" => Self::RealEstate,
+            "crypto" | "crypto currency" | "cryptocurrency" | "digital assets" => Self::Crypto,
+            _ => Self::Other(text),
+        })
+    }
+}
+
+impl fmt::Display for AssetClass {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Equity => write!(f, "Equity"),
+            Self::FixedIncome => write!(f, "Fixed Income"),
+            Self::Commodity => write!(f, "Commodity"),
+            Self::MoneyMarket => write!(f, "Money Market"),
+            Self::MultiAsset => write!(f, "Multi Asset"),
+            Self::RealEstate => write!(f, "Real Estate"),
+            Self::Crypto => write!(f, "Crypto"),
+            Self::Other(text) => write!(f, "{}", text),
+        }
+    }
+}
+
+// How a fund gets its index exposure
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Replication {
+    PhysicalFull,    // Holds every index constituent
+    PhysicalSampled, // Holds a representative sample ("optimised")
+    Physical,        // Physical, the issuer does not say how
+    SyntheticSwap,   // Index return delivered by a swap
+    Other(String),
+}
+
+impl Replication {
+    pub fn parse(text: &str) -> Option<Self> {
+        let text = utils::non_empty(text)?;
+        let words = normalize(&text);
+        let has = |word: &str| words.split(' ').any(|w| w == word);
+
+        Some(if has("synthetic") || has("swap") || has("indirect") {
+            Self::SyntheticSwap
+        } else if has("sampling") || has("sampled") || has("optimised") || has("optimized") || has("optimisation") {
+            Self::PhysicalSampled
+        } else if has("full") {
+            Self::PhysicalFull
+        } else if has("physical") || has("direct") || has("replicating") || has("replication") {
+            Self::Physical
+        } else {
+            Self::Other(text)
+        })
+    }
+}
+
+impl fmt::Display for Replication {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::PhysicalFull => write!(f, "Physical (full)"),
+            Self::PhysicalSampled => write!(f, "Physical (sampled)"),
+            Self::Physical => write!(f, "Physical"),
+            Self::SyntheticSwap => write!(f, "Synthetic (swap)"),
+            Self::Other(text) => write!(f, "{}", text),
+        }
+    }
+}
+
+// Currency hedging of a share class
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Hedging {
+    Unhedged,
+    Hedged(String), // Currency the share class is hedged to
+    Other(String),
+}
+
+impl Hedging {
+    // Parse "Currency Hedged (EUR)", "EUR Hdg", "Unhedged" or "No". A bare currency code or
+    // "Multiple" is a currency exposure, so unhedged. Hedged text without a currency is hedged
+    // to `currency`, the share class currency.
+    pub fn parse(text: &str, currency: &str) -> Option<Self> {
+        let text = utils::non_empty(text)?;
+        let words = normalize(&text);
+        let has = |word: &str| words.split(' ').any(|w| w == word);
+
+        Some(if has("unhedged") || matches!(words.as_str(), "no" | "none" | "multiple" | "n") {
+            Self::Unhedged
+        } else if has("hedged") || has("hdg") || has("hedge") || matches!(words.as_str(), "yes" | "y") {
+            // The currency is written next to the hedge word: "EUR Hedged", "Hedged (EUR)"
+            let original: Vec<&str> = text.split(|c: char| !c.is_alphanumeric()).filter(|w| !w.is_empty()).collect();
+            let is_code = |word: &&&str| word.len() == 3 && word.chars().all(|c| c.is_ascii_uppercase()) && !matches!(**word, "ETF" | "ETC");
+            let code = original.iter()
+                .position(|word| matches!(word.to_lowercase().as_str(), "hedged" | "hdg" | "hedge"))
+                .and_then(|at| {
+                    [at.checked_sub(1), Some(at + 1)].into_iter().flatten()
+                        .filter_map(|i| original.get(i))
+                        .find(is_code)
+                })
+                .copied()
+                .unwrap_or(currency);
+            Self::Hedged(code.to_string())
+        } else if text.len() == 3 && text.chars().all(|c| c.is_ascii_uppercase()) {
+            Self::Unhedged
+        } else {
+            Self::Other(text)
+        })
+    }
+}
+
+impl fmt::Display for Hedging {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Unhedged => write!(f, "Unhedged"),
+            Self::Hedged(currency) if currency.is_empty() => write!(f, "Hedged"),
+            Self::Hedged(currency) => write!(f, "{} Hedged", currency),
+            Self::Other(text) => write!(f, "{}", text),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_issuer_asset_classes() {
+        for (text, expected) in [
+            ("Equity", Some(AssetClass::Equity)),
+            ("Fixed Income", Some(AssetClass::FixedIncome)),
+            ("Fixed-Income", Some(AssetClass::FixedIncome)),
+            ("Commodities", Some(AssetClass::Commodity)),
+            ("Precious Metals", Some(AssetClass::Commodity)),
+            ("Money Market", Some(AssetClass::MoneyMarket)),
+            ("Multi Asset", Some(AssetClass::MultiAsset)),
+            ("Real Estate", Some(AssetClass::RealEstate)),
+            ("Digital Assets", Some(AssetClass::Crypto)),
+            ("Alternatives", Some(AssetClass::Other("Alternatives".to_string()))),
+            ("", None),
+            ("-", None),
+        ] {
+            assert_eq!(AssetClass::parse(text), expected, "{}", text);
+        }
+    }
+
+    #[test]
+    fn parses_issuer_replication_methods() {
+        for (text, expected) in [
+            ("Physical", Replication::Physical),
+            ("Direct (Physical)", Replication::Physical),
+            ("Full replication", Replication::PhysicalFull),
+            ("Physical - Full", Replication::PhysicalFull),
+            ("Optimised Sampling", Replication::PhysicalSampled),
+            ("Physical (sampled)", Replication::PhysicalSampled),
+            ("Synthetic swap", Replication::SyntheticSwap),
+            ("Indirect (Synthetic)", Replication::SyntheticSwap),
+            ("Unfunded Swap", Replication::SyntheticSwap),
+            ("Derivatives", Replication::Other("Derivatives".to_string())),
+        ] {
+            assert_eq!(Replication::parse(text), Some(expected), "{}", text);
+        }
+        assert_eq!(Replication::parse(""), None);
+    }
+
+    #[test]
+    fn parses_hedging_and_its_currency() {
+        let hedged = |currency: &str| Some(Hedging::Hedged(currency.to_string()));
+        for (text, expected) in [
+            // Xtrackers currency exposure column
+            ("Currency Hedged (EUR)", hedged("EUR")),
+            ("USD", Some(Hedging::Unhedged)),
+            ("Multiple", Some(Hedging::Unhedged)),
+            // Hedge word in the share class name or a yes/no column
+            ("EUR Hdg", hedged("EUR")),
+            ("GBP Hedged", hedged("GBP")),
+            ("Hedged", hedged("CHF")),
+            ("Yes", hedged("CHF")),
+            ("No", Some(Hedging::Unhedged)),
+            ("Unhedged", Some(Hedging::Unhedged)),
+            ("Partially", Some(Hedging::Other("Partially".to_string()))),
+            ("", None),
+        ] {
+            assert_eq!(Hedging::parse(text, "CHF"), expected, "{}", text);
+        }
+        assert_eq!(Hedging::parse("Currency Hedged (EUR)", "CHF").unwrap().to_string(), "EUR Hedged");
+    }
+
+    #[test]
+    fn parses_distribution_policies() {
+        assert_eq!(DistributionPolicy::parse("Reinvested"), Some(DistributionPolicy::Accumulating));
+        assert_eq!(DistributionPolicy::parse("Capitalisation"), Some(DistributionPolicy::Accumulating));
+        assert_eq!(DistributionPolicy::parse("Distributed"), Some(DistributionPolicy::Distributing));
+        assert_eq!(DistributionPolicy::parse("Semi-Annually"), Some(DistributionPolicy::Distributing));
+        assert_eq!(DistributionPolicy::parse("Mixed"), Some(DistributionPolicy::Other("Mixed".to_string())));
+        assert_eq!(DistributionPolicy::parse(" "), None);
+    }
+}
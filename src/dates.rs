@@ -0,0 +1,155 @@
+use chrono::{Datelike, Duration, NaiveDate};
+
+// Formats used by issuer files, tried in order. Day-first formats come before month-first
+// ones as the files are published for UK investors.
+const FORMATS: [&str; 14] = [
+    "%Y-%m-%d",
+    "%d/%m/%Y",
+    "%d.%m.%Y",
+    "%d-%m-%Y",
+    "%d/%b/%Y",
+    "%d-%b-%Y",
+    "%d %b %Y",
+    "%d %B %Y",
+    "%d %b %y",
+    "%b %d, %Y",
+    "%B %d, %Y",
+    "%b %d %Y",
+    "%m/%d/%Y",
+    "%Y%m%d",
+];
+
+// Serial number of 1970-01-01. Some exports write an unset date as this day plus a fraction.
+const UNIX_EPOCH_SERIAL: f64 = 25569.0;
+
+// Date of an Excel serial number (1900 date system, as read by calamine)
+pub fn from_excel_serial(serial: f64) -> Option<NaiveDate> {
+    if !(1.0..200_000.0).contains(&serial) || serial.floor() == UNIX_EPOCH_SERIAL {
+        return None;
+    }
+    // Day 0 is 1899-12-30 because of the 1900 leap year bug kept by Excel
+    let epoch = NaiveDate::from_ymd_opt(1899, 12, 30)?;
+    epoch.checked_add_signed(Duration::days(serial.floor() as i64))
+}
+
+// 20250328: a number, but never a valid serial
+fn is_compact_date(text: &str) -> bool {
+    text.len() == 8 && text.bytes().all(|b| b.is_ascii_digit())
+}
+
+// Parse a date cell: an Excel serial number or a date written in one of the issuer formats.
+// Time and time zone suffixes ("10:06 UTC", "T00:00:00") are ignored.
+pub fn parse_date(text: &str) -> Option<NaiveDate> {
+    let text = text.trim().trim_matches(|c: char| c == ':' || c == ',').trim();
+    if text.is_empty() {
+        return None;
+    }
+
+    // Compact dates like 20250328 are numbers too, but never valid serials
+    if !is_compact_date(text) {
+        if let Ok(serial) = text.parse::<f64>() {
+            return from_excel_serial(serial);
+        }
+    }
+
+    let date = text.split(['T', ' ']).next().filter(|_| text.len() > 10 && text.as_bytes()[4] == b'-');
+    let candidates = [Some(text), date];
+    candidates.into_iter().flatten()
+        .find_map(|text| {
+            FORMATS.iter()
+                .filter_map(|format| NaiveDate::parse_from_str(text, format).ok())
+                // "%Y" also accepts two-digit years, which "%y" reads correctly
+                .find(|date| date.year() >= 1900)
+        })
+}
+
+// First date in free text such as "Spreadsheet created on 28 Mar 25 10:06 UTC" or "As of: 27.03.2025"
+pub fn find_date(text: &str) -> Option<NaiveDate> {
+    let words: Vec<&str> = text.split_whitespace().collect();
+    (0..words.len()).find_map(|start| {
+        // Longest run first, so "28 Mar 25" is not read as the serial 28
+        (1..=3).rev()
+            .filter(|len| start + len <= words.len())
+            .find_map(|len| {
+                let candidate = words[start..start + len].join(" ");
+                // A lone number is only a date when written as one
+                let number = candidate.trim_matches([':', ',']);
+                if len == 1 && number.parse::<f64>().is_ok() && !is_compact_date(number) {
+                    return None;
+                }
+                parse_date(&candidate)
+            })
+    })
+}
+
+const AS_OF_PHRASES: [&str; 5] = ["as of", "as at", "created on", "valid on", "date:"];
+
+// Date given next to an "As of" label in preamble or summary rows: in the same cell
+// ("As of: 27.03.2025") or in the next non-empty cell of the row
+pub fn find_as_of(rows: &[Vec<String>]) -> Option<NaiveDate> {
+    rows.iter().find_map(|row| {
+        let cells: Vec<&str> = row.iter().map(|cell| cell.trim()).filter(|cell| !cell.is_empty()).collect();
+        cells.iter().enumerate().find_map(|(i, cell)| {
+            let lower = cell.to_lowercase();
+            let start = AS_OF_PHRASES.iter().find_map(|phrase| lower.find(phrase).map(|at| at + phrase.len()))?;
+            cell.get(start..).and_then(find_date).or_else(|| cells.get(i + 1).and_then(|next| parse_date(next)))
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn day(year: i32, month: u32, day: u32) -> Option<NaiveDate> {
+        NaiveDate::from_ymd_opt(year, month, day)
+    }
+
+    fn rows(rows: &[&[&str]]) -> Vec<Vec<String>> {
+        rows.iter().map(|row| row.iter().map(|cell| cell.to_string()).collect()).collect()
+    }
+
+    #[test]
+    fn reads_excel_serials() {
+        assert_eq!(from_excel_serial(45744.0), day(2025, 3, 28));
+        assert_eq!(from_excel_serial(45744.75), day(2025, 3, 28));
+        assert_eq!(from_excel_serial(61.0), day(1900, 3, 1));
+        // 1970-01-01 is the placeholder of an unset date
+        assert_eq!(from_excel_serial(25569.0), None);
+        assert_eq!(from_excel_serial(25569.5), None);
+        assert_eq!(from_excel_serial(0.0), None);
+    }
+
+    #[test]
+    fn parses_issuer_date_formats() {
+        for text in [
+            "2025-03-28", "28/03/2025", "28.03.2025", "28-Mar-2025", "28 Mar 2025", "28 March 2025",
+            "28 Mar 25", "Mar 28, 2025", "March 28, 2025", "20250328", "45744", "45744.25",
+            "2025-03-28T00:00:00", "2025-03-28 10:06 UTC", " 28/03/2025: ",
+        ] {
+            assert_eq!(parse_date(text), day(2025, 3, 28), "{}", text);
+        }
+        // Day first
+        assert_eq!(parse_date("01/02/2025"), day(2025, 2, 1));
+        assert_eq!(parse_date("12/31/2025"), day(2025, 12, 31));
+        assert_eq!(parse_date("25569.25"), None);
+        assert_eq!(parse_date(""), None);
+        assert_eq!(parse_date("N/A"), None);
+    }
+
+    #[test]
+    fn finds_dates_in_text() {
+        assert_eq!(find_date("Spreadsheet created on 28 Mar 25 10:06 UTC"), day(2025, 3, 28));
+        assert_eq!(find_date("As of: 27.03.2025"), day(2025, 3, 27));
+        assert_eq!(find_date("Report 20250327"), day(2025, 3, 27));
+        assert_eq!(find_date("Top 10 holdings"), None);
+    }
+
+    #[test]
+    fn finds_as_of_dates_next_to_their_label() {
+        assert_eq!(find_as_of(&rows(&[&["Fund"], &["As of: 27.03.2025"]])), day(2025, 3, 27));
+        assert_eq!(find_as_of(&rows(&[&["", "As Of", "", "45743"]])), day(2025, 3, 27));
+        assert_eq!(find_as_of(&rows(&[&["Spreadsheet created on 28 Mar 25 10:06 UTC"]])), day(2025, 3, 28));
+        assert_eq!(find_as_of(&rows(&[&["Inception Date", "19/05/2010"]])), None);
+    }
+}
@@ -0,0 +1,526 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use color_eyre::{Result, eyre::eyre, eyre::WrapErr};
+use serde::Deserialize;
+use crate::cache;
+use crate::dates;
+use crate::etf::ETF;
+use crate::money;
+use crate::performance;
+use crate::provider::{Capabilities, IssuerProvider};
+use crate::schema::{fields, ColumnMap, Record, Schema, Section};
+use crate::utils;
+
+// An issuer product list described in a TOML file instead of code.
+//
+//     name = "Xtrackers"
+//     [source]
+//     url = "https://example.com/product-list.xlsx"
+//     path = "data/xtrackers_etfs.xlsx"
+//     [header]
+//     marker = "Name"
+//     [columns]
+//     name = ["Name"]
+//     ter = { headers = ["TER p.a. (%)"], required = true }
+//     [performance]
+//     discrete = true
+//     [[transforms]]
+//     field = "distribution_policy"
+//     map = { Reinvested = "Accumulating", Distributed = "Distributing" }
+//     [[skip]]
+//     field = "name"
+//     starts_with = ["Past performance"]
+#[derive(Debug, Clone, Deserialize)]
+pub struct IssuerDefinition {
+    pub name: String,
+    pub source: Source,
+    #[serde(default)]
+    pub header: HeaderRules,
+    pub columns: BTreeMap<String, ColumnDefinition>,
+    #[serde(default)]
+    pub performance: PerformanceRules,
+    #[serde(default)]
+    pub transforms: Vec<Transform>,
+    #[serde(default)]
+    pub skip: Vec<SkipRule>,
+}
+
+// Where the product list comes from. At least one of `url` and `path` is needed.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Source {
+    pub url: Option<String>,        // Downloaded into the cache and refreshed when stale
+    pub path: Option<String>,       // Local copy, used when there is no URL or the download fails
+    pub cache_file: Option<String>, // Name in the cache, the file name of `path` by default
+    pub sheet: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct HeaderRules {
+    pub marker: Option<String>, // Cell text identifying the header row
+    #[serde(default)]
+    pub group_row: bool,        // Empty headers are named by the row above
+    #[serde(default)]
+    pub sections: bool,         // The header repeats before each block of funds
+}
+
+// Discrete return columns have no fixed header: they are named by the period they cover
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct PerformanceRules {
+    #[serde(default)]
+    pub discrete: bool,              // Columns titled "2/20-2/21 (%)" or "2023 (%)" are discrete returns
+    #[serde(default)]
+    pub index_prefixes: Vec<String>, // Header prefixes of the index's discrete returns, e.g. "Benchmark"
+}
+
+// Headers a field can be found under. A plain list is an optional column.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum ColumnDefinition {
+    Headers(Vec<String>),
+    Detailed {
+        headers: Vec<String>,
+        #[serde(default)]
+        required: bool,
+    },
+}
+
+// Rewrites the value of `field`. Steps run in the order they are declared here.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Transform {
+    pub field: String,
+    pub from: Option<String>,                 // Source field, `field` itself by default
+    #[serde(default)]
+    pub from_header: bool,                    // Use the header of the source column instead of the cell
+    #[serde(default)]
+    pub fraction_to_percent: bool,            // Multiply by 100 unless written with a '%'
+    #[serde(default)]
+    pub strip: Vec<String>,                   // Substrings removed from the value
+    #[serde(default)]
+    pub map: BTreeMap<String, String>,        // Exact replacements
+    #[serde(default)]
+    pub contains: BTreeMap<String, String>,   // Replacement when the value contains the key
+    pub scale: Option<f64>,                   // Numeric multiplier
+    pub default: Option<String>,              // Value when missing, or when no map/contains entry matched.
+                                              // An empty default removes the field.
+}
+
+// Drops a row when `field` matches any of the conditions
+#[derive(Debug, Clone, Deserialize)]
+pub struct SkipRule {
+    pub field: String,
+    #[serde(default)]
+    pub empty: bool,
+    #[serde(default)]
+    pub equals: Vec<String>,
+    #[serde(default)]
+    pub starts_with: Vec<String>,
+    #[serde(default)]
+    pub contains: Vec<String>,
+}
+
+impl SkipRule {
+    fn matches(&self, record: &Record) -> bool {
+        match record.get(&self.field) {
+            None => self.empty,
+            Some(value) => {
+                self.equals.iter().any(|text| value == text)
+                    || self.starts_with.iter().any(|text| value.starts_with(text.as_str()))
+                    || self.contains.iter().any(|text| value.contains(text.as_str()))
+            }
+        }
+    }
+}
+
+impl Transform {
+    fn apply(&self, record: &mut Record, columns: &ColumnMap) {
+        let source = self.from.as_deref().unwrap_or(&self.field);
+        let value = if self.from_header {
+            columns.header(source).map(str::to_string)
+        } else {
+            record.get(source).cloned()
+        };
+
+        let value = value.map(|mut value| {
+            if self.fraction_to_percent && !value.contains('%') {
+                // Rounded so 0.0035 reads 0.35, not 0.35000000000000003
+                if let Some(number) = utils::parse_number(&value) {
+                    value = ((number * 100.0 * 1e10).round() / 1e10).to_string();
+                }
+            }
+            for text in &self.strip {
+                value = value.replace(text.as_str(), "");
+            }
+            value = value.trim().to_string();
+
+            let mut matched = self.map.is_empty() && self.contains.is_empty();
+            if let Some(mapped) = self.map.get(&value) {
+                value = mapped.clone();
+                matched = true;
+            } else if let Some((_, mapped)) = self.contains.iter().find(|(key, _)| value.contains(key.as_str())) {
+                value = mapped.clone();
+                matched = true;
+            }
+            if !matched {
+                if let Some(default) = &self.default {
+                    value = default.clone();
+                }
+            }
+
+            if let Some(scale) = self.scale {
+                if let Some(number) = utils::parse_number(&value) {
+                    value = (number * scale).to_string();
+                }
+            }
+            value
+        });
+
+        let value = value.filter(|value| !value.is_empty()).or_else(|| self.default.clone());
+        match value.filter(|value| !value.is_empty()) {
+            Some(value) => record.insert(self.field.clone(), value),
+            None => record.remove(&self.field),
+        };
+    }
+}
+
+impl IssuerDefinition {
+    pub fn from_toml(text: &str) -> Result<Self> {
+        toml::from_str(text).wrap_err("Invalid issuer definition")
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .wrap_err_with(|| format!("Failed to read issuer definition {}", path.display()))?;
+        Self::from_toml(&text)
+            .wrap_err_with(|| format!("Invalid issuer definition {}", path.display()))
+    }
+
+    pub fn schema(&self) -> Schema {
+        let mut schema = Schema::new(&self.name);
+        for (field, column) in &self.columns {
+            let (headers, required) = match column {
+                ColumnDefinition::Headers(headers) => (headers, false),
+                ColumnDefinition::Detailed { headers, required } => (headers, *required),
+            };
+            let aliases: Vec<&str> = headers.iter().map(String::as_str).collect();
+            schema = if required {
+                schema.required(field, &aliases)
+            } else {
+                schema.optional(field, &aliases)
+            };
+        }
+        if self.header.group_row {
+            schema = schema.with_group_header_row();
+        }
+        if let Some(marker) = &self.header.marker {
+            schema = schema.with_marker(marker);
+        }
+        schema
+    }
+
+    fn cache_file(&self) -> String {
+        match (&self.source.cache_file, &self.source.path) {
+            (Some(name), _) => name.clone(),
+            (None, Some(path)) => cache::file_name(path),
+            (None, None) => format!("{}_etfs.xlsx", self.name.to_lowercase().replace(' ', "_")),
+        }
+    }
+
+    // Local file holding the product list: the cached download when the source has a URL,
+    // the local copy when it has none or the download fails without a previous copy
+    pub fn source_file(&self) -> Result<PathBuf> {
+        let local = self.source.path.as_ref().map(PathBuf::from);
+
+        if let Some(url) = &self.source.url {
+            match cache::global().fetch(&self.cache_file(), url) {
+                Ok(path) => return Ok(path),
+                Err(e) => match &local {
+                    Some(local) if local.exists() => {
+                        utils::log(&format!("{:#}; using {}", e, local.display()));
+                    }
+                    _ => return Err(e),
+                },
+            }
+        }
+
+        match local {
+            Some(local) if local.exists() => Ok(local),
+            Some(local) => Err(eyre!("File does not exist at: {}", local.display())),
+            None => Err(eyre!("Issuer definition {} has neither a source URL nor a path", self.name)),
+        }
+    }
+
+    // Download the product list again, whatever the age of the cached copy
+    pub fn refresh(&self) -> Result<PathBuf> {
+        let url = self.source.url.as_deref()
+            .ok_or_else(|| eyre!("Issuer definition {} has no source URL", self.name))?;
+        cache::global().download(&self.cache_file(), url)
+    }
+
+    // Read the product list and turn every fund row into an ETF
+    pub fn load_etfs(&self) -> Result<Vec<ETF>> {
+        let path = self.source_file()?;
+        let path = path.to_str().ok_or_else(|| eyre!("Invalid path {}", path.display()))?;
+
+        let rows = utils::read_sheet(path, self.source.sheet.as_deref())?;
+        self.parse_rows(&rows)
+    }
+
+    // Record fields of the discrete return columns of `header`, by column index
+    fn discrete_columns(&self, header: &[String]) -> Vec<(usize, String)> {
+        if !self.performance.discrete {
+            return Vec::new();
+        }
+        header.iter().enumerate()
+            .filter_map(|(index, cell)| {
+                let cell = cell.trim();
+                let index_header = self.performance.index_prefixes.iter().find_map(|prefix| {
+                    let rest = cell.get(..prefix.len()).filter(|start| start.eq_ignore_ascii_case(prefix))?;
+                    Some(cell[rest.len()..].trim())
+                });
+                let (prefix, period) = match index_header {
+                    Some(period) => (performance::INDEX_DISCRETE_PREFIX, period),
+                    None => (performance::DISCRETE_PREFIX, cell),
+                };
+                performance::discrete_period(period).map(|_| (index, format!("{}{}", prefix, period)))
+            })
+            .collect()
+    }
+
+    // Parse product list rows into ETFs, in sheet order
+    pub fn parse_rows(&self, rows: &[Vec<String>]) -> Result<Vec<ETF>> {
+        let schema = self.schema();
+        let sections = if self.header.sections {
+            schema.find_sections(rows)?
+        } else {
+            let (header_row, columns) = schema.find_header(rows)?;
+            vec![Section { header_row, end: rows.len(), columns }]
+        };
+
+        // Rows around the funds may give the date of the whole sheet: a preamble line like
+        // "Spreadsheet created on 28 Mar 25" or "As Of" rows between the funds
+        let first_header = sections.first().map_or(0, |section| section.header_row);
+        let mut dated_rows: Vec<Vec<String>> = rows[..first_header].to_vec();
+
+        let mut etfs = Vec::new();
+        for section in &sections {
+            let discrete = self.discrete_columns(&schema.header_at(rows, section.header_row));
+            let record = |row: &Vec<String>| {
+                let mut record = section.columns.record(row);
+                for (index, field) in &discrete {
+                    if let Some(value) = row.get(*index).and_then(|cell| utils::non_empty(cell)) {
+                        record.insert(field.clone(), value);
+                    }
+                }
+                record
+            };
+
+            etfs.extend(section.rows(rows).iter()
+                .map(|row| (row, record(row)))
+                .filter(|(row, record)| {
+                    let skipped = self.skip.iter().any(|rule| rule.matches(record));
+                    if skipped {
+                        dated_rows.push(row.to_vec());
+                    }
+                    !skipped
+                })
+                .filter_map(|(_, mut record)| {
+                    add_aum_unit(&mut record, &section.columns);
+                    for transform in &self.transforms {
+                        transform.apply(&mut record, &section.columns);
+                    }
+                    ETF::from_record(&record, &self.name)
+                }));
+        }
+
+        // Issuers repeat a fund once per listing: keep the first row and collect the listings
+        let mut merged: Vec<ETF> = Vec::with_capacity(etfs.len());
+        let mut positions: HashMap<String, usize> = HashMap::new();
+        for etf in etfs {
+            match positions.get(&etf.isin) {
+                Some(&index) => merged[index].merge_listings(&etf),
+                None => {
+                    positions.insert(etf.isin.clone(), merged.len());
+                    merged.push(etf);
+                }
+            }
+        }
+        let mut etfs = merged;
+
+        // A date per row wins over the date of the sheet
+        if let Some(sheet_as_of) = dates::find_as_of(&dated_rows) {
+            for etf in etfs.iter_mut().filter(|etf| etf.as_of.is_none()) {
+                etf.as_of = Some(sheet_as_of);
+            }
+        }
+
+        Ok(etfs)
+    }
+}
+
+// Record the currency and magnitude named by the AUM header ("AuM (CHF)", "Net Assets (M)"),
+// so transforms can still override them
+fn add_aum_unit(record: &mut Record, columns: &ColumnMap) {
+    let Some(header) = columns.header(fields::AUM) else { return };
+    let (currency, scale) = money::header_unit(header);
+    if let Some(currency) = currency {
+        record.entry(fields::AUM_CURRENCY.to_string()).or_insert(currency);
+    }
+    if scale != 1.0 {
+        record.entry(fields::AUM_SCALE.to_string()).or_insert(scale.to_string());
+    }
+}
+
+// Built-in definitions, compiled into the binary
+pub const XTRACKERS: &str = include_str!("../issuers/builtin/xtrackers.toml");
+pub const ISHARES: &str = include_str!("../issuers/builtin/ishares.toml");
+pub const INVESCO: &str = include_str!("../issuers/builtin/invesco.toml");
+
+// Parse a built-in definition. These are checked in with the code, so a failure is a bug.
+pub fn builtin(text: &str) -> IssuerDefinition {
+    IssuerDefinition::from_toml(text).expect("built-in issuer definition is valid")
+}
+
+// Read every `*.toml` definition in `dir`
+pub fn load_definitions(dir: &Path) -> Result<Vec<IssuerDefinition>> {
+    let mut paths: Vec<_> = std::fs::read_dir(dir)
+        .wrap_err_with(|| format!("Failed to read issuer definitions from {}", dir.display()))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "toml"))
+        .collect();
+    paths.sort();
+
+    paths.iter().map(|path| IssuerDefinition::from_file(path)).collect()
+}
+
+// Provider for issuers that only exist as a definition file
+pub struct DefinitionProvider {
+    definition: IssuerDefinition,
+}
+
+impl DefinitionProvider {
+    pub fn new(definition: IssuerDefinition) -> Self {
+        Self { definition }
+    }
+}
+
+impl IssuerProvider for DefinitionProvider {
+    fn name(&self) -> &str {
+        &self.definition.name
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities { list_etfs: true, holdings: false, price_history: false }
+    }
+
+    fn list_etfs(&self) -> Result<Vec<ETF>> {
+        self.definition.load_etfs()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::classification::{AssetClass, Hedging, Replication};
+    use chrono::NaiveDate;
+
+    fn rows(rows: &[&[&str]]) -> Vec<Vec<String>> {
+        rows.iter().map(|row| row.iter().map(|cell| cell.to_string()).collect()).collect()
+    }
+
+    #[test]
+    fn builtin_definitions_parse() {
+        for text in [XTRACKERS, ISHARES, INVESCO] {
+            let definition = builtin(text);
+            let schema = definition.schema();
+            assert!(schema.columns.iter().any(|column| column.field == fields::ISIN && column.required), "{}", definition.name);
+        }
+    }
+
+    #[test]
+    fn reads_invesco_sections() {
+        let etfs = builtin(INVESCO).parse_rows(&rows(&[
+            &["Spreadsheet created on 28 Mar 25 10:06 UTC"],
+            &["Equity ETFs", "", "", "", ""],
+            &["", "ISIN", "Bloomberg", "OCF/TER", "Base currency"],
+            &["Invesco S&P 500 UCITS ETF", "IE00B3YCGJ38", "SPXS LN", "0.0005", "USD"],
+            &["Invesco S&P 500 UCITS ETF", "IE00B3YCGJ38", "SPXD GY", "0.0005", "USD"],
+            &["Invesco MSCI World EUR Hdg UCITS ETF", "IE00BYML9W36", "MXWE LN", "0.0035", "EUR"],
+            &["Fixed Income ETFs"],
+            &["", "ISIN", "Bloomberg", "OCF/TER", "Base currency"],
+            &["Invesco US Treasury Bond UCITS ETF", "IE00BF2FN646", "TRXG LN", "0.06%", "USD"],
+        ])).unwrap();
+
+        let isins: Vec<&str> = etfs.iter().map(|etf| etf.isin.as_str()).collect();
+        assert_eq!(isins, ["IE00B3YCGJ38", "IE00BYML9W36", "IE00BF2FN646"]);
+
+        // The second line of the S&P 500 fund is merged into the first
+        assert_eq!(etfs[0].listings.len(), 2);
+        assert!(etfs[0].has_code("SPXD"));
+
+        // Fractions become percent, values written with a '%' are kept
+        assert_eq!(etfs[1].ter, 0.35);
+        assert_eq!(etfs[2].ter, 0.06);
+
+        assert_eq!(etfs[0].hedging, Some(Hedging::Unhedged));
+        assert_eq!(etfs[1].hedging, Some(Hedging::Hedged("EUR".to_string())));
+        assert_eq!(etfs[0].asset_class, Some(AssetClass::Equity));
+        assert_eq!(etfs[2].asset_class, Some(AssetClass::FixedIncome));
+        assert!(etfs.iter().all(|etf| etf.as_of == NaiveDate::from_ymd_opt(2025, 3, 28)));
+    }
+
+    #[test]
+    fn skips_xtrackers_disclaimer_rows() {
+        let etfs = builtin(XTRACKERS).parse_rows(&rows(&[
+            &["Name", "ISIN", "TER p.a. (%)", "Currency exposure", "2/23-2/24 (%)"],
+            &["Xtrackers MSCI World Swap UCITS ETF 1C", "LU0274208692", "0.45", "Currency Hedged (EUR)", "12.3"],
+            &["Xtrackers Physical Gold ETC", "DE000A1E0HR8", "0.25", "USD", "-"],
+            &["Past performance is no guide to future returns", "", "", "", ""],
+            &["© DWS", "", "", "", ""],
+        ])).unwrap();
+
+        assert_eq!(etfs.len(), 2);
+        assert_eq!(etfs[0].replication, Some(Replication::SyntheticSwap));
+        assert_eq!(etfs[1].replication, Some(Replication::Physical));
+        assert_eq!(etfs[0].hedging, Some(Hedging::Hedged("EUR".to_string())));
+        assert_eq!(etfs[0].performance.fund.discrete_summary(), "2/23-2/24: 12.30%");
+        assert!(etfs[1].performance.fund.discrete.is_empty());
+    }
+
+    #[test]
+    fn skip_rules_and_defaults() {
+        let definition = IssuerDefinition::from_toml(r#"
+            name = "Test"
+            [source]
+            path = "test.xlsx"
+            [columns]
+            name = { headers = ["Fund"], required = true }
+            isin = { headers = ["ISIN"], required = true }
+            distribution_policy = ["Income"]
+            [[transforms]]
+            field = "distribution_policy"
+            map = { Yes = "Distributing", No = "Accumulating" }
+            default = ""
+            [[skip]]
+            field = "distribution_policy"
+            empty = true
+            [[skip]]
+            field = "name"
+            equals = ["Total"]
+        "#).unwrap();
+
+        let etfs = definition.parse_rows(&rows(&[
+            &["Fund", "ISIN", "Income"],
+            &["Dist", "IE00B3YCGJ38", "Yes"],
+            &["Acc", "IE00BYML9W36", "No"],
+            &["Unknown", "IE00BF2FN646", ""],
+            &["Total", "IE00B4L5Y983", "Yes"],
+        ])).unwrap();
+
+        let names: Vec<&str> = etfs.iter().map(|etf| etf.name.as_str()).collect();
+        assert_eq!(names, ["Dist", "Acc"]);
+        // An unmapped value takes the empty default, which removes the field
+        let mut record: Record = [("distribution_policy".to_string(), "Maybe".to_string())].into();
+        definition.transforms[0].apply(&mut record, &ColumnMap::default());
+        assert!(record.is_empty());
+    }
+}
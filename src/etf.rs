@@ -1,136 +1,270 @@
+use chrono::NaiveDate;
 use serde::{Deserialize, Serialize};
 use color_eyre::Result;
-use serde_json::Value;
+use crate::classification::{AssetClass, DistributionPolicy, Hedging, Replication};
+use crate::dates;
+use crate::identifiers::Identifiers;
+use crate::listing::Listing;
+use crate::money::Aum;
+use crate::performance::Performance;
+use crate::provider::{HoldingsStatus, ProviderRegistry};
+use crate::schema::{fields, ColumnMap, Record};
+use crate::utils;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ETF {
     pub name: String,
     pub isin: String,
-    pub asset_class: String,
+    pub identifiers: Identifiers, // Tickers and national codes
+    pub listings: Vec<Listing>,   // Exchanges the fund trades on, main listing first
+    pub asset_class: Option<AssetClass>,
     pub ter: f64,
     pub currency: String,
-    pub aum: String,
+    pub aum: Aum,
     pub currency_exposure: String,
-    pub distribution_policy: String,
-    pub launch_date: String,
-    pub performance_1y: Option<f64>,
-    pub performance_ytd: Option<f64>,
-    pub holdings: Vec<(String, f64)>, // (ISIN, weight in percent)
+    pub distribution_policy: Option<DistributionPolicy>,
+    pub replication: Option<Replication>,
+    pub hedging: Option<Hedging>,
+    pub launch_date: Option<NaiveDate>,
+    pub performance: Performance, // Fund and index returns published by the issuer
+    pub holdings: Vec<Holding>,
+    pub as_of: Option<NaiveDate>,          // Date the issuer's figures (AUM, performance) are valid for
+    pub holdings_as_of: Option<NaiveDate>, // Date reported by the issuer for the holdings
+    pub holdings_basis: HoldingsBasis,
     pub issuer: String,
     pub product_url: Option<String>, // URL to the product page
 }
 
+// What the holdings of an ETF represent
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum HoldingsBasis {
+    #[default]
+    Portfolio,        // Securities that make up the index exposure
+    SubstituteBasket, // Collateral basket of a swap-based fund, not the index constituents
+}
+
 impl ETF {
-    pub fn from_row(row: &[String], issuer: String) -> Option<Self> {
-        // Skip empty rows or rows that don't look like ETF data
-        if row.len() < 17 || row[0].is_empty() || row[1].is_empty() || 
-           row[0].starts_with("Past performance") || row[0].starts_with("©") {
-            return None;
-        }
+    // Build an ETF from the values of a product list row
+    pub fn from_record(record: &Record, issuer: &str) -> Option<Self> {
+        let text = |field: &str| record.get(field).cloned().unwrap_or_default();
+        let number = |field: &str| record.get(field).and_then(|value| utils::parse_number(value));
+
+        // Skip rows that don't look like ETF data
+        let name = record.get(fields::NAME)?.clone();
+        let isin = record.get(fields::ISIN)?.clone();
+
+        let optional = |field: &str| record.get(field).and_then(|value| utils::non_empty(value));
+        let identifiers = Identifiers::new(&isin, Identifiers {
+            ticker: optional(fields::TICKER),
+            bloomberg: optional(fields::BLOOMBERG),
+            reuters: optional(fields::REUTERS),
+            wkn: optional(fields::WKN),
+            sedol: optional(fields::SEDOL),
+            cusip: optional(fields::CUSIP),
+        });
+
+        let listings = Listing::from_codes(
+            optional(fields::EXCHANGE).as_deref(),
+            optional(fields::TICKER).as_deref(),
+            optional(fields::TRADING_CURRENCY).as_deref(),
+            optional(fields::BLOOMBERG).as_deref(),
+            optional(fields::REUTERS).as_deref(),
+        );
 
         Some(Self {
-            name: row[0].clone(),
-            isin: row[1].clone(),
-            asset_class: row[2].clone(),
-            ter: row[3].trim().replace('%', "").parse().unwrap_or(0.0),
-            currency: row[4].clone(),
-            aum: row[5].clone(),
-            currency_exposure: row[6].clone(),
-            distribution_policy: row[7].clone(),
-            launch_date: row[8].clone(),
-            performance_1y: row[15].trim().replace('%', "").parse().ok(),
-            performance_ytd: row[14].trim().replace('%', "").parse().ok(),
+            name,
+            isin,
+            identifiers,
+            listings: listings.into_iter().collect(),
+            asset_class: AssetClass::parse(&text(fields::ASSET_CLASS)),
+            ter: number(fields::TER).unwrap_or(0.0),
+            currency: text(fields::CURRENCY),
+            aum: Aum::parse(
+                &text(fields::AUM),
+                record.get(fields::AUM_CURRENCY).or(record.get(fields::CURRENCY)).map(String::as_str),
+                number(fields::AUM_SCALE).unwrap_or(1.0),
+            ),
+            currency_exposure: text(fields::CURRENCY_EXPOSURE),
+            distribution_policy: DistributionPolicy::parse(&text(fields::DISTRIBUTION_POLICY)),
+            replication: Replication::parse(&text(fields::REPLICATION)),
+            hedging: Hedging::parse(&text(fields::HEDGING), &text(fields::CURRENCY)),
+            launch_date: record.get(fields::LAUNCH_DATE).and_then(|value| dates::parse_date(value)),
+            performance: Performance::from_record(record),
             holdings: Vec::new(), // Initialize with empty holdings
-            issuer,
+            as_of: record.get(fields::AS_OF).and_then(|value| dates::parse_date(value)),
+            holdings_as_of: None,
+            holdings_basis: HoldingsBasis::Portfolio,
+            issuer: issuer.to_string(),
             product_url: None, // Initialize with None
         })
     }
     
-    // Load holdings information based on the ETF issuer
-    pub fn load_holdings(&mut self) -> Result<()> {
-        match self.issuer.as_str() {
-            "Invesco" => self.load_invesco_holdings()?,
-            // Add other issuers here as needed
-            _ => {} // Do nothing for unsupported issuers
-        }
-        
-        Ok(())
+    // Listing on `exchange`, e.g. "LSE" or "Xetra"
+    pub fn listing_on(&self, exchange: &str) -> Option<&Listing> {
+        self.listings.iter().find(|listing| listing.is_on(exchange))
     }
-    
-    // Load holdings for Invesco ETFs
-    fn load_invesco_holdings(&mut self) -> Result<()> {
-        // For Invesco ETFs, we don't need to add the 'I' prefix
-        let url = format!(
-            "https://dng-api.invesco.com/cache/v1/accounts/en_GB/shareclasses/{}/holdings/index?idType=isin",
-            self.isin
-        );
-        
-        println!("Fetching holdings from URL: {}", url);
-        
-        let client = reqwest::blocking::Client::new();
-        let response = client.get(&url)
-            .header("User-Agent", "Mozilla/5.0")
-            .send()?;
-        
-        if !response.status().is_success() {
-            println!("Failed to fetch holdings for {}: HTTP status {}", self.isin, response.status());
-            return Ok(());
-        }
-        
-        let json: Value = response.json()?;
-        
-        // Extract holdings from the JSON
-        if let Some(holdings) = json.get("holdings").and_then(|h| h.as_array()) {
-            self.holdings.clear(); // Clear existing holdings
-            
-            for holding in holdings {
-                if let (Some(isin), Some(weight)) = (
-                    holding.get("isin").and_then(|i| i.as_str()),
-                    holding.get("weight").and_then(|w| w.as_f64())
-                ) {
-                    self.holdings.push((isin.to_string(), weight));
-                }
+
+    // True if `code` is the ISIN of the fund or the ticker or code of one of its listings
+    pub fn has_code(&self, code: &str) -> bool {
+        let code = code.trim();
+        self.isin.eq_ignore_ascii_case(code)
+            || self.identifiers.ticker.as_deref().is_some_and(|ticker| ticker.eq_ignore_ascii_case(code))
+            || self.listings.iter().any(|listing| listing.matches(code))
+    }
+
+    // Add the listings of another row of the same fund, skipping the ones already known
+    pub fn merge_listings(&mut self, other: &ETF) {
+        for listing in &other.listings {
+            if !self.listings.contains(listing) {
+                self.listings.push(listing.clone());
             }
-            
-            println!("Successfully loaded {} holdings", self.holdings.len());
-        } else {
-            println!("No holdings found in the response for {}", self.isin);
         }
-        
-        Ok(())
+    }
+
+    // Load holdings information through the provider registered for the ETF issuer
+    pub fn load_holdings(&mut self) -> Result<HoldingsStatus> {
+        ProviderRegistry::with_builtin().load_holdings(self)
+    }
+}
+
+#[cfg(test)]
+impl ETF {
+    // Fund "Fund" of "Issuer" with only an ISIN and the given holdings
+    pub fn for_test(isin: &str, holdings: Vec<Holding>) -> Self {
+        let record: Record = [(fields::NAME, "Fund"), (fields::ISIN, isin)]
+            .into_iter()
+            .map(|(field, value)| (field.to_string(), value.to_string()))
+            .collect();
+        Self { holdings, ..Self::from_record(&record, "Issuer").unwrap() }
     }
 }
 
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct Security {
+// A single position of an ETF. Positions without an ISIN (cash, futures, FX forwards, swaps) are kept.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Holding {
     pub name: String,
-    pub isin: String,
-    pub country: String,
-    pub currency: String,
-    pub exchange: String,
-    pub security_type: String,
-    pub rating: String,
-    pub primary_listing: Option<String>,
-    pub industry_classification: Option<String>,
-    pub weight: f64,
+    pub isin: Option<String>,
+    pub ticker: Option<String>,
+    pub sector: Option<String>,
+    pub country: Option<String>,
+    pub currency: Option<String>,
+    pub asset_type: Option<String>, // Equity, Cash, Futures, FX, ...
+    pub exchange: Option<String>,
+    pub market_value: Option<f64>,  // In the fund currency
+    pub shares: Option<f64>,        // Shares, or nominal for bonds
+    pub weight: f64,                // Weight in percent
 }
 
-impl Security {
-    pub fn from_row(row: &[String]) -> Option<Self> {
+impl Holding {
+    // Build a holding from a holdings file row using the columns resolved from its header
+    pub fn from_columns(row: &[String], columns: &ColumnMap) -> Option<Self> {
+        let weight = columns.number(row, fields::WEIGHT)?;
+        let isin = columns.text(row, fields::ISIN);
+        let ticker = columns.text(row, fields::TICKER);
+        let name = columns.text(row, fields::NAME)
+            .or_else(|| isin.clone())
+            .or_else(|| ticker.clone())?;
+
         Some(Self {
-            name: row[0].clone(),
-            isin: row[1].clone(),
-            country: row[2].clone(),
-            currency: row[3].clone(),
-            exchange: row[4].clone(),
-            security_type: row[5].clone(),
-            rating: row[6].clone(),
-            primary_listing: Some(row[7].clone()),
-            industry_classification: Some(row[8].clone()),
-            weight: row[9].trim().replace('%', "").parse().unwrap_or(0.0),
+            name,
+            isin,
+            ticker,
+            sector: columns.text(row, fields::SECTOR),
+            country: columns.text(row, fields::COUNTRY),
+            currency: columns.text(row, fields::CURRENCY),
+            asset_type: columns.text(row, fields::ASSET_TYPE),
+            exchange: columns.text(row, fields::EXCHANGE),
+            market_value: columns.number(row, fields::MARKET_VALUE),
+            shares: columns.number(row, fields::SHARES),
+            weight,
         })
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::money::Money;
+    use crate::schema::Schema;
+
+    fn record(values: &[(&str, &str)]) -> Record {
+        values.iter().map(|(field, value)| (field.to_string(), value.to_string())).collect()
+    }
+
+    #[test]
+    fn builds_an_etf_from_a_product_list_row() {
+        let etf = ETF::from_record(&record(&[
+            (fields::NAME, "Invesco S&P 500 UCITS ETF"),
+            (fields::ISIN, "IE00B3YCGJ38"),
+            (fields::TER, "0.05"),
+            (fields::CURRENCY, "USD"),
+            (fields::AUM, "1,250"),
+            (fields::AUM_SCALE, "1000000"),
+            (fields::DISTRIBUTION_POLICY, "Accumulating"),
+            (fields::BLOOMBERG, "SPXS LN"),
+            (fields::REUTERS, "SPXS.L"),
+            (fields::TRADING_CURRENCY, "GBp"),
+            (fields::LAUNCH_DATE, "2010-05-20"),
+        ]), "Invesco").unwrap();
+
+        assert_eq!(etf.ter, 0.05);
+        assert_eq!(etf.aum.value, Some(Money { amount: 1_250_000_000.0, currency: "USD".to_string() }));
+        assert_eq!(etf.distribution_policy, Some(DistributionPolicy::Accumulating));
+        assert_eq!(etf.launch_date, NaiveDate::from_ymd_opt(2010, 5, 20));
+        assert_eq!(etf.listings.len(), 1);
+        assert_eq!(etf.listings[0].exchange.as_deref(), Some("LSE"));
+        assert_eq!(etf.listings[0].trading_currency.as_deref(), Some("GBp"));
+        assert_eq!(etf.issuer, "Invesco");
+
+        // Rows without a name or an ISIN are not funds
+        assert!(ETF::from_record(&record(&[(fields::NAME, "Equity ETFs")]), "Invesco").is_none());
+    }
 
+    #[test]
+    fn codes_match_the_isin_and_every_listing() {
+        let etf = ETF::from_record(&record(&[
+            (fields::NAME, "Fund"),
+            (fields::ISIN, "IE00B3YCGJ38"),
+            (fields::BLOOMBERG, "SPXS LN"),
+        ]), "Invesco").unwrap();
+        assert!(etf.has_code("ie00b3ycgj38"));
+        assert!(etf.has_code(" spxs "));
+        assert!(etf.has_code("SPXS LN"));
+        assert!(!etf.has_code("SPXP"));
+    }
+
+    #[test]
+    fn merging_rows_keeps_each_listing_once() {
+        let row = |bloomberg: &str| {
+            ETF::from_record(&record(&[(fields::NAME, "Fund"), (fields::ISIN, "IE00B3YCGJ38"), (fields::BLOOMBERG, bloomberg)]), "Invesco").unwrap()
+        };
+        let mut etf = row("SPXS LN");
+        etf.merge_listings(&row("SPXD GY"));
+        etf.merge_listings(&row("SPXS LN"));
+
+        let exchanges: Vec<Option<&str>> = etf.listings.iter().map(|listing| listing.exchange.as_deref()).collect();
+        assert_eq!(exchanges, [Some("LSE"), Some("Xetra")]);
+        assert!(etf.has_code("SPXD"));
+    }
+
+    #[test]
+    fn builds_holdings_from_a_holdings_row() {
+        let schema = Schema::new("Issuer")
+            .required(fields::WEIGHT, &["Weight (%)"])
+            .optional(fields::NAME, &["Name"])
+            .optional(fields::ISIN, &["ISIN"])
+            .optional(fields::MARKET_VALUE, &["Market Value"]);
+        let columns = schema.resolve(&["Name".to_string(), "ISIN".to_string(), "Weight (%)".to_string(), "Market Value".to_string()]).unwrap();
+        let row = |cells: [&str; 4]| cells.map(str::to_string);
+
+        let holding = Holding::from_columns(&row(["Apple", "US0378331005", "6.5", "1,000.50"]), &columns).unwrap();
+        assert_eq!(holding.weight, 6.5);
+        assert_eq!(holding.market_value, Some(1000.5));
+
+        // A position without a name is named by its ISIN; one without a weight is not a position
+        assert_eq!(Holding::from_columns(&row(["", "US0378331005", "1", ""]), &columns).unwrap().name, "US0378331005");
+        assert!(Holding::from_columns(&row(["Apple", "US0378331005", "-", ""]), &columns).is_none());
+        assert!(Holding::from_columns(&row(["", "", "1", ""]), &columns).is_none());
+    }
+}
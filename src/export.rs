@@ -0,0 +1,104 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::Path;
+use color_eyre::{Result, eyre::WrapErr};
+use crate::etf::ETF;
+use crate::fx::Reporting;
+use crate::performance::Period;
+use crate::risk::RiskMetrics;
+
+fn optional<T: ToString>(value: Option<T>) -> String {
+    value.map(|value| value.to_string()).unwrap_or_default()
+}
+
+// Write the catalog as CSV, one row per fund. With a reporting currency, AUM is also given in it,
+// at the rate of the fund's as-of date. Risk metrics are left blank for funds missing from `risk`.
+pub fn write_etfs_csv<W: Write>(
+    writer: W,
+    etfs: &[ETF],
+    reporting: Option<&Reporting>,
+    risk: &HashMap<String, RiskMetrics>,
+) -> Result<()> {
+    let mut csv = csv::Writer::from_writer(writer);
+
+    let mut header = vec![
+        "Issuer", "Name", "ISIN", "Listings", "Asset Class", "TER (%)", "Currency", "Distribution Policy", "Replication", "Hedging",
+        "Launch Date", "As Of", "Holdings As Of", "AUM (as published)", "AUM", "AUM Currency",
+    ];
+    let reporting_header = reporting.map(|reporting| format!("AUM ({})", reporting.currency));
+    if let Some(reporting_header) = &reporting_header {
+        header.push(reporting_header);
+    }
+    let performance_header: Vec<String> = Period::ALL.iter().map(|period| format!("{} Perf (%)", period))
+        .chain(Period::ALL.iter().map(|period| format!("{} Index (%)", period)))
+        .chain(["Discrete Perf".to_string(), "Discrete Index".to_string()])
+        .collect();
+    let risk_header = [
+        "Risk From", "Risk To", "Annualised Return (%)", "Volatility (%)", "Max Drawdown (%)", "Downside Deviation (%)",
+        "Sharpe", "Sortino", "Beta", "Rolling Window", "Rolling Worst (%)", "Rolling Average (%)", "Rolling Best (%)",
+    ];
+    header.extend(performance_header.iter().map(String::as_str));
+    header.extend(risk_header);
+    csv.write_record(&header)?;
+
+    for etf in etfs {
+        let mut record = vec![
+            etf.issuer.clone(),
+            etf.name.clone(),
+            etf.isin.clone(),
+            etf.listings.iter().map(|listing| listing.to_string()).collect::<Vec<_>>().join("; "),
+            optional(etf.asset_class.as_ref()),
+            etf.ter.to_string(),
+            etf.currency.clone(),
+            optional(etf.distribution_policy.as_ref()),
+            optional(etf.replication.as_ref()),
+            optional(etf.hedging.as_ref()),
+            optional(etf.launch_date),
+            optional(etf.as_of),
+            optional(etf.holdings_as_of),
+            etf.aum.raw.clone(),
+            optional(etf.aum.amount()),
+            etf.aum.currency().unwrap_or_default().to_string(),
+        ];
+        if let Some(reporting) = reporting {
+            let converted = etf.aum.value.as_ref().and_then(|money| reporting.convert(money, etf.as_of));
+            record.push(optional(converted.map(|money| money.amount)));
+        }
+        let performance = &etf.performance;
+        record.extend(Period::ALL.iter().map(|&period| optional(performance.fund(period))));
+        record.extend(Period::ALL.iter().map(|&period| optional(performance.index(period))));
+        record.extend([performance.fund.discrete_summary(), performance.index.discrete_summary()]);
+
+        let metrics = risk.get(&etf.isin).cloned().unwrap_or_default();
+        let rolling = metrics.rolling.as_ref();
+        record.extend([
+            optional(metrics.start),
+            optional(metrics.end),
+            optional(metrics.annualised_return),
+            optional(metrics.volatility),
+            optional(metrics.max_drawdown),
+            optional(metrics.downside_deviation),
+            optional(metrics.sharpe),
+            optional(metrics.sortino),
+            optional(metrics.beta),
+            optional(rolling.map(|rolling| rolling.window)),
+            optional(rolling.map(|rolling| rolling.worst)),
+            optional(rolling.map(|rolling| rolling.average)),
+            optional(rolling.map(|rolling| rolling.best)),
+        ]);
+        csv.write_record(&record)?;
+    }
+
+    csv.flush().wrap_err("Failed to write CSV")
+}
+
+pub fn export_etfs_csv(
+    path: &Path,
+    etfs: &[ETF],
+    reporting: Option<&Reporting>,
+    risk: &HashMap<String, RiskMetrics>,
+) -> Result<()> {
+    let file = std::fs::File::create(path)
+        .wrap_err_with(|| format!("Failed to create {}", path.display()))?;
+    write_etfs_csv(file, etfs, reporting, risk)
+}
@@ -0,0 +1,278 @@
+use std::collections::BTreeSet;
+use std::path::Path;
+use chrono::NaiveDate;
+use color_eyre::{Result, eyre::eyre, eyre::WrapErr};
+use serde::Deserialize;
+use crate::dates;
+use crate::money::{currency_code, Money, MINOR_UNITS};
+use crate::utils;
+
+// One unit of `base` is worth `rate` units of `quote`
+#[derive(Debug, Clone, PartialEq)]
+pub struct FxRate {
+    pub date: Option<NaiveDate>, // None for a rate valid at any date
+    pub base: String,
+    pub quote: String,
+    pub rate: f64,
+}
+
+// Rates as published in JSON: {"base": "USD", "date": "2025-03-28", "rates": {"EUR": 0.92}}.
+// A file holds one such object or a list of them.
+#[derive(Debug, Deserialize)]
+struct RatesSnapshot {
+    base: String,
+    date: Option<String>,
+    rates: std::collections::BTreeMap<String, f64>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum RatesFile {
+    One(RatesSnapshot),
+    Many(Vec<RatesSnapshot>),
+}
+
+// Exchange rates loaded from a local file. Rates are used in both directions and crossed
+// through a common currency when there is no direct rate.
+#[derive(Debug, Clone, Default)]
+pub struct FxTable {
+    rates: Vec<FxRate>,
+}
+
+// Major currency of a code and the number of its units in one major unit
+fn major_unit(code: &str) -> (String, f64) {
+    match MINOR_UNITS.iter().find(|(minor, _, _)| *minor == code) {
+        Some((_, major, units)) => (major.to_string(), *units),
+        None => (code.to_uppercase(), 1.0),
+    }
+}
+
+impl FxTable {
+    pub fn new(rates: Vec<FxRate>) -> Self {
+        Self { rates }
+    }
+
+    // Read a CSV file with `date,base,quote,rate` columns (date optional) or a JSON file of snapshots
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .wrap_err_with(|| format!("Failed to read FX rates from {}", path.display()))?;
+
+        let table = if path.extension().is_some_and(|ext| ext == "json") {
+            Self::from_json(&text)
+        } else {
+            Self::from_csv(&text)
+        };
+        table.wrap_err_with(|| format!("Invalid FX rates file {}", path.display()))
+    }
+
+    pub fn from_csv(text: &str) -> Result<Self> {
+        let rows = utils::read_csv(text)?;
+        let header = rows.first().ok_or_else(|| eyre!("Empty FX rates file"))?;
+        let column = |name: &str| header.iter().position(|cell| cell.eq_ignore_ascii_case(name));
+        let (base, quote, rate) = match (column("base"), column("quote"), column("rate")) {
+            (Some(base), Some(quote), Some(rate)) => (base, quote, rate),
+            _ => return Err(eyre!("FX rates need base, quote and rate columns, found {:?}", header)),
+        };
+        let date = column("date");
+
+        let mut rates = Vec::new();
+        for (line, row) in rows.iter().enumerate().skip(1) {
+            let cell = |index: usize| row.get(index).and_then(|cell| utils::non_empty(cell));
+            let (Some(base), Some(quote)) = (cell(base), cell(quote)) else { continue };
+            let rate = cell(rate)
+                .and_then(|rate| utils::parse_number(&rate))
+                .filter(|rate| *rate > 0.0)
+                .ok_or_else(|| eyre!("Invalid rate on line {}", line + 1))?;
+            let date = match date.and_then(cell) {
+                Some(text) => Some(dates::parse_date(&text).ok_or_else(|| eyre!("Invalid date on line {}", line + 1))?),
+                None => None,
+            };
+            rates.push(FxRate { date, base, quote, rate });
+        }
+        Ok(Self::new(rates))
+    }
+
+    pub fn from_json(text: &str) -> Result<Self> {
+        let snapshots = match serde_json::from_str(text).wrap_err("Invalid FX rates JSON")? {
+            RatesFile::One(snapshot) => vec![snapshot],
+            RatesFile::Many(snapshots) => snapshots,
+        };
+        let mut rates = Vec::new();
+        for RatesSnapshot { base, date, rates: quotes } in snapshots {
+            let date = match date {
+                Some(text) => Some(dates::parse_date(&text).ok_or_else(|| eyre!("Invalid date {}", text))?),
+                None => None,
+            };
+            rates.extend(quotes.into_iter()
+                .filter(|(_, rate)| *rate > 0.0)
+                .map(|(quote, rate)| FxRate { date, base: base.clone(), quote, rate }));
+        }
+        Ok(Self::new(rates))
+    }
+
+    pub fn rates(&self) -> &[FxRate] {
+        &self.rates
+    }
+
+    pub fn currencies(&self) -> BTreeSet<String> {
+        self.rates.iter()
+            .flat_map(|rate| [rate.base.clone(), rate.quote.clone()])
+            .collect()
+    }
+
+    // Rate between two major currencies, in either direction: the most recent on or before `date`,
+    // or with `later`, the earliest after it when the table starts later. Undated rates apply to
+    // any date.
+    fn direct_rate(&self, from: &str, to: &str, date: Option<NaiveDate>, later: bool) -> Option<f64> {
+        let rates: Vec<(Option<NaiveDate>, f64)> = self.rates.iter()
+            .filter_map(|rate| {
+                if rate.base.eq_ignore_ascii_case(from) && rate.quote.eq_ignore_ascii_case(to) {
+                    Some((rate.date, rate.rate))
+                } else if rate.base.eq_ignore_ascii_case(to) && rate.quote.eq_ignore_ascii_case(from) {
+                    Some((rate.date, 1.0 / rate.rate))
+                } else {
+                    None
+                }
+            })
+            .collect();
+
+        let on_or_before = |rate_date: &Option<NaiveDate>| match (date, rate_date) {
+            (Some(date), Some(rate_date)) => *rate_date <= date,
+            _ => true,
+        };
+        rates.iter()
+            .filter(|(rate_date, _)| on_or_before(rate_date))
+            .max_by_key(|(rate_date, _)| *rate_date)
+            .or_else(|| rates.iter().filter(|_| later).min_by_key(|(rate_date, _)| *rate_date))
+            .map(|(_, rate)| *rate)
+    }
+
+    // Units of `to` for one unit of `from`, as of `date` or the latest rate
+    pub fn rate(&self, from: &str, to: &str, date: Option<NaiveDate>) -> Option<f64> {
+        self.lookup(from, to, date, true)
+    }
+
+    // Rate known on `date`: None before the first rate, where `rate` would take a later one
+    pub fn rate_on(&self, from: &str, to: &str, date: NaiveDate) -> Option<f64> {
+        self.lookup(from, to, Some(date), false)
+    }
+
+    fn lookup(&self, from: &str, to: &str, date: Option<NaiveDate>, later: bool) -> Option<f64> {
+        let (from_major, from_units) = major_unit(from);
+        let (to_major, to_units) = major_unit(to);
+
+        let major_rate = if from_major == to_major {
+            Some(1.0)
+        } else {
+            self.direct_rate(&from_major, &to_major, date, later).or_else(|| {
+                // Cross through any currency quoted against both
+                self.currencies().iter().find_map(|pivot| {
+                    Some(self.direct_rate(&from_major, pivot, date, later)? * self.direct_rate(pivot, &to_major, date, later)?)
+                })
+            })
+        }?;
+        Some(major_rate * to_units / from_units)
+    }
+
+    pub fn convert(&self, money: &Money, to: &str, date: Option<NaiveDate>) -> Option<Money> {
+        if money.currency.is_empty() {
+            return None;
+        }
+        // The rate includes the minor units of `to`, so the amount is in `to` as given
+        let rate = self.rate(&money.currency, to, date)?;
+        Some(Money::new(money.amount * rate, to))
+    }
+}
+
+// Currency figures are reported in, with the rates to get there
+#[derive(Debug, Clone)]
+pub struct Reporting {
+    pub currency: String,
+    pub rates: FxTable,
+}
+
+impl Reporting {
+    pub fn new(currency: &str, rates: FxTable) -> Self {
+        Self { currency: currency_code(currency), rates }
+    }
+
+    // Amount in the reporting currency at the rate of `date` (the latest rate if None).
+    // None if the amount has no currency or no rate is known.
+    pub fn convert(&self, money: &Money, date: Option<NaiveDate>) -> Option<Money> {
+        self.rates.convert(money, &self.currency, date)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table() -> FxTable {
+        FxTable::from_csv("base,quote,rate\nGBP,USD,1.25\n").unwrap()
+    }
+
+    #[test]
+    fn converts_to_and_from_pence() {
+        let rates = table();
+        let pence = rates.convert(&Money::new(12.34, "GBP"), "GBX", None).unwrap();
+        assert_eq!(pence.currency, "GBX");
+        assert!((pence.amount - 1234.0).abs() < 1e-9);
+
+        let pounds = rates.convert(&pence, "GBP", None).unwrap();
+        assert_eq!(pounds.currency, "GBP");
+        assert!((pounds.amount - 12.34).abs() < 1e-9);
+    }
+
+    fn day(year: i32, month: u32, day: u32) -> Option<NaiveDate> {
+        NaiveDate::from_ymd_opt(year, month, day)
+    }
+
+    fn close(actual: Option<f64>, expected: f64) {
+        utils::assert_close(actual.unwrap(), expected);
+    }
+
+    #[test]
+    fn uses_the_latest_rate_on_or_before_the_date() {
+        let rates = FxTable::from_csv("date,base,quote,rate\n2025-03-03,EUR,USD,1.05\n2025-03-10,EUR,USD,1.08\n2025-03-17,EUR,USD,1.09\n").unwrap();
+        close(rates.rate("EUR", "USD", day(2025, 3, 12)), 1.08);
+        close(rates.rate("EUR", "USD", day(2025, 3, 10)), 1.08);
+        close(rates.rate("EUR", "USD", None), 1.09);
+        // Before the first rate, the earliest is used, unless the rate must be known on the day
+        close(rates.rate("EUR", "USD", day(2025, 1, 1)), 1.05);
+        assert_eq!(rates.rate_on("EUR", "USD", day(2025, 1, 1).unwrap()), None);
+    }
+
+    #[test]
+    fn inverts_and_crosses_rates() {
+        let rates = FxTable::from_json(r#"[
+            {"base": "USD", "date": "2025-03-28", "rates": {"EUR": 0.925, "CHF": 0.88, "JPY": 0}},
+            {"base": "GBP", "rates": {"USD": 1.25}}
+        ]"#).unwrap();
+        close(rates.rate("EUR", "USD", None), 1.0 / 0.925);
+        // Through USD
+        close(rates.rate("EUR", "CHF", None), 0.88 / 0.925);
+        close(rates.rate("GBP", "EUR", None), 1.25 * 0.925);
+        close(rates.rate("GBX", "CHF", None), 1.25 * 0.88 / 100.0);
+        // A rate of 0 is left out
+        assert_eq!(rates.rate("USD", "JPY", None), None);
+        assert_eq!(rates.convert(&Money::new(1.0, ""), "USD", None), None);
+    }
+
+    #[test]
+    fn rejects_invalid_rate_files() {
+        let error = |result: Result<FxTable>| format!("{:#}", result.unwrap_err());
+        assert!(error(FxTable::from_csv("from,to,rate\nEUR,USD,1.08\n")).starts_with("FX rates need base, quote and rate columns"));
+        assert_eq!(error(FxTable::from_csv("base,quote,rate\nEUR,USD,1.08\nEUR,CHF,-1\n")), "Invalid rate on line 3");
+        assert_eq!(error(FxTable::from_csv("date,base,quote,rate\nsoon,EUR,USD,1.08\n")), "Invalid date on line 2");
+        assert_eq!(error(FxTable::from_csv("")), "Empty FX rates file");
+        assert!(error(FxTable::from_json(r#"{"base": "USD"}"#)).starts_with("Invalid FX rates JSON"));
+        assert_eq!(error(FxTable::from_json(r#"{"base": "USD", "date": "soon", "rates": {}}"#)), "Invalid date soon");
+    }
+
+    #[test]
+    fn converts_pence_through_the_major_rate() {
+        let dollars = table().convert(&Money::new(100.0, "GBp"), "USD", None).unwrap();
+        assert_eq!(dollars.currency, "USD");
+        assert!((dollars.amount - 1.25).abs() < 1e-9);
+    }
+}
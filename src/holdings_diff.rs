@@ -0,0 +1,209 @@
+use std::collections::BTreeMap;
+use chrono::NaiveDate;
+use color_eyre::{Result, eyre::eyre};
+use crate::etf::Holding;
+use crate::store::{Store, StoredHoldings};
+
+// Weight of one position before and after, in percent. 0 where the position is absent.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WeightChange {
+    pub name: String,
+    pub isin: Option<String>,
+    pub before: f64,
+    pub after: f64,
+}
+
+impl WeightChange {
+    pub fn change(&self) -> f64 {
+        self.after - self.before
+    }
+}
+
+// What changed in the holdings of a fund between two dates
+#[derive(Debug, Clone, PartialEq)]
+pub struct HoldingsDiff {
+    pub fund_isin: String,
+    pub from: Option<NaiveDate>, // Dates of the two holdings, as reported by the issuer
+    pub to: Option<NaiveDate>,
+    pub added: Vec<WeightChange>,   // Largest first
+    pub removed: Vec<WeightChange>, // Largest first
+    pub changed: Vec<WeightChange>, // Held on both dates, largest weight change first
+    pub turnover: f64,              // Estimated one-way turnover in percent
+}
+
+impl HoldingsDiff {
+    // Positions held on both dates with the largest weight changes, up to `count`
+    pub fn top_changes(&self, count: usize) -> &[WeightChange] {
+        &self.changed[..count.min(self.changed.len())]
+    }
+}
+
+// Key a position is matched by across holdings lists: its ISIN, then ticker, then name
+fn position_key(holding: &Holding) -> String {
+    holding.isin.clone()
+        .or_else(|| holding.ticker.clone())
+        .unwrap_or_else(|| holding.name.clone())
+        .to_uppercase()
+}
+
+// Name, ISIN and weight of each position by `position_key`. Lines of the same position (cash in
+// several accounts, share lines) are added up.
+pub(crate) fn positions(holdings: &[Holding]) -> BTreeMap<String, (String, Option<String>, f64)> {
+    let mut positions: BTreeMap<String, (String, Option<String>, f64)> = BTreeMap::new();
+    for holding in holdings {
+        let entry = positions.entry(position_key(holding)).or_insert_with(|| (holding.name.clone(), holding.isin.clone(), 0.0));
+        entry.2 += holding.weight;
+    }
+    positions
+}
+
+// Weights scaled to add up to 100, so a change in the cash or derivative lines reported
+// does not show up as turnover in every other position
+pub(crate) fn normalized(positions: &mut BTreeMap<String, (String, Option<String>, f64)>) {
+    let total: f64 = positions.values().map(|(_, _, weight)| *weight).sum();
+    if total > 0.0 {
+        for (_, _, weight) in positions.values_mut() {
+            *weight *= 100.0 / total;
+        }
+    }
+}
+
+fn by_size(changes: &mut [WeightChange]) {
+    changes.sort_by(|a, b| b.change().abs().total_cmp(&a.change().abs()));
+}
+
+// Compare two holdings lists. Turnover is estimated as half the sum of the absolute weight
+// changes: the share of the portfolio that was bought (or sold). It includes weight drift
+// from price moves, so it overstates trading in volatile periods.
+pub fn diff(before: &[Holding], after: &[Holding]) -> HoldingsDiff {
+    let mut old = positions(before);
+    let mut new = positions(after);
+    normalized(&mut old);
+    normalized(&mut new);
+
+    let mut added = Vec::new();
+    let mut removed = Vec::new();
+    let mut changed = Vec::new();
+    let mut total_change = 0.0;
+
+    for (key, (name, isin, weight)) in &new {
+        match old.get(key) {
+            Some((_, _, before)) => {
+                total_change += (weight - before).abs();
+                changed.push(WeightChange { name: name.clone(), isin: isin.clone(), before: *before, after: *weight });
+            }
+            None => {
+                total_change += weight.abs();
+                added.push(WeightChange { name: name.clone(), isin: isin.clone(), before: 0.0, after: *weight });
+            }
+        }
+    }
+    for (key, (name, isin, weight)) in &old {
+        if !new.contains_key(key) {
+            total_change += weight.abs();
+            removed.push(WeightChange { name: name.clone(), isin: isin.clone(), before: *weight, after: 0.0 });
+        }
+    }
+
+    by_size(&mut added);
+    by_size(&mut removed);
+    by_size(&mut changed);
+    changed.retain(|change| change.change() != 0.0);
+
+    HoldingsDiff {
+        fund_isin: String::new(),
+        from: None,
+        to: None,
+        added,
+        removed,
+        changed,
+        turnover: total_change / 2.0,
+    }
+}
+
+// Compare two stored holdings of the same fund
+pub fn diff_stored(before: &StoredHoldings, after: &StoredHoldings) -> HoldingsDiff {
+    HoldingsDiff {
+        fund_isin: after.fund_isin.clone(),
+        from: Some(before.as_of.unwrap_or(before.snapshot.taken_at.date_naive())),
+        to: Some(after.as_of.unwrap_or(after.snapshot.taken_at.date_naive())),
+        ..diff(&before.holdings, &after.holdings)
+    }
+}
+
+// Compare the holdings of a fund stored on `from` and on `to` (the latest if None). Without
+// `from`, the two latest holdings are compared.
+pub fn diff_between(store: &Store, fund_isin: &str, from: Option<NaiveDate>, to: Option<NaiveDate>) -> Result<HoldingsDiff> {
+    let after = match to {
+        Some(to) => store.holdings_on(fund_isin, to)?,
+        None => store.holdings(fund_isin, None)?,
+    }
+    .ok_or_else(|| eyre!("No holdings of {} stored{}", fund_isin, to.map_or(String::new(), |to| format!(" on or before {}", to))))?;
+
+    let before = match from {
+        Some(from) => store.holdings_on(fund_isin, from)?,
+        None => store.holdings(fund_isin, Some(after.snapshot.id - 1))?,
+    }
+    .filter(|before| before.snapshot.id != after.snapshot.id)
+    .ok_or_else(|| eyre!("No earlier holdings of {} stored to compare with", fund_isin))?;
+
+    Ok(diff_stored(&before, &after))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::assert_close as close;
+
+    fn holding(name: &str, isin: Option<&str>, weight: f64) -> Holding {
+        Holding { name: name.to_string(), isin: isin.map(str::to_string), weight, ..Holding::default() }
+    }
+
+    #[test]
+    fn finds_added_removed_and_changed_positions() {
+        let before = [
+            holding("Apple", Some("US0378331005"), 50.0),
+            holding("Microsoft", Some("US5949181045"), 30.0),
+            holding("Nestle", Some("CH0038863350"), 20.0),
+        ];
+        let after = [
+            holding("Apple", Some("US0378331005"), 40.0),
+            holding("Microsoft", Some("US5949181045"), 30.0),
+            holding("Toyota", Some("JP3633400001"), 30.0),
+        ];
+
+        let diff = diff(&before, &after);
+        assert_eq!(diff.added.iter().map(|change| change.name.as_str()).collect::<Vec<_>>(), ["Toyota"]);
+        assert_eq!(diff.removed.iter().map(|change| change.name.as_str()).collect::<Vec<_>>(), ["Nestle"]);
+        // Microsoft kept its weight, so only Apple changed
+        assert_eq!(diff.changed.len(), 1);
+        close(diff.changed[0].change(), -10.0);
+        // Apple -10, Nestle -20, Toyota +30: half of the 60 points moved
+        close(diff.turnover, 30.0);
+    }
+
+    #[test]
+    fn unchanged_holdings_have_no_turnover() {
+        let holdings = [holding("Apple", Some("US0378331005"), 60.0), holding("Cash", None, 40.0)];
+        let diff = diff(&holdings, &holdings);
+        assert!(diff.added.is_empty() && diff.removed.is_empty() && diff.changed.is_empty());
+        close(diff.turnover, 0.0);
+    }
+
+    #[test]
+    fn a_full_switch_is_all_turnover() {
+        let diff = diff(&[holding("Apple", Some("US0378331005"), 100.0)], &[holding("Toyota", Some("JP3633400001"), 100.0)]);
+        close(diff.turnover, 100.0);
+    }
+
+    #[test]
+    fn positions_without_an_isin_match_by_name() {
+        let before = [holding("USD Cash", None, 10.0), holding("Apple", Some("US0378331005"), 90.0)];
+        let after = [holding("usd cash", None, 20.0), holding("Apple", Some("US0378331005"), 80.0)];
+
+        let diff = diff(&before, &after);
+        assert!(diff.added.is_empty() && diff.removed.is_empty());
+        assert!(diff.changed.iter().any(|change| change.isin.is_none() && change.change() == 10.0));
+        close(diff.turnover, 10.0);
+    }
+}
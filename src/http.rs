@@ -0,0 +1,441 @@
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use color_eyre::{Result, eyre::eyre, eyre::WrapErr};
+use serde::de::DeserializeOwned;
+use crate::replay::HttpMode;
+use crate::utils;
+
+#[derive(Debug, Clone)]
+pub struct HttpConfig {
+    pub user_agent: String,
+    pub timeout: Duration,
+    pub connect_timeout: Duration,
+    pub max_retries: u32,              // Retries after the first attempt
+    pub backoff: Duration,             // Delay before the first retry, doubled for each retry
+    pub max_retry_after: Duration,     // Longest wait a server's Retry-After header can ask for
+    pub min_host_interval: Duration,   // Minimum time between two requests to the same host
+    pub proxy: Option<String>,         // Proxy URL for all requests
+    pub ca_certificate: Option<PathBuf>, // Extra PEM root certificate, e.g. for a corporate proxy
+    // URL prefixes replaced before sending, e.g. to point loaders at a local server
+    pub rewrites: Vec<(String, String)>,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            user_agent: "Mozilla/5.0".to_string(),
+            timeout: Duration::from_secs(60),
+            connect_timeout: Duration::from_secs(15),
+            max_retries: 3,
+            backoff: Duration::from_millis(500),
+            max_retry_after: Duration::from_secs(60),
+            min_host_interval: Duration::from_millis(250),
+            proxy: None,
+            ca_certificate: None,
+            rewrites: Vec::new(),
+        }
+    }
+}
+
+impl HttpConfig {
+    // Defaults overridden by ETF_TOOL_HTTP_TIMEOUT_SECS, ETF_TOOL_HTTP_RETRIES,
+    // ETF_TOOL_HTTP_MAX_RETRY_AFTER_SECS, ETF_TOOL_HTTP_PROXY and ETF_TOOL_HTTP_CA_CERT
+    pub fn from_env() -> Self {
+        let mut config = Self::default();
+        if let Some(secs) = std::env::var("ETF_TOOL_HTTP_TIMEOUT_SECS").ok().and_then(|s| s.parse().ok()) {
+            config.timeout = Duration::from_secs(secs);
+        }
+        if let Some(retries) = std::env::var("ETF_TOOL_HTTP_RETRIES").ok().and_then(|s| s.parse().ok()) {
+            config.max_retries = retries;
+        }
+        if let Some(secs) = std::env::var("ETF_TOOL_HTTP_MAX_RETRY_AFTER_SECS").ok().and_then(|s| s.parse().ok()) {
+            config.max_retry_after = Duration::from_secs(secs);
+        }
+        if let Ok(proxy) = std::env::var("ETF_TOOL_HTTP_PROXY") {
+            config.proxy = Some(proxy);
+        }
+        if let Ok(path) = std::env::var("ETF_TOOL_HTTP_CA_CERT") {
+            config.ca_certificate = Some(PathBuf::from(path));
+        }
+        config
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).to_string()
+    }
+
+    pub fn json<T: DeserializeOwned>(&self) -> Result<T> {
+        serde_json::from_slice(&self.body).wrap_err("Failed to parse JSON response")
+    }
+}
+
+// Sends a single request. Implemented by the real network client and by stand-ins for tests.
+pub trait Transport: Send + Sync {
+    fn send(&self, request: &Request) -> Result<Response>;
+}
+
+// Transport over the network, sharing one connection pool
+pub struct ReqwestTransport {
+    client: reqwest::blocking::Client,
+}
+
+impl ReqwestTransport {
+    pub fn new(config: &HttpConfig) -> Result<Self> {
+        let mut builder = reqwest::blocking::Client::builder()
+            .timeout(config.timeout)
+            .connect_timeout(config.connect_timeout);
+
+        if let Some(proxy) = &config.proxy {
+            builder = builder.proxy(reqwest::Proxy::all(proxy).wrap_err("Invalid proxy URL")?);
+        }
+        if let Some(path) = &config.ca_certificate {
+            let pem = std::fs::read(path)
+                .wrap_err_with(|| format!("Failed to read CA certificate {}", path.display()))?;
+            let certificate = reqwest::Certificate::from_pem(&pem).wrap_err("Invalid CA certificate")?;
+            builder = builder.add_root_certificate(certificate);
+        }
+
+        let client = builder.build().wrap_err("Failed to build HTTP client")?;
+        Ok(Self { client })
+    }
+}
+
+impl Transport for ReqwestTransport {
+    fn send(&self, request: &Request) -> Result<Response> {
+        let mut builder = self.client.get(&request.url);
+        for (name, value) in &request.headers {
+            builder = builder.header(name, value);
+        }
+        let response = builder.send().wrap_err("Failed to send request")?;
+
+        let status = response.status().as_u16();
+        let headers = response.headers().iter()
+            .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+            .collect();
+        let body = response.bytes().wrap_err("Failed to read response body")?.to_vec();
+
+        Ok(Response { status, headers, body })
+    }
+}
+
+// Transport answering from a fixed set of responses. Each URL answers with its responses in
+// the order they were added, then keeps repeating the last one. Unknown URLs get a 404.
+#[derive(Default)]
+pub struct CannedTransport {
+    responses: Mutex<HashMap<String, VecDeque<Response>>>,
+    requests: Mutex<HashMap<String, usize>>, // Requests received per URL
+}
+
+impl CannedTransport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with(self, url: &str, status: u16, body: impl Into<Vec<u8>>) -> Self {
+        self.with_response(url, Response { status, headers: Vec::new(), body: body.into() })
+    }
+
+    pub fn with_response(self, url: &str, response: Response) -> Self {
+        self.responses.lock().unwrap_or_else(|e| e.into_inner())
+            .entry(url.to_string())
+            .or_default()
+            .push_back(response);
+        self
+    }
+
+    // Number of requests received for `url`
+    pub fn requests(&self, url: &str) -> usize {
+        self.requests.lock().unwrap_or_else(|e| e.into_inner()).get(url).copied().unwrap_or(0)
+    }
+}
+
+impl Transport for CannedTransport {
+    fn send(&self, request: &Request) -> Result<Response> {
+        *self.requests.lock().unwrap_or_else(|e| e.into_inner()).entry(request.url.clone()).or_insert(0) += 1;
+
+        let mut responses = self.responses.lock().unwrap_or_else(|e| e.into_inner());
+        let response = match responses.get_mut(&request.url) {
+            Some(queue) if queue.len() > 1 => queue.pop_front(),
+            Some(queue) => queue.front().cloned(),
+            None => None,
+        };
+        Ok(response.unwrap_or(Response {
+            status: 404,
+            headers: Vec::new(),
+            body: Vec::new(),
+        }))
+    }
+}
+
+// HTTP client shared by all issuer loaders: retries with backoff and per-host rate limiting
+// on top of a `Transport`
+pub struct HttpClient {
+    config: HttpConfig,
+    transport: Arc<dyn Transport>,
+    next_request: Mutex<HashMap<String, Instant>>, // Earliest start of the next request per host
+}
+
+impl HttpClient {
+    pub fn new(config: HttpConfig) -> Result<Self> {
+        let transport = ReqwestTransport::new(&config)?;
+        Ok(Self::with_transport(config, Arc::new(transport)))
+    }
+
+    // Client recording or replaying its traffic according to `mode`
+    pub fn for_mode(mut config: HttpConfig, mode: &HttpMode) -> Result<Self> {
+        if let HttpMode::Replay(_) = mode {
+            // Recorded responses are final: a missing fixture will not appear by waiting
+            config.max_retries = 0;
+            config.min_host_interval = Duration::ZERO;
+        }
+        let transport = ReqwestTransport::new(&config)?;
+        Ok(Self::with_transport(config, mode.wrap(Arc::new(transport))))
+    }
+
+    pub fn with_transport(config: HttpConfig, transport: Arc<dyn Transport>) -> Self {
+        Self {
+            config,
+            transport,
+            next_request: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn config(&self) -> &HttpConfig {
+        &self.config
+    }
+
+    pub fn transport(&self) -> Arc<dyn Transport> {
+        self.transport.clone()
+    }
+
+    fn rewrite(&self, url: &str) -> String {
+        for (prefix, replacement) in &self.config.rewrites {
+            if let Some(rest) = url.strip_prefix(prefix.as_str()) {
+                return format!("{}{}", replacement, rest);
+            }
+        }
+        url.to_string()
+    }
+
+    // Wait until the rate limit of the URL's host allows another request
+    fn wait_for_host(&self, url: &str) {
+        let host = reqwest::Url::parse(url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string))
+            .unwrap_or_default();
+
+        let wait = {
+            let mut next_request = self.next_request.lock().unwrap_or_else(|e| e.into_inner());
+            let now = Instant::now();
+            let start = next_request.get(&host).copied().filter(|next| *next > now).unwrap_or(now);
+            next_request.insert(host, start + self.config.min_host_interval);
+            start - now
+        };
+
+        if !wait.is_zero() {
+            std::thread::sleep(wait);
+        }
+    }
+
+    // GET a URL. Transport errors, 429 and 5xx responses are retried; other statuses are returned.
+    pub fn get(&self, url: &str) -> Result<Response> {
+        let request = Request {
+            url: self.rewrite(url),
+            headers: vec![("User-Agent".to_string(), self.config.user_agent.clone())],
+        };
+
+        let mut attempt = 0;
+        loop {
+            self.wait_for_host(&request.url);
+            let result = self.transport.send(&request);
+
+            // A server asking for a long wait would stall the whole load, so the wait is capped
+            let retry_after = match &result {
+                Ok(response) if response.status == 429 || response.status >= 500 => {
+                    response.header("Retry-After")
+                        .and_then(|secs| secs.trim().parse().ok())
+                        .map(|secs| Duration::from_secs(secs).min(self.config.max_retry_after))
+                }
+                Ok(_) => return result,
+                Err(_) => None,
+            };
+
+            if attempt >= self.config.max_retries {
+                return result.wrap_err_with(|| format!("GET {} failed after {} attempts", url, attempt + 1));
+            }
+
+            let backoff = self.config.backoff * 2u32.saturating_pow(attempt);
+            std::thread::sleep(retry_after.unwrap_or(backoff));
+            attempt += 1;
+        }
+    }
+
+    // GET a URL, failing on any non-success status
+    pub fn get_ok(&self, url: &str) -> Result<Response> {
+        let response = self.get(url)?;
+        if !response.is_success() {
+            return Err(eyre!("GET {} failed: HTTP status {}", url, response.status));
+        }
+        Ok(response)
+    }
+
+    pub fn get_json<T: DeserializeOwned>(&self, url: &str) -> Result<T> {
+        self.get_ok(url)?.json()
+    }
+
+    // Download a URL into a file
+    pub fn download(&self, url: &str, path: &Path) -> Result<()> {
+        let response = self.get_ok(url)?;
+        std::fs::write(path, &response.body)
+            .wrap_err_with(|| format!("Failed to write {}", path.display()))
+    }
+}
+
+static CLIENT: RwLock<Option<Arc<HttpClient>>> = RwLock::new(None);
+
+// Client used by the issuer loaders. Built from the environment unless `install` was called,
+// including the record/replay mode (see `replay::HttpMode::from_env`).
+pub fn client() -> Arc<HttpClient> {
+    if let Some(client) = CLIENT.read().unwrap_or_else(|e| e.into_inner()).as_ref() {
+        return client.clone();
+    }
+
+    let mut slot = CLIENT.write().unwrap_or_else(|e| e.into_inner());
+    if let Some(client) = slot.as_ref() {
+        return client.clone();
+    }
+    let mode = HttpMode::from_env();
+    let client = match HttpClient::for_mode(HttpConfig::from_env(), &mode) {
+        Ok(client) => client,
+        Err(e) => {
+            // An invalid proxy or certificate should not take the whole app down
+            utils::log(&format!("Invalid HTTP configuration, using defaults: {:#}", e));
+            HttpClient::for_mode(HttpConfig::default(), &mode).expect("default HTTP client can be built")
+        }
+    };
+    slot.insert(Arc::new(client)).clone()
+}
+
+// Replace the client used by the issuer loaders
+pub fn install(client: HttpClient) {
+    *CLIENT.write().unwrap_or_else(|e| e.into_inner()) = Some(Arc::new(client));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const URL: &str = "https://issuer.example/funds.xlsx";
+
+    fn config() -> HttpConfig {
+        HttpConfig {
+            max_retries: 2,
+            backoff: Duration::from_millis(1),
+            min_host_interval: Duration::ZERO,
+            ..HttpConfig::default()
+        }
+    }
+
+    fn response(status: u16, headers: &[(&str, &str)]) -> Response {
+        Response {
+            status,
+            headers: headers.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect(),
+            body: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn retries_server_errors_until_success() {
+        let transport = Arc::new(CannedTransport::new().with(URL, 503, "").with(URL, 200, "ok"));
+        let client = HttpClient::with_transport(config(), transport.clone());
+
+        let response = client.get_ok(URL).unwrap();
+        assert_eq!(response.text(), "ok");
+        assert_eq!(transport.requests(URL), 2);
+    }
+
+    #[test]
+    fn gives_up_when_retries_run_out() {
+        let transport = Arc::new(CannedTransport::new().with(URL, 500, ""));
+        let client = HttpClient::with_transport(config(), transport.clone());
+
+        let error = client.get_ok(URL).unwrap_err();
+        assert!(format!("{:#}", error).contains("HTTP status 500"));
+        assert_eq!(transport.requests(URL), 3);
+    }
+
+    #[test]
+    fn does_not_retry_client_errors() {
+        let transport = Arc::new(CannedTransport::new());
+        let client = HttpClient::with_transport(config(), transport.clone());
+
+        assert_eq!(client.get(URL).unwrap().status, 404);
+        assert_eq!(transport.requests(URL), 1);
+    }
+
+    #[test]
+    fn waits_as_long_as_retry_after_asks() {
+        let transport = Arc::new(CannedTransport::new()
+            .with_response(URL, response(429, &[("Retry-After", "0")]))
+            .with(URL, 200, "ok"));
+        // The backoff alone would take far longer than the test
+        let client = HttpClient::with_transport(HttpConfig { backoff: Duration::from_secs(30), ..config() }, transport.clone());
+
+        let started = Instant::now();
+        assert!(client.get(URL).unwrap().is_success());
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert_eq!(transport.requests(URL), 2);
+    }
+
+    #[test]
+    fn caps_retry_after() {
+        let transport = Arc::new(CannedTransport::new()
+            .with_response(URL, response(503, &[("Retry-After", "3600")]))
+            .with(URL, 200, "ok"));
+        let client = HttpClient::with_transport(HttpConfig { max_retry_after: Duration::from_millis(20), ..config() }, transport);
+
+        let started = Instant::now();
+        assert!(client.get(URL).unwrap().is_success());
+        let elapsed = started.elapsed();
+        assert!(elapsed >= Duration::from_millis(20) && elapsed < Duration::from_secs(5), "{:?}", elapsed);
+    }
+
+    #[test]
+    fn spaces_requests_to_the_same_host() {
+        let transport = Arc::new(CannedTransport::new().with(URL, 200, "ok"));
+        let client = HttpClient::with_transport(HttpConfig { min_host_interval: Duration::from_millis(50), ..config() }, transport);
+
+        let started = Instant::now();
+        for _ in 0..3 {
+            client.get(URL).unwrap();
+        }
+        assert!(started.elapsed() >= Duration::from_millis(100));
+    }
+}
@@ -0,0 +1,260 @@
+use std::collections::HashMap;
+use std::fmt;
+use color_eyre::{Result, eyre::eyre};
+use serde::{Deserialize, Serialize};
+use crate::etf::ETF;
+use crate::utils;
+
+// A validated ISIN: two-letter country prefix, nine-character national code, check digit
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Isin(String);
+
+// Value of an ISIN character in the check digit computation: 0-9, then A=10 to Z=35
+fn char_value(c: char) -> Option<u32> {
+    c.to_digit(36)
+}
+
+// Luhn check digit over the digits of the first eleven characters
+fn check_digit(body: &str) -> Option<u32> {
+    let digits: String = body.chars()
+        .map(|c| char_value(c).map(|value| value.to_string()))
+        .collect::<Option<String>>()?;
+
+    let sum: u32 = digits.chars().rev().enumerate()
+        .map(|(i, c)| {
+            let digit = c.to_digit(10).unwrap_or_default();
+            // The rightmost digit sits next to the check digit, so it is doubled
+            if i % 2 == 0 {
+                let doubled = digit * 2;
+                doubled / 10 + doubled % 10
+            } else {
+                digit
+            }
+        })
+        .sum();
+    Some((10 - sum % 10) % 10)
+}
+
+impl Isin {
+    pub fn parse(text: &str) -> Result<Self> {
+        let isin = text.trim().to_uppercase();
+        // Checked first, so the lengths and slices below count characters
+        if !isin.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(eyre!("ISIN {:?} has characters other than letters and digits", text));
+        }
+        if isin.len() != 12 {
+            return Err(eyre!("ISIN {:?} has {} characters instead of 12", text, isin.len()));
+        }
+        if !isin[..2].chars().all(|c| c.is_ascii_uppercase()) {
+            return Err(eyre!("ISIN {:?} does not start with a country code", text));
+        }
+        let expected = check_digit(&isin[..11]).ok_or_else(|| eyre!("Invalid ISIN {:?}", text))?;
+        let actual = isin[11..].chars().next().and_then(|c| c.to_digit(10));
+        if actual != Some(expected) {
+            return Err(eyre!("ISIN {:?} has check digit {} instead of {}", text, &isin[11..], expected));
+        }
+        Ok(Self(isin))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    // Country of issue prefix, "XS" for international securities cleared through Euroclear/Clearstream
+    pub fn country_code(&self) -> &str {
+        &self.0[..2]
+    }
+
+    pub fn country_name(&self) -> Option<&'static str> {
+        country_name(self.country_code())
+    }
+
+    // The nine characters between the country code and the check digit
+    pub fn national_code(&self) -> &str {
+        &self.0[2..11]
+    }
+
+    // US and Canadian ISINs embed the CUSIP
+    pub fn cusip(&self) -> Option<&str> {
+        matches!(self.country_code(), "US" | "CA").then(|| self.national_code())
+    }
+
+    // UK and Irish ISINs embed the SEDOL, padded with two zeros
+    pub fn sedol(&self) -> Option<&str> {
+        let national = self.national_code();
+        (matches!(self.country_code(), "GB" | "IE" | "IM" | "JE" | "GG") && national.starts_with("00"))
+            .then(|| &national[2..])
+            .filter(|sedol| sedol_is_valid(sedol))
+    }
+
+    // German ISINs embed the WKN, padded with three zeros
+    pub fn wkn(&self) -> Option<&str> {
+        let national = self.national_code();
+        (self.country_code() == "DE" && national.starts_with("000")).then(|| &national[3..])
+    }
+}
+
+impl fmt::Display for Isin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+pub fn is_valid_isin(text: &str) -> bool {
+    Isin::parse(text).is_ok()
+}
+
+// SEDOL check digit: weights 1, 3, 1, 7, 3, 9 over the first six characters
+fn sedol_is_valid(sedol: &str) -> bool {
+    if sedol.len() != 7 {
+        return false;
+    }
+    let values: Option<Vec<u32>> = sedol.chars().map(char_value).collect();
+    let Some(values) = values else { return false };
+    let sum: u32 = values.iter().zip([1, 3, 1, 7, 3, 9]).map(|(value, weight)| value * weight).sum();
+    values[6] == (10 - sum % 10) % 10
+}
+
+const COUNTRIES: [(&str, &str); 30] = [
+    ("AT", "Austria"),
+    ("AU", "Australia"),
+    ("BE", "Belgium"),
+    ("CA", "Canada"),
+    ("CH", "Switzerland"),
+    ("CN", "China"),
+    ("DE", "Germany"),
+    ("DK", "Denmark"),
+    ("ES", "Spain"),
+    ("EU", "European Union"),
+    ("FI", "Finland"),
+    ("FR", "France"),
+    ("GB", "United Kingdom"),
+    ("GG", "Guernsey"),
+    ("HK", "Hong Kong"),
+    ("IE", "Ireland"),
+    ("IM", "Isle of Man"),
+    ("IN", "India"),
+    ("IT", "Italy"),
+    ("JE", "Jersey"),
+    ("JP", "Japan"),
+    ("KR", "South Korea"),
+    ("LU", "Luxembourg"),
+    ("NL", "Netherlands"),
+    ("NO", "Norway"),
+    ("SE", "Sweden"),
+    ("TW", "Taiwan"),
+    ("US", "United States"),
+    ("XS", "International (Euroclear/Clearstream)"),
+    ("ZA", "South Africa"),
+];
+
+pub fn country_name(code: &str) -> Option<&'static str> {
+    COUNTRIES.iter().find(|(prefix, _)| *prefix == code).map(|(_, name)| *name)
+}
+
+// Identifiers of a fund besides its ISIN
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Identifiers {
+    pub ticker: Option<String>,    // Exchange ticker of the main listing, e.g. "IVAI"
+    pub bloomberg: Option<String>, // Bloomberg code, e.g. "IVAI LN"
+    pub reuters: Option<String>,   // Reuters instrument code, e.g. "IVAI.L"
+    pub wkn: Option<String>,
+    pub sedol: Option<String>,
+    pub cusip: Option<String>,
+}
+
+impl Identifiers {
+    // Codes published by the issuer, completed with the ones embedded in the ISIN
+    pub fn new(isin: &str, published: Identifiers) -> Self {
+        let isin = Isin::parse(isin).ok();
+        let embedded = |code: Option<&str>| code.map(str::to_string);
+        let ticker = published.ticker
+            .or_else(|| published.bloomberg.as_deref().and_then(|code| code.split_whitespace().next()).map(str::to_string));
+
+        Self {
+            ticker,
+            wkn: published.wkn.or_else(|| embedded(isin.as_ref().and_then(Isin::wkn))),
+            sedol: published.sedol.or_else(|| embedded(isin.as_ref().and_then(Isin::sedol))),
+            cusip: published.cusip.or_else(|| embedded(isin.as_ref().and_then(Isin::cusip))),
+            ..published
+        }
+    }
+}
+
+// Problems with the identifiers of a list of funds: malformed ISINs, the same ISIN listed twice
+// and published codes contradicting the ones embedded in the ISIN
+pub fn check_etfs(etfs: &[ETF]) -> Vec<String> {
+    let mut issues = Vec::new();
+    let mut seen: HashMap<&str, &str> = HashMap::new();
+
+    for etf in etfs {
+        match Isin::parse(&etf.isin) {
+            Err(e) => issues.push(format!("{}: {}", etf.name, e)),
+            Ok(isin) => {
+                if let (Some(published), Some(embedded)) = (&etf.identifiers.wkn, isin.wkn()) {
+                    if published != embedded {
+                        issues.push(format!("{}: WKN {} does not match ISIN {}", etf.name, published, isin));
+                    }
+                }
+            }
+        }
+        if let Some(other) = seen.insert(&etf.isin, &etf.name) {
+            issues.push(format!("{}: ISIN {} is also used by {}", etf.name, etf.isin, other));
+        }
+    }
+    issues
+}
+
+// Holdings of a fund with a malformed ISIN. Holdings without an ISIN are fine.
+pub fn check_holdings(etf: &ETF) -> Vec<String> {
+    etf.holdings.iter()
+        .filter_map(|holding| {
+            let isin = utils::non_empty(holding.isin.as_deref()?)?;
+            Isin::parse(&isin).err().map(|e| format!("{} holding {}: {}", etf.isin, holding.name, e))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_digit_of_known_isins() {
+        assert_eq!(check_digit("IE00B4L5Y98"), Some(3));
+        assert_eq!(check_digit("US037833100"), Some(5));
+        assert_eq!(check_digit("DE000A1E0HR"), Some(8));
+        assert_eq!(check_digit("IE00B4L5Y9Ä"), None);
+    }
+
+    #[test]
+    fn parses_valid_isins() {
+        let isin = Isin::parse(" ie00b4l5y983 ").unwrap();
+        assert_eq!(isin.as_str(), "IE00B4L5Y983");
+        assert_eq!(isin.country_code(), "IE");
+        assert!(is_valid_isin("US0378331005"));
+        assert_eq!(Isin::parse("US0378331005").unwrap().cusip(), Some("037833100"));
+    }
+
+    #[test]
+    fn rejects_a_bad_check_digit() {
+        assert!(Isin::parse("IE00B4L5Y984").is_err());
+        assert!(Isin::parse("US0378331006").is_err());
+    }
+
+    #[test]
+    fn rejects_a_wrong_length() {
+        assert!(Isin::parse("").is_err());
+        assert!(Isin::parse("IE00B4L5Y98").is_err());
+        assert!(Isin::parse("IE00B4L5Y9833").is_err());
+    }
+
+    #[test]
+    fn rejects_non_ascii_input() {
+        // 12 bytes, but "Ä" spans the boundary of the country code slice
+        assert_eq!("AÄBCDEFGHIJ".len(), 12);
+        assert!(Isin::parse("AÄBCDEFGHIJ").is_err());
+        assert!(Isin::parse("IE00B4L5Y98Ä").is_err());
+        assert!(!is_valid_isin("ÄÄÄÄÄÄ"));
+    }
+}
@@ -1,88 +1,119 @@
-use color_eyre::Result;
-use crate::etf::ETF;
+use color_eyre::{Result, eyre::eyre};
+use crate::dates;
+use crate::definition;
+use crate::etf::{ETF, Holding};
+use crate::http;
+use crate::provider::{Capabilities, HoldingsStatus, IssuerProvider};
 use crate::utils;
+use serde_json::Value;
 
 const ISSUER: &str = "Invesco";
 
 pub fn get_invesco_etfs() -> Result<Vec<ETF>> {
-    let file_path = "data/invesco_etfs.xlsx";
+    definition::builtin(definition::INVESCO).load_etfs()
+}
 
-    if !std::path::Path::new(file_path).exists() {
-        panic!("File does not exist at: {}", file_path);
+// First of `keys` present in a holding, as text
+fn text_field(holding: &Value, keys: &[&str]) -> Option<String> {
+    keys.iter()
+        .filter_map(|key| holding.get(*key))
+        .find_map(|value| match value {
+            Value::String(text) => utils::non_empty(text),
+            Value::Number(number) => Some(number.to_string()),
+            _ => None,
+        })
+}
+
+// First of `keys` present in a holding, as a number. Numbers are sometimes sent as text.
+fn number_field(holding: &Value, keys: &[&str]) -> Option<f64> {
+    keys.iter()
+        .filter_map(|key| holding.get(*key))
+        .find_map(|value| match value {
+            Value::Number(number) => number.as_f64(),
+            Value::String(text) => utils::parse_number(text),
+            _ => None,
+        })
+}
+
+fn holding_from_json(holding: &Value) -> Option<Holding> {
+    let weight = number_field(holding, &["weight", "percentageOfTotalNetAssets"])?;
+    let isin = text_field(holding, &["isin"]);
+    let name = text_field(holding, &["name", "securityName", "description"])
+        .or_else(|| isin.clone())
+        .unwrap_or_default();
+
+    Some(Holding {
+        name,
+        isin,
+        ticker: text_field(holding, &["ticker", "tickerSymbol"]),
+        sector: text_field(holding, &["sector", "gicsSector", "industry"]),
+        country: text_field(holding, &["country", "countryName"]),
+        currency: text_field(holding, &["currency", "currencyCode"]),
+        asset_type: text_field(holding, &["assetType", "securityType", "assetClass"]),
+        exchange: text_field(holding, &["exchange"]),
+        market_value: number_field(holding, &["marketValue", "marketValueBase"]),
+        shares: number_field(holding, &["shares", "nominal", "quantity"]),
+        weight,
+    })
+}
+
+// Load holdings for Invesco ETFs
+pub fn load_invesco_holdings(etf: &mut ETF) -> Result<HoldingsStatus> {
+    // For Invesco ETFs, we don't need to add the 'I' prefix
+    let url = format!(
+        "https://dng-api.invesco.com/cache/v1/accounts/en_GB/shareclasses/{}/holdings/index?idType=isin",
+        etf.isin
+    );
+
+    utils::log(&format!("Fetching holdings from URL: {}", url));
+
+    let response = http::client().get(&url)?;
+
+    if !response.is_success() {
+        return Err(eyre!("Failed to fetch holdings for {}: HTTP status {}", etf.isin, response.status));
     }
 
-    let raw_data = utils::read_xlsx(file_path)?;
-    
-    // Find the header row that contains "Bloomberg"
-    let header_row_index = raw_data.iter()
-        .position(|row| row.iter().any(|cell| cell == "Bloomberg"))
-        .unwrap_or(0);
-
-    // Process data starting from the row after headers
-    let etfs: Vec<ETF> = raw_data.iter()
-        .skip(header_row_index + 1) // Skip header row
-        .filter_map(|row| {
-            // Skip empty rows or rows that don't look like ETF data
-            if row.len() < 8 || row[2].is_empty() || row[7].is_empty() {
-                return None;
+    let json: Value = response.json()?;
+
+    // Date of the holdings, under the first of these keys the response has
+    etf.holdings_as_of = ["effectiveDate", "asOfDate", "date"].iter()
+        .find_map(|key| json.get(key).and_then(Value::as_str))
+        .and_then(dates::parse_date);
+
+    // Extract holdings from the JSON
+    if let Some(holdings) = json.get("holdings").and_then(|h| h.as_array()) {
+        etf.holdings.clear(); // Clear existing holdings
+
+        for holding in holdings {
+            if let Some(holding) = holding_from_json(holding) {
+                etf.holdings.push(holding);
             }
+        }
 
-            // Parse TER/OCF (column 12)
-            let ter = row.get(12)
-                .and_then(|s| s.trim().replace('%', "").replace(',', "").parse().ok())
-                .unwrap_or(0.0);
-
-            // Parse AUM (column 23)
-            let aum = row.get(23)
-                .map(|s| s.to_string())
-                .unwrap_or_default();
-
-            // Get distribution type from the column 28
-            let distribution_policy = match row.get(28).map(|s| s.as_str()) {
-                Some("Reinvested") => "Accumulating".to_string(),
-                Some("Distributed") => "Distributing".to_string(),
-                _ => "Unknown".to_string(),
-            };
-
-            // let row_0 = row[0].clone();
-            // let row_1 = row[1].clone();
-            // let row_2 = row[2].clone();
-            // let row_3 = row[3].clone();
-            // let row_4 = row[4].clone();
-            // let row_5 = row[5].clone();
-            // let row_6 = row[6].clone();
-            // let row_7 = row[7].clone();
-            // let row_8 = row[8].clone();
-            // let row_9 = row[9].clone();
-            // let row_10 = row[10].clone();
-            // let row_11 = row[11].clone();
-            // let row_12 = row[12].clone();
-            // let row_13 = row[13].clone();
-            // let row_14 = row[14].clone();
-            // let row_15 = row[15].clone();
-            // let row_16 = row[16].clone();
-            // let row_17 = row[17].clone();
-            
-            
-
-            Some(ETF {
-                name: row[1].clone(), // Fund Name (Equity ETFs column)
-                isin: row[6].clone(), // ISIN
-                asset_class: "Equity".to_string(), // All ETFs in the file are equity ETFs
-                ter,
-                currency: row[12].clone(), // Base currency
-                aum,
-                currency_exposure: row[15].clone(), // Index currency
-                distribution_policy,
-                launch_date: row[16].clone(), // Date of issue
-                performance_1y: None, // Not available in the file
-                performance_ytd: None, // Not available in the file
-                holdings: Vec::new(), // Initialize with empty holdings
-                issuer: ISSUER.to_string(),
-                product_url: None, // Initialize with None
-            })
-        })
-        .collect();
+        utils::log(&format!("Successfully loaded {} holdings", etf.holdings.len()));
+    } else {
+        utils::log(&format!("No holdings found in the response for {}", etf.isin));
+    }
+
+    Ok(HoldingsStatus::Loaded(etf.holdings.len()))
+}
+
+pub struct InvescoProvider;
+
+impl IssuerProvider for InvescoProvider {
+    fn name(&self) -> &str {
+        ISSUER
+    }
 
-    Ok(etfs)
-} 
\ No newline at end of file
+    fn capabilities(&self) -> Capabilities {
+        Capabilities { list_etfs: true, holdings: true, price_history: false }
+    }
+
+    fn list_etfs(&self) -> Result<Vec<ETF>> {
+        get_invesco_etfs()
+    }
+
+    fn load_holdings(&self, etf: &mut ETF) -> Result<HoldingsStatus> {
+        load_invesco_holdings(etf)
+    }
+}
@@ -1,23 +1,27 @@
-use color_eyre::Result;
-use crate::etf::ETF;
+use std::io::Cursor;
+use color_eyre::{Result, eyre::eyre};
+use crate::dates;
+use crate::definition;
+use crate::etf::{ETF, Holding};
+use crate::http;
+use crate::prices::{self, PriceSeries};
+use crate::provider::{Capabilities, HoldingsStatus, IssuerProvider};
+use crate::schema::{fields, Schema};
 use crate::utils;
-use reqwest::blocking::Client;
 use serde_json::Value;
 
 const ISSUER: &str = "iShares";
+const BASE_URL: &str = "https://www.ishares.com";
 
 fn fetch_ishares_product_urls() -> Result<Vec<(String, String)>> {
     let url = "https://www.ishares.com/us/product-screener/product-screener-v3.1.jsn?dcrPath=/templatedata/config/product-screener-v3/data/en/us-ishares/ishares-product-screener-backend-config&siteEntryPassthrough=true";
     
-    println!("Fetching product URLs from iShares API...");
+    utils::log("Fetching product URLs from iShares API...");
     
-    let client = Client::new();
-    let response = client.get(url)
-        .header("User-Agent", "Mozilla/5.0")
-        .send()?;
+    let response = http::client().get(url)?;
     
-    if !response.status().is_success() {
-        return Err(color_eyre::eyre::eyre!("Failed to fetch product URLs: HTTP status {}", response.status()));
+    if !response.is_success() {
+        return Err(eyre!("Failed to fetch product URLs: HTTP status {}", response.status));
     }
     
     let json: Value = response.json()?;
@@ -35,83 +39,240 @@ fn fetch_ishares_product_urls() -> Result<Vec<(String, String)>> {
         }
     }
     
-    println!("Found {} product URLs in the API response", product_urls.len());
+    utils::log(&format!("Found {} product URLs in the API response", product_urls.len()));
     Ok(product_urls)
 }
 
 pub fn get_ishares_etfs() -> Result<Vec<ETF>> {
-    let file_path = "data/iShares-UnitedKingdom.xls";
+    let mut etfs = definition::builtin(definition::ISHARES).load_etfs()?;
 
-    if !std::path::Path::new(file_path).exists() {
-        panic!("File does not exist at: {}", file_path);
+    // Fetch product URLs from the API. They are only needed for holdings, so a failure leaves them empty.
+    let product_urls = fetch_ishares_product_urls().unwrap_or_else(|e| {
+        utils::log(&format!("Failed to fetch iShares product URLs: {:#}", e));
+        Vec::new()
+    });
+    let mut url_map: std::collections::HashMap<String, String> = product_urls.into_iter().collect();
+    for etf in &mut etfs {
+        etf.product_url = url_map.remove(&etf.isin);
     }
 
-    let raw_data = utils::read_xlsx(file_path)?;
-    
-    // Find the header row that contains "Fund Name"
-    let header_row_index = raw_data.iter()
-        .position(|row| row.get(1).map_or(false, |cell| cell == "Fund Name"))
-        .unwrap_or(0);
+    // Print statistics about URL matching
+    let matched_count = etfs.iter().filter(|etf| etf.product_url.is_some()).count();
+    utils::log(&format!("Matched product URLs for {}/{} iShares ETFs", matched_count, etfs.len()));
 
-    // Fetch product URLs from the API
-    let product_urls = fetch_ishares_product_urls()?;
-    let mut url_map: std::collections::HashMap<String, String> = product_urls.into_iter().collect();
+    Ok(etfs)
+}
+
+// Turn a link found on an iShares page into an absolute URL
+fn absolute_url(link: &str) -> String {
+    if link.starts_with("http") {
+        link.to_string()
+    } else {
+        format!("{}/{}", BASE_URL, link.trim_start_matches('/'))
+    }
+}
+
+// Find a download link on a product page: the first link of one of `file_types`, in order,
+// whose file name ends with `suffix`.
+// Links look like `/uk/.../1506575576011.ajax?fileType=csv&amp;fileName=CSPX_holdings&amp;dataType=fund`
+fn find_download_url(page: &str, file_types: &[&str], suffix: &str) -> Option<String> {
+    for file_type in file_types {
+        let needle = format!(".ajax?fileType={}", file_type);
+        for (pos, _) in page.match_indices(&needle) {
+            let start = page[..pos].rfind(['"', '\'']).map_or(0, |i| i + 1);
+            let end = page[pos..].find(['"', '\'']).map_or(page.len(), |i| pos + i);
+            let link = page[start..end].replace("&amp;", "&");
 
-    // Process data starting from the row after headers
-    let mut etfs: Vec<ETF> = raw_data.iter()
-        .skip(header_row_index + 2) // Skip both header rows
-        .filter_map(|row| {
-            // Skip empty rows or rows that don't look like ETF data
-            if row.len() < 4 || row[1].is_empty() || row[3].is_empty() || 
-               row[1].contains("TER / OCF") || row[1].contains("AUM") || 
-               row[1].contains("As Of") {
-                return None;
+            let file_name = link.split(['?', '&']).find_map(|part| part.strip_prefix("fileName="));
+            if file_name.is_some_and(|name| name.ends_with(suffix)) && link.contains("dataType=fund") {
+                return Some(absolute_url(&link));
             }
+        }
+    }
+    None
+}
+
+fn find_holdings_download_url(page: &str) -> Option<String> {
+    find_download_url(page, &["csv", "xls"], "_holdings")
+}
+
+// The "fund" workbook has the NAV history on its "Historical" sheet
+fn find_fund_data_download_url(page: &str) -> Option<String> {
+    find_download_url(page, &["xls"], "_fund")
+}
 
-            // Parse TER (column 26)
-            let ter = row.get(26)
-                .and_then(|s| s.trim().replace('%', "").replace(',', "").parse().ok())
-                .unwrap_or(0.0);
-
-            // Parse AUM (column 27)
-            let aum = row.get(27)
-                .map(|s| s.to_string())
-                .unwrap_or_default();
-
-            // Get distribution type from the data
-            let distribution_policy = if row[1].contains("(Dist)") {
-                "Distributing".to_string()
-            } else if row[1].contains("(Acc)") {
-                "Accumulating".to_string()
-            } else {
-                "Unknown".to_string()
-            };
-
-            let isin = row[3].clone();
-            let product_url = url_map.remove(&isin);
-
-            Some(ETF {
-                name: row[1].clone(), // Fund Name
-                isin, // ISIN
-                asset_class: row[2].clone(), // Fund type
-                ter,
-                currency: row[4].clone(), // Share Class Currency
-                aum,
-                currency_exposure: row[5].clone(), // Share Class
-                distribution_policy,
-                launch_date: String::new(), // TODO: Find the correct column
-                performance_1y: None, // TODO: Find the correct column for 1Y performance
-                performance_ytd: None, // TODO: Find the correct column for YTD performance
-                holdings: Vec::new(), // Initialize with empty holdings
-                issuer: ISSUER.to_string(),
-                product_url,
-            })
-        })
+fn fetch_product_page(etf: &ETF) -> Result<String> {
+    let product_url = etf.product_url.as_deref()
+        .ok_or_else(|| eyre!("No product page URL known for {}", etf.isin))?;
+    let product_url = absolute_url(product_url);
+
+    utils::log(&format!("Fetching product page: {}", product_url));
+
+    let response = http::client().get(&product_url)?;
+
+    if !response.is_success() {
+        return Err(eyre!("Failed to fetch product page for {}: HTTP status {}", etf.isin, response.status));
+    }
+    Ok(response.text())
+}
+
+fn holdings_schema() -> Schema {
+    Schema::new(ISSUER)
+        .optional(fields::TICKER, &["Ticker", "Issuer Ticker"])
+        .required(fields::NAME, &["Name"])
+        .optional(fields::ISIN, &["ISIN"])
+        .optional(fields::SECTOR, &["Sector"])
+        .optional(fields::COUNTRY, &["Location", "Location of Risk"])
+        .optional(fields::CURRENCY, &["Market Currency", "Currency"])
+        .optional(fields::ASSET_TYPE, &["Asset Class"])
+        .optional(fields::EXCHANGE, &["Exchange"])
+        .optional(fields::MARKET_VALUE, &["Market Value"])
+        .optional(fields::SHARES, &["Shares", "Nominal", "Quantity", "Par Value"])
+        .required(fields::WEIGHT, &["Weight (%)"])
+}
+
+// Parse the rows of an iShares holdings file into the holdings of `etf`.
+// The file starts with preamble rows ("Fund Holdings as of", "Inception Date", "Shares Outstanding", ...)
+// followed by the header row, the holdings and a trailing disclaimer.
+fn parse_holdings_rows(etf: &mut ETF, rows: &[Vec<String>]) -> Result<()> {
+    let (header_row_index, columns) = holdings_schema().find_header(rows)?;
+
+    let as_of = rows[..header_row_index].iter()
+        .find(|row| row.first().is_some_and(|cell| cell.eq_ignore_ascii_case("Fund Holdings as of")))
+        .and_then(|row| row.get(1))
+        .and_then(|cell| dates::parse_date(cell));
+
+    let weight_col = columns.index(fields::WEIGHT).unwrap_or_default();
+    etf.holdings = rows.iter()
+        .skip(header_row_index + 1)
+        // Holdings end at the first blank line or the disclaimer
+        .take_while(|row| row.len() > weight_col)
+        .filter_map(|row| Holding::from_columns(row, &columns))
         .collect();
+    etf.holdings_as_of = as_of;
 
-    // Print statistics about URL matching
-    let matched_count = etfs.iter().filter(|etf| etf.product_url.is_some()).count();
-    println!("Matched product URLs for {}/{} iShares ETFs", matched_count, etfs.len());
+    Ok(())
+}
 
-    Ok(etfs)
-} 
\ No newline at end of file
+// Load holdings for iShares ETFs from the download linked on the product page
+pub fn load_ishares_holdings(etf: &mut ETF) -> Result<HoldingsStatus> {
+    let page = fetch_product_page(etf)?;
+    let download_url = find_holdings_download_url(&page)
+        .ok_or_else(|| eyre!("No holdings download found on the product page of {}", etf.isin))?;
+
+    utils::log(&format!("Fetching holdings from URL: {}", download_url));
+
+    let response = http::client().get(&download_url)?;
+
+    if !response.is_success() {
+        return Err(eyre!("Failed to fetch holdings for {}: HTTP status {}", etf.isin, response.status));
+    }
+
+    let body = response.body;
+    let rows = if download_url.contains("fileType=csv") {
+        utils::read_csv(&String::from_utf8_lossy(&body))?
+    } else {
+        utils::read_xml_spreadsheet(Cursor::new(body), None)?
+    };
+
+    parse_holdings_rows(etf, &rows)?;
+
+    utils::log(&format!("Successfully loaded {} holdings", etf.holdings.len()));
+
+    Ok(HoldingsStatus::Loaded(etf.holdings.len()))
+}
+
+// Load the daily NAV history from the fund data workbook linked on the product page
+pub fn load_ishares_price_history(etf: &ETF) -> Result<PriceSeries> {
+    let page = fetch_product_page(etf)?;
+    let download_url = find_fund_data_download_url(&page)
+        .ok_or_else(|| eyre!("No fund data download found on the product page of {}", etf.isin))?;
+
+    utils::log(&format!("Fetching NAV history from URL: {}", download_url));
+
+    let response = http::client().get(&download_url)?;
+
+    if !response.is_success() {
+        return Err(eyre!("Failed to fetch NAV history for {}: HTTP status {}", etf.isin, response.status));
+    }
+
+    let rows = utils::read_xml_spreadsheet(Cursor::new(response.body), Some("Historical"))?;
+    prices::parse_price_rows(&rows, Some(&etf.isin), Some(&etf.currency))?
+        .into_iter()
+        .find(|series| series.isin == etf.isin)
+        .ok_or_else(|| eyre!("NAV history of {} has no prices", etf.isin))
+}
+
+pub struct ISharesProvider;
+
+impl IssuerProvider for ISharesProvider {
+    fn name(&self) -> &str {
+        ISSUER
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities { list_etfs: true, holdings: true, price_history: true }
+    }
+
+    fn list_etfs(&self) -> Result<Vec<ETF>> {
+        get_ishares_etfs()
+    }
+
+    fn load_holdings(&self, etf: &mut ETF) -> Result<HoldingsStatus> {
+        load_ishares_holdings(etf)
+    }
+
+    fn load_price_history(&self, etf: &ETF) -> Result<Option<PriceSeries>> {
+        load_ishares_price_history(etf).map(Some)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    const PAGE: &str = r#"
+        <a class="icon-xls-export" href="/uk/individual/en/products/253743/ishares-sp-500-b-ucits-etf-acc-fund/1506575576011.ajax?fileType=xls&amp;fileName=iShares-Core-SP-500-UCITS-ETF-USD-Acc_fund&amp;dataType=fund">Data</a>
+        <a href='/uk/individual/en/products/253743/ishares-sp-500-b-ucits-etf-acc-fund/1506575576011.ajax?fileType=csv&amp;fileName=CSPX_holdings&amp;dataType=fund'>Holdings</a>
+        <a href="https://www.ishares.com/uk/individual/en/products/253743/1506575576011.ajax?fileType=csv&amp;fileName=CSPX_holdings&amp;dataType=index">Index</a>
+    "#;
+
+    #[test]
+    fn finds_download_links_on_the_product_page() {
+        assert_eq!(
+            find_holdings_download_url(PAGE).as_deref(),
+            Some("https://www.ishares.com/uk/individual/en/products/253743/ishares-sp-500-b-ucits-etf-acc-fund/1506575576011.ajax?fileType=csv&fileName=CSPX_holdings&dataType=fund"),
+        );
+        assert_eq!(
+            find_fund_data_download_url(PAGE).as_deref(),
+            Some("https://www.ishares.com/uk/individual/en/products/253743/ishares-sp-500-b-ucits-etf-acc-fund/1506575576011.ajax?fileType=xls&fileName=iShares-Core-SP-500-UCITS-ETF-USD-Acc_fund&dataType=fund"),
+        );
+        assert_eq!(find_holdings_download_url("<html>No downloads</html>"), None);
+    }
+
+    #[test]
+    fn reads_holdings_up_to_the_disclaimer() {
+        let rows = utils::read_csv(concat!(
+            "\u{feff}iShares Core S&P 500 UCITS ETF\n",
+            "Fund Holdings as of,\"Mar 27, 2025\"\n",
+            "Inception Date,\"May 19, 2010\"\n",
+            "\n",
+            "Ticker,Name,Sector,Asset Class,Market Value,Weight (%),ISIN\n",
+            "AAPL,APPLE INC,Information Technology,Equity,\"7,000,000.00\",6.95,US0378331005\n",
+            "USD,USD CASH,Cash and/or Derivatives,Cash,\"100,000.00\",0.10,\n",
+            "\n",
+            "\"The content contained herein is owned or licensed by BlackRock\"\n",
+            "XXX,NOT A HOLDING,,,,1.00,\n",
+        )).unwrap();
+
+        let mut etf = ETF::for_test("IE00B5BMR087", Vec::new());
+        parse_holdings_rows(&mut etf, &rows).unwrap();
+
+        let names: Vec<&str> = etf.holdings.iter().map(|holding| holding.name.as_str()).collect();
+        assert_eq!(names, ["APPLE INC", "USD CASH"]);
+        assert_eq!(etf.holdings[0].market_value, Some(7_000_000.0));
+        assert_eq!(etf.holdings[1].isin, None);
+        assert_eq!(etf.holdings_as_of, NaiveDate::from_ymd_opt(2025, 3, 27));
+    }
+}
@@ -1,6 +1,28 @@
+pub mod bulk;
+pub mod cache;
+pub mod catalog_diff;
+pub mod classification;
+pub mod dates;
+pub mod definition;
 pub mod etf;
+pub mod export;
+pub mod fx;
+pub mod holdings_diff;
+pub mod http;
+pub mod identifiers;
 pub mod invesco;
 pub mod ishares;
+pub mod listing;
+pub mod money;
+pub mod overlap;
+pub mod performance;
+pub mod portfolio;
+pub mod prices;
+pub mod provider;
+pub mod replay;
+pub mod risk;
+pub mod schema;
+pub mod store;
 pub mod xtrackers;
 pub mod utils;
-pub mod ui; 
\ No newline at end of file
+pub mod ui;
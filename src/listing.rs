@@ -0,0 +1,149 @@
+use std::fmt;
+use serde::{Deserialize, Serialize};
+use crate::money;
+use crate::utils;
+
+// A line of an ETF on an exchange. The same ISIN trades under a different ticker, and often
+// in a different currency, on each exchange.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Listing {
+    pub exchange: Option<String>,         // "LSE", "Xetra", ...
+    pub ticker: Option<String>,
+    pub trading_currency: Option<String>,
+    pub bloomberg: Option<String>,        // "IVAI LN"
+    pub reuters: Option<String>,          // "IVAI.L"
+}
+
+// Bloomberg exchange codes and Reuters suffixes of the exchanges European ETFs list on
+const EXCHANGES: [(&str, &str, &str); 13] = [
+    ("LN", "L", "LSE"),
+    ("GY", "DE", "Xetra"),
+    ("GR", "F", "Frankfurt"),
+    ("GS", "SG", "Stuttgart"),
+    ("IM", "MI", "Borsa Italiana"),
+    // Bloomberg codes SIX lines as SW (Swiss composite) or SE (SIX Swiss Exchange); Reuters
+    // uses S for both
+    ("SW", "S", "SIX"),
+    ("SE", "S", "SIX"),
+    ("NA", "AS", "Euronext Amsterdam"),
+    ("FP", "PA", "Euronext Paris"),
+    ("BB", "BR", "Euronext Brussels"),
+    ("SM", "MC", "BME"),
+    ("IX", "CHI", "Cboe Europe"),
+    ("US", "N", "NYSE"),
+];
+
+fn exchange_from_bloomberg(code: &str) -> Option<&'static str> {
+    let suffix = code.split_whitespace().nth(1)?;
+    EXCHANGES.iter().find(|(bloomberg, _, _)| suffix.eq_ignore_ascii_case(bloomberg)).map(|(_, _, name)| *name)
+}
+
+fn exchange_from_reuters(code: &str) -> Option<&'static str> {
+    let (_, suffix) = code.rsplit_once('.')?;
+    EXCHANGES.iter().find(|(_, reuters, _)| suffix.eq_ignore_ascii_case(reuters)).map(|(_, _, name)| *name)
+}
+
+impl Listing {
+    // Listing from the codes of a spreadsheet row. The exchange and ticker are derived from the
+    // Bloomberg or Reuters code when the sheet does not give them. None if nothing identifies it.
+    pub fn from_codes(
+        exchange: Option<&str>,
+        ticker: Option<&str>,
+        trading_currency: Option<&str>,
+        bloomberg: Option<&str>,
+        reuters: Option<&str>,
+    ) -> Option<Self> {
+        let text = |value: Option<&str>| value.and_then(utils::non_empty);
+        let bloomberg = text(bloomberg);
+        let reuters = text(reuters);
+
+        let exchange = text(exchange).or_else(|| {
+            bloomberg.as_deref().and_then(exchange_from_bloomberg)
+                .or_else(|| reuters.as_deref().and_then(exchange_from_reuters))
+                .map(str::to_string)
+        });
+        let ticker = text(ticker).or_else(|| {
+            bloomberg.as_deref().and_then(|code| code.split_whitespace().next()).map(str::to_string)
+        });
+
+        if ticker.is_none() && bloomberg.is_none() && reuters.is_none() {
+            return None;
+        }
+        Some(Self {
+            exchange,
+            ticker,
+            trading_currency: text(trading_currency).map(|currency| money::currency_code(&currency)),
+            bloomberg,
+            reuters,
+        })
+    }
+
+    // True if `code` is the ticker, Bloomberg or Reuters code of the listing, ignoring case
+    pub fn matches(&self, code: &str) -> bool {
+        let code = code.trim();
+        [&self.ticker, &self.bloomberg, &self.reuters].iter()
+            .any(|value| value.as_deref().is_some_and(|value| value.eq_ignore_ascii_case(code)))
+    }
+
+    pub fn is_on(&self, exchange: &str) -> bool {
+        self.exchange.as_deref().is_some_and(|name| name.eq_ignore_ascii_case(exchange))
+    }
+}
+
+// "IVAI (LSE, USD)"
+impl fmt::Display for Listing {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let code = self.ticker.as_deref().or(self.bloomberg.as_deref()).or(self.reuters.as_deref()).unwrap_or("?");
+        let details: Vec<&str> = [self.exchange.as_deref(), self.trading_currency.as_deref()].into_iter().flatten().collect();
+        if details.is_empty() {
+            write!(f, "{}", code)
+        } else {
+            write!(f, "{} ({})", code, details.join(", "))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn derives_the_exchange_and_ticker_from_codes() {
+        let listing = Listing::from_codes(None, None, Some("GBp"), Some("IVAI LN"), Some("IVAI.L")).unwrap();
+        assert_eq!(listing.exchange.as_deref(), Some("LSE"));
+        assert_eq!(listing.ticker.as_deref(), Some("IVAI"));
+        assert_eq!(listing.trading_currency.as_deref(), Some("GBp"));
+        assert_eq!(listing.to_string(), "IVAI (LSE, GBp)");
+
+        let swiss = |bloomberg: Option<&str>, reuters: Option<&str>| Listing::from_codes(None, None, None, bloomberg, reuters).unwrap().exchange;
+        assert_eq!(swiss(Some("CSSPX SW"), None).as_deref(), Some("SIX"));
+        assert_eq!(swiss(Some("CSSPX SE"), None).as_deref(), Some("SIX"));
+        assert_eq!(swiss(None, Some("CSSPX.S")).as_deref(), Some("SIX"));
+        assert_eq!(swiss(Some("CSSPX XX"), None), None);
+
+        // Only a Reuters code: the exchange is known, the ticker is not
+        let listing = Listing::from_codes(None, None, Some("eur"), None, Some("SXR8.DE")).unwrap();
+        assert_eq!((listing.exchange.as_deref(), listing.ticker.as_deref()), (Some("Xetra"), None));
+        assert_eq!(listing.trading_currency.as_deref(), Some("EUR"));
+        assert_eq!(listing.to_string(), "SXR8.DE (Xetra, EUR)");
+    }
+
+    #[test]
+    fn codes_given_by_the_sheet_win() {
+        let listing = Listing::from_codes(Some("London Stock Exchange"), Some("CSP1"), None, Some("CSPX LN"), None).unwrap();
+        assert_eq!(listing.exchange.as_deref(), Some("London Stock Exchange"));
+        assert_eq!(listing.ticker.as_deref(), Some("CSP1"));
+        assert!(Listing::from_codes(Some("LSE"), Some("-"), Some("USD"), None, Some("")).is_none());
+    }
+
+    #[test]
+    fn matches_any_code_of_the_listing() {
+        let listing = Listing::from_codes(None, Some("CSPX"), None, Some("CSPX LN"), Some("CSPX.L")).unwrap();
+        for code in ["CSPX", "cspx", " CSPX LN ", "cspx.l"] {
+            assert!(listing.matches(code), "{}", code);
+        }
+        assert!(!listing.matches("CSPX GY"));
+        assert!(!listing.matches(""));
+        assert!(listing.is_on("lse"));
+    }
+}
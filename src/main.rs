@@ -1,41 +1,801 @@
-use color_eyre::Result;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
+use std::time::Duration;
+use color_eyre::{Result, eyre::eyre};
 use crossterm::event::{self, Event, KeyCode};
 use ratatui::DefaultTerminal;
 
-mod etf;
-mod ui;
-mod utils;
-mod xtrackers;
-mod ishares;
-mod invesco;
+use etf_tool::bulk::{self, BulkOptions, BulkReport, CancelToken};
+use etf_tool::cache::{self, CacheConfig};
+use etf_tool::etf::ETF;
+use etf_tool::export;
+use etf_tool::catalog_diff::{self, CatalogDiffConfig};
+use etf_tool::fx::{FxTable, Reporting};
+use etf_tool::holdings_diff::{self, HoldingsDiff, WeightChange};
+use etf_tool::overlap::{self, Overlap};
+use etf_tool::portfolio::{self, Gap, LookThrough, Portfolio};
+use etf_tool::performance::Period;
+use etf_tool::prices::PriceStore;
+use etf_tool::risk::{self, RiskConfig, RiskMetrics};
+use etf_tool::provider::{CatalogLoad, IssuerReport, ProviderRegistry};
+use etf_tool::store::{Snapshot, Store};
+use etf_tool::ui::{self, Screen};
+use etf_tool::{dates, utils};
 
-use etf::ETF;
+const ISSUER_DEFINITIONS_DIR: &str = "issuers";
+const DEFAULT_FX_RATES: &str = "data/fx_rates.csv";
+const EXPORT_FILE: &str = "etf_export.csv";
+
+// Value of `--name VALUE` or `--name=VALUE` in `args`
+fn arg_value(args: &[String], name: &str) -> Option<String> {
+    args.iter().enumerate().find_map(|(i, arg)| {
+        if arg == name {
+            args.get(i + 1).cloned()
+        } else {
+            arg.strip_prefix(name)?.strip_prefix('=').map(str::to_string)
+        }
+    })
+}
+
+// Options followed by a value, skipped when collecting positional arguments
+const VALUE_OPTIONS: [&str; 9] = [
+    "--isin", "--currency", "--fx-rates", "--risk-free-rate", "--benchmark", "--from", "--to", "--top", "--aum-move",
+];
+
+// Arguments that are neither options nor option values
+fn positional_args(args: &[String]) -> Vec<String> {
+    let mut positional = Vec::new();
+    let mut skip_value = false;
+    for arg in args {
+        if std::mem::take(&mut skip_value) {
+            continue;
+        }
+        if arg.starts_with("--") {
+            skip_value = VALUE_OPTIONS.contains(&arg.as_str());
+        } else {
+            positional.push(arg.clone());
+        }
+    }
+    positional
+}
+
+// ISIN of a fund given by ISIN or ticker, looked up in the catalog when it is not an ISIN
+fn resolve_isin(registry: &ProviderRegistry, code: &str) -> Result<String> {
+    if etf_tool::identifiers::is_valid_isin(code) {
+        return Ok(code.to_uppercase());
+    }
+    registry.load_catalog().etfs.iter()
+        .find(|etf| etf.has_code(code))
+        .map(|etf| etf.isin.clone())
+        .ok_or_else(|| eyre!("No fund with ISIN or ticker {}", code))
+}
+
+const PRICES_USAGE: &str = "Usage: etf-tool prices import FILE... [--isin ISIN] [--currency CCY]
+       etf-tool prices fetch FUND...
+       etf-tool prices returns FUND [--currency CCY] [--fx-rates FILE]
+FUND is an ISIN or a ticker";
+
+// Price history commands, on the store in ETF_TOOL_PRICES_DIR
+fn prices_command(args: &[String]) -> Result<()> {
+    let positional = positional_args(args);
+    let mut store = PriceStore::from_env()?;
+    let (registry, _) = build_registry();
+
+    match positional.first().map(String::as_str) {
+        // `--currency` is the currency of the prices when the file has no currency column
+        Some("import") if positional.len() > 1 => {
+            let isin = arg_value(args, "--isin");
+            let currency = arg_value(args, "--currency");
+            for file in &positional[1..] {
+                let count = store.import_file(Path::new(file), isin.as_deref(), currency.as_deref())?;
+                println!("Imported {} prices from {}", count, file);
+            }
+            store.save()?;
+            println!("Saved price histories to {}", store.dir().display());
+        }
+        // A fund that fails does not stop the others, and what was fetched is saved
+        Some("fetch") if positional.len() > 1 => {
+            let catalog = registry.load_catalog();
+            let mut failed = Vec::new();
+            for code in &positional[1..] {
+                let Some(etf) = catalog.etfs.iter().find(|etf| etf.has_code(code)) else {
+                    println!("No fund with ISIN or ticker {}", code);
+                    failed.push(code.clone());
+                    continue;
+                };
+                match registry.load_price_history(etf) {
+                    Ok(Some(series)) => {
+                        println!("Fetched {} prices of {}", series.points.len(), etf.isin);
+                        store.add(series);
+                    }
+                    Ok(None) => println!("{} publishes no NAV history for {}", etf.issuer, etf.isin),
+                    Err(e) => {
+                        println!("Failed to fetch the prices of {}: {:#}", etf.isin, e);
+                        failed.push(code.clone());
+                    }
+                }
+            }
+            store.save()?;
+            if !failed.is_empty() {
+                return Err(eyre!("No prices fetched for {}", failed.join(", ")));
+            }
+        }
+        // `--currency` is the currency the returns are computed in
+        Some("returns") if positional.len() == 2 => {
+            let isin = resolve_isin(&registry, &positional[1])?;
+            let series = store.get(&isin)
+                .ok_or_else(|| eyre!("No prices of {} in {}", isin, store.dir().display()))?;
+            let series = match load_reporting(args)? {
+                Some(reporting) => series.in_currency(&reporting.rates, &reporting.currency)
+                    .ok_or_else(|| eyre!("Currency of the prices of {} is unknown", isin))?,
+                None => series.clone(),
+            };
+            let (Some(first), Some(last)) = (series.first_date(), series.last_date()) else {
+                return Err(eyre!("No prices of {} to compute returns from", isin));
+            };
+
+            println!(
+                "{} in {}: {} prices from {} to {}",
+                isin,
+                series.currency.as_deref().unwrap_or("unknown currency"),
+                series.points.len(),
+                first,
+                last,
+            );
+            println!("{:<16} {:>10} {:>12}", "Period", "Return", "Annualised");
+            for period in Period::ALL {
+                let Some(total) = series.period_return(period, None) else { continue };
+                let start = period.start(last).unwrap_or(first);
+                let annualised = series.annualised_return(start, last)
+                    .map_or(String::new(), |value| format!("{:.2}%", value));
+                println!("{:<16} {:>9.2}% {:>12}", period.to_string(), total, annualised);
+            }
+        }
+        _ => return Err(eyre!(PRICES_USAGE)),
+    }
+    Ok(())
+}
+
+// Reporting currency from `--currency` or ETF_TOOL_REPORTING_CURRENCY, with rates from
+// `--fx-rates`, ETF_TOOL_FX_RATES or data/fx_rates.csv
+fn load_reporting(args: &[String]) -> Result<Option<Reporting>> {
+    let Some(currency) = arg_value(args, "--currency").or_else(|| std::env::var("ETF_TOOL_REPORTING_CURRENCY").ok()) else {
+        return Ok(None);
+    };
+    let path = arg_value(args, "--fx-rates")
+        .or_else(|| std::env::var("ETF_TOOL_FX_RATES").ok())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_FX_RATES));
+    Ok(Some(Reporting::new(&currency, FxTable::from_file(&path)?)))
+}
+
+// Registry of the built-in issuers and of the ones defined in `issuers/*.toml`, with a report
+// when the definitions cannot be read
+fn build_registry() -> (ProviderRegistry, Option<IssuerReport>) {
+    let mut registry = ProviderRegistry::with_builtin();
+    let mut report = None;
+    if Path::new(ISSUER_DEFINITIONS_DIR).is_dir() {
+        if let Err(e) = registry.load_definitions(Path::new(ISSUER_DEFINITIONS_DIR)) {
+            report = Some(IssuerReport {
+                issuer: ISSUER_DEFINITIONS_DIR.to_string(),
+                etf_count: 0,
+                as_of: None,
+                issues: Vec::new(),
+                error: Some(format!("{:#}", e)),
+            });
+        }
+    }
+    (registry, report)
+}
+
+// Load every issuer and store the catalog as a new snapshot. With `--holdings`, the holdings
+// of every fund are loaded and stored too.
+fn refresh_command(args: &[String]) -> Result<()> {
+    let mut store = Store::from_env()?;
+    let (registry, definitions_report) = build_registry();
+    let catalog = registry.load_catalog();
+    let reports: Vec<IssuerReport> = definitions_report.into_iter().chain(catalog.reports).collect();
+    for report in &reports {
+        match &report.error {
+            None => println!("{}: {} funds", report.issuer, report.etf_count),
+            Some(error) => println!("{}: failed: {}", report.issuer, error),
+        }
+    }
+    let snapshot = store.save_catalog(&catalog.etfs, &reports)?;
+    println!("Saved catalog snapshot {} with {} funds", snapshot.id, snapshot.funds);
+    if store.previous_catalog(snapshot.id)?.is_some() {
+        let changes = catalog_diff::diff_snapshots(&store, None, Some(snapshot.id), &CatalogDiffConfig::default())?;
+        println!("Since the previous catalog: {} (see `etf-tool changes`)", changes.summary());
+    }
+
+    if args.iter().any(|arg| arg == "--holdings") {
+        let mut etfs = catalog.etfs;
+        let report = bulk::load_holdings(&registry, &mut etfs, &BulkOptions::default(), &CancelToken::new(), |progress| {
+            println!("[{}/{}] {}", progress.done, progress.total, progress.fund.isin);
+        });
+        println!("Holdings: {} loaded, {} failed, {} unsupported", report.loaded(), report.failures().count(), report.unsupported());
+        match store.save_holdings(&etfs)? {
+            Some(snapshot) => println!("Saved holdings snapshot {} with {} funds", snapshot.id, snapshot.funds),
+            None => println!("No holdings to save"),
+        }
+    }
+    Ok(())
+}
+
+fn snapshots_command() -> Result<()> {
+    let store = Store::from_env()?;
+    for snapshot in store.snapshots()? {
+        println!(
+            "{:>5}  {}  {:<8}  {} funds",
+            snapshot.id,
+            snapshot.taken_at.format("%Y-%m-%d %H:%M:%S UTC"),
+            format!("{:?}", snapshot.kind).to_lowercase(),
+            snapshot.funds,
+        );
+    }
+    Ok(())
+}
+
+// `changes [--from ID] [--to ID] [--aum-move PCT]`: launches, closures, TER, distribution
+// and AUM changes between two catalog snapshots, the two latest by default
+fn changes_command(args: &[String]) -> Result<()> {
+    let id = |name: &str| -> Result<Option<i64>> {
+        arg_value(args, name)
+            .map(|text| text.parse().map_err(|_| eyre!("Invalid snapshot {:?} for {}", text, name)))
+            .transpose()
+    };
+    let mut config = CatalogDiffConfig::default();
+    if let Some(percent) = arg_value(args, "--aum-move") {
+        let percent = utils::parse_number(&percent).ok_or_else(|| eyre!("Invalid AUM move {:?}", percent))?;
+        config = config.with_aum_move(percent);
+    }
+
+    let store = Store::from_env()?;
+    let diff = catalog_diff::diff_snapshots(&store, id("--from")?, id("--to")?, &config)?;
+    let taken = |snapshot: &Option<Snapshot>| {
+        snapshot.as_ref().map_or(String::new(), |snapshot| format!("{} ({})", snapshot.id, snapshot.taken_at.format("%Y-%m-%d %H:%M UTC")))
+    };
+    println!("Catalog changes from snapshot {} to {}: {}", taken(&diff.from), taken(&diff.to), diff.summary());
+    if !diff.skipped_issuers.is_empty() {
+        println!("Left out, failed to load: {}", diff.skipped_issuers.join(", "));
+    }
+    for change in &diff.changes {
+        println!(
+            "  {:<12} {:<12} {:<10} {:<50} {}",
+            change.kind.label(),
+            change.isin,
+            change.issuer,
+            change.name.chars().take(50).collect::<String>(),
+            change.kind,
+        );
+    }
+    Ok(())
+}
+
+fn print_breakdown(title: &str, breakdown: &[(String, f64)], top: usize) {
+    println!("\n{}", title);
+    for (label, weight) in breakdown.iter().take(top) {
+        println!("  {:<40} {:>7.2}%", label, weight);
+    }
+}
+
+// Why a fund of the portfolio is left out, or looked through with a caveat
+fn gap_note(gap: &Gap) -> &'static str {
+    match gap {
+        Gap::UnknownFund => "not in the catalog",
+        Gap::NoHoldings => "no holdings loaded",
+        Gap::SubstituteBasket => "holdings are a substitute basket",
+    }
+}
+
+// `portfolio [FILE] [--top N]`: look-through exposure of a portfolio, with the holdings stored
+// by `refresh --holdings`
+fn portfolio_command(args: &[String]) -> Result<()> {
+    let portfolio = match positional_args(args).first() {
+        Some(path) => Portfolio::from_file(Path::new(path))?,
+        None => Portfolio::from_env()?,
+    };
+    let top = arg_value(args, "--top").and_then(|top| top.parse().ok()).unwrap_or(10);
+
+    let etfs = match Store::from_env()?.latest_catalog()? {
+        Some(catalog) => catalog.etfs,
+        None => build_registry().0.load_catalog().etfs,
+    };
+    let exposure = portfolio::look_through(&portfolio, &etfs)?;
+
+    println!(
+        "{} distinct securities, {:.1}% of the portfolio looked through",
+        exposure.securities,
+        exposure.covered,
+    );
+    for fund in &exposure.flagged {
+        println!("  {} ({:.1}%): {}", fund.name.as_deref().unwrap_or(&fund.fund), fund.weight, gap_note(&fund.gap));
+    }
+    println!("\nLargest positions");
+    for position in exposure.top_positions(top) {
+        println!(
+            "  {:<40} {:<12} {:>7.2}%  in {} funds",
+            position.name.chars().take(40).collect::<String>(),
+            position.isin.as_deref().unwrap_or(""),
+            position.weight,
+            position.funds,
+        );
+    }
+    print_breakdown("Countries", &exposure.countries, top);
+    print_breakdown("Sectors", &exposure.sectors, top);
+    print_breakdown("Currencies", &exposure.currencies, top);
+    Ok(())
+}
+
+// Date of `--name DATE` in `args`
+fn date_arg(args: &[String], name: &str) -> Result<Option<chrono::NaiveDate>> {
+    arg_value(args, name)
+        .map(|text| dates::parse_date(&text).ok_or_else(|| eyre!("Invalid date {:?} for {}", text, name)))
+        .transpose()
+}
+
+fn print_weight_changes(title: &str, changes: &[WeightChange]) {
+    if changes.is_empty() {
+        return;
+    }
+    println!("\n{}", title);
+    for change in changes {
+        println!(
+            "  {:<40} {:<12} {:>7.2}% -> {:>7.2}%  ({:+.2})",
+            change.name.chars().take(40).collect::<String>(),
+            change.isin.as_deref().unwrap_or(""),
+            change.before,
+            change.after,
+            change.change(),
+        );
+    }
+}
+
+// `diff FUND [--from DATE] [--to DATE] [--top N]`: changes in the stored holdings of a fund
+fn diff_command(args: &[String]) -> Result<()> {
+    let positional = positional_args(args);
+    let [code] = positional.as_slice() else {
+        return Err(eyre!("Usage: etf-tool diff FUND [--from DATE] [--to DATE] [--top N]"));
+    };
+    let top = arg_value(args, "--top").and_then(|top| top.parse().ok()).unwrap_or(10);
+
+    let store = Store::from_env()?;
+    let isin = match store.latest_catalog()? {
+        Some(catalog) => catalog.etfs.iter().find(|etf| etf.has_code(code)).map(|etf| etf.isin.clone()),
+        None => None,
+    }
+    .unwrap_or_else(|| code.to_uppercase());
+
+    let diff = holdings_diff::diff_between(&store, &isin, date_arg(args, "--from")?, date_arg(args, "--to")?)?;
+    let date = |date: Option<chrono::NaiveDate>| date.map_or("unknown date".to_string(), |date| date.to_string());
+    println!("Holdings of {} from {} to {}", diff.fund_isin, date(diff.from), date(diff.to));
+    println!(
+        "{} added, {} removed, {} changed, estimated turnover {:.2}%",
+        diff.added.len(),
+        diff.removed.len(),
+        diff.changed.len(),
+        diff.turnover,
+    );
+    print_weight_changes("Added", &diff.added[..top.min(diff.added.len())]);
+    print_weight_changes("Removed", &diff.removed[..top.min(diff.removed.len())]);
+    print_weight_changes("Largest weight changes", diff.top_changes(top));
+    Ok(())
+}
+
+// Risk settings from ETF_TOOL_RISK_FREE_RATE and ETF_TOOL_BENCHMARK, overridden by
+// `--risk-free-rate PERCENT` and `--benchmark FUND` in `args`. The benchmark is an ISIN or a ticker of `etfs`.
+fn risk_config(args: &[String], etfs: &[ETF]) -> Result<RiskConfig> {
+    let mut config = RiskConfig::from_env();
+    if let Some(rate) = arg_value(args, "--risk-free-rate") {
+        let rate = utils::parse_number(&rate).ok_or_else(|| eyre!("Invalid risk-free rate {:?}", rate))?;
+        config = config.with_risk_free_rate(rate);
+    }
+    if let Some(code) = arg_value(args, "--benchmark").or(config.benchmark.clone()) {
+        let isin = match etfs.iter().find(|etf| etf.has_code(&code)) {
+            Some(etf) => etf.isin.clone(),
+            None if etf_tool::identifiers::is_valid_isin(&code) => code,
+            None => return Err(eyre!("No benchmark fund with ISIN or ticker {}", code)),
+        };
+        config = config.with_benchmark(&isin);
+    }
+    Ok(config)
+}
+
+// Messages from the background holdings load
+enum HoldingsMessage {
+    Progress { done: usize, total: usize },
+    Finished(Vec<ETF>, BulkReport),
+}
+
+// Holdings load running in the background
+struct HoldingsJob {
+    cancel: CancelToken,
+    receiver: Receiver<HoldingsMessage>,
+    done: usize,
+    total: usize,
+}
 
 struct App {
+    registry: Arc<ProviderRegistry>,
     etfs: Vec<ETF>,
+    reports: Vec<IssuerReport>,
     selected_index: usize,
+    holdings_job: Option<HoldingsJob>,
+    holdings_report: Option<BulkReport>,
+    sort_by_aum: bool,
+    reporting: Option<Reporting>, // Currency AUM is shown in, the fund's own currency if None
+    messages: Vec<String>,        // Notices shown in the status area
+    catalog_positions: HashMap<(String, String), usize>, // Position in the catalog by issuer and ISIN
+    risk: HashMap<String, RiskMetrics>, // By ISIN, for funds with a stored price history
+    store: Option<Store>,         // Snapshots of catalogs and holdings, None if the database cannot be opened
+    definitions_report: Option<IssuerReport>, // Failure to read `issuers/*.toml`
+    catalog_refresh: Option<Receiver<CatalogLoad>>, // Catalog load running in the background
+    holdings_diff: Option<HoldingsDiff>, // Shown instead of the fund list when set
+    marked: Vec<String>,          // ISINs of the funds to compare, marked with 'm'
+    overlap: Option<(Vec<Overlap>, usize)>, // Pairwise overlaps shown with 'o', and the selected pair
+    look_through: Option<LookThrough>, // Exposure of the portfolio shown with 'p'
+    search: Option<String>,       // Query being typed after '/'
+    last_search: Option<String>,  // Repeated by 'n'
+    args: Vec<String>,            // Command line, for the risk settings
 }
 
 impl App {
-    fn new() -> Result<Self> {
-        let mut xtrackers_etfs = xtrackers::get_xtrackers_etfs()?;
-        let ishares_etfs = ishares::get_ishares_etfs()?;
-        let invesco_etfs = invesco::get_invesco_etfs()?;
-        
-        // Combine all ETF lists
-        let mut all_etfs = Vec::new();
-        all_etfs.extend(xtrackers_etfs);
-        all_etfs.extend(ishares_etfs);
-        all_etfs.extend(invesco_etfs);
-        
-        Ok(Self {
-            etfs: all_etfs,
+    // Start from the latest catalog snapshot in the store, or load every issuer when there is
+    // none or `refresh` is set. `args` are the command line options.
+    fn new(reporting: Option<Reporting>, store: Result<Store>, refresh: bool, args: Vec<String>) -> Self {
+        let (registry, definitions_report) = build_registry();
+        let mut messages = Vec::new();
+        let store = store
+            .map_err(|e| messages.push(format!("Snapshot store unavailable: {:#}", e)))
+            .ok();
+
+        let stored = match &store {
+            Some(store) if !refresh => store.latest_catalog()
+                .map_err(|e| messages.push(format!("Failed to read the latest snapshot: {:#}", e)))
+                .ok()
+                .flatten(),
+            _ => None,
+        };
+
+        let mut app = Self {
+            registry: Arc::new(registry),
+            etfs: Vec::new(),
+            reports: Vec::new(),
             selected_index: 0,
+            holdings_job: None,
+            holdings_report: None,
+            sort_by_aum: false,
+            reporting,
+            messages,
+            catalog_positions: HashMap::new(),
+            risk: HashMap::new(),
+            store,
+            definitions_report,
+            catalog_refresh: None,
+            holdings_diff: None,
+            marked: Vec::new(),
+            overlap: None,
+            look_through: None,
+            search: None,
+            last_search: None,
+            args,
+        };
+
+        match stored {
+            Some(stored) => {
+                app.messages.push(format!(
+                    "Catalog snapshot {} of {} (press r to refresh)",
+                    stored.snapshot.id,
+                    stored.snapshot.taken_at.format("%Y-%m-%d %H:%M UTC"),
+                ));
+                app.set_catalog(stored.etfs, stored.reports);
+            }
+            // Issuers that fail are reported in the status area instead of aborting startup
+            None => {
+                let catalog = app.registry.load_catalog();
+                app.apply_catalog(catalog);
+            }
+        }
+        app
+    }
+
+    // Show a new catalog, keeping the holdings already loaded for funds it still has
+    fn set_catalog(&mut self, mut etfs: Vec<ETF>, reports: Vec<IssuerReport>) {
+        for etf in etfs.iter_mut().filter(|etf| etf.holdings.is_empty()) {
+            if let Some(previous) = self.etfs.iter().find(|previous| previous.isin == etf.isin) {
+                etf.holdings = previous.holdings.clone();
+                etf.holdings_as_of = previous.holdings_as_of;
+                etf.holdings_basis = previous.holdings_basis;
+            }
+        }
+
+        self.catalog_positions = etfs.iter().enumerate()
+            .map(|(position, etf)| ((etf.issuer.clone(), etf.isin.clone()), position))
+            .collect();
+        self.etfs = etfs;
+        self.reports = reports;
+        self.sort_by_aum = false;
+        self.selected_index = self.selected_index.min(self.etfs.len().saturating_sub(1));
+
+        self.risk = match risk_config(&self.args, &self.etfs).and_then(|config| Ok((config, PriceStore::from_env()?))) {
+            Ok((config, prices)) => risk::compute_store(&prices, &config, self.reporting.as_ref()),
+            Err(e) => {
+                self.messages.push(format!("Risk metrics unavailable: {:#}", e));
+                HashMap::new()
+            }
+        };
+    }
+
+    // Show a freshly loaded catalog and store it as a snapshot
+    fn apply_catalog(&mut self, catalog: CatalogLoad) {
+        let reports: Vec<IssuerReport> = self.definitions_report.clone().into_iter().chain(catalog.reports).collect();
+        if let Some(store) = &mut self.store {
+            match store.save_catalog(&catalog.etfs, &reports) {
+                Ok(snapshot) => {
+                    self.messages.push(format!("Saved catalog snapshot {}", snapshot.id));
+                    if let Ok(Some(_)) = store.previous_catalog(snapshot.id) {
+                        match catalog_diff::diff_snapshots(store, None, Some(snapshot.id), &CatalogDiffConfig::default()) {
+                            Ok(changes) => self.messages.push(format!("Since the previous catalog: {}", changes.summary())),
+                            Err(e) => self.messages.push(format!("Failed to compare catalogs: {:#}", e)),
+                        }
+                    }
+                }
+                Err(e) => self.messages.push(format!("Failed to save the catalog: {:#}", e)),
+            }
+        }
+        self.set_catalog(catalog.etfs, reports);
+    }
+
+    // Load every issuer again in a background thread
+    fn start_catalog_refresh(&mut self) {
+        if self.catalog_refresh.is_some() {
+            return;
+        }
+        let (sender, receiver) = mpsc::channel();
+        let registry = self.registry.clone();
+        std::thread::spawn(move || {
+            let _ = sender.send(registry.load_catalog());
+        });
+        self.catalog_refresh = Some(receiver);
+        self.messages = vec!["Refreshing the catalog...".to_string()];
+    }
+
+    fn poll_catalog_refresh(&mut self) {
+        let Some(receiver) = &self.catalog_refresh else { return };
+        if let Ok(catalog) = receiver.try_recv() {
+            self.catalog_refresh = None;
+            self.messages.clear();
+            self.apply_catalog(catalog);
+        }
+    }
+
+    // Switch between catalog order and largest funds first, keeping the selected fund selected
+    fn toggle_sort(&mut self) {
+        let selected = self.etfs.get(self.selected_index).map(|etf| (etf.issuer.clone(), etf.isin.clone()));
+
+        self.sort_by_aum = !self.sort_by_aum;
+        if self.sort_by_aum {
+            // Compare in the reporting currency when there is one, so share classes in
+            // different currencies rank by their actual size
+            match &self.reporting {
+                Some(reporting) => {
+                    let amount = |etf: &ETF| etf.aum.value.as_ref()
+                        .and_then(|money| reporting.convert(money, etf.as_of))
+                        .map(|money| money.amount);
+                    self.etfs.sort_by(|a, b| match (amount(a), amount(b)) {
+                        (Some(a), Some(b)) => b.total_cmp(&a),
+                        (a, b) => b.is_some().cmp(&a.is_some()),
+                    });
+                }
+                None => self.etfs.sort_by(|a, b| b.aum.cmp_amount(&a.aum)),
+            }
+        } else {
+            let positions = &self.catalog_positions;
+            self.etfs.sort_by_key(|etf| positions.get(&(etf.issuer.clone(), etf.isin.clone())).copied());
+        }
+
+        if let Some((issuer, isin)) = selected {
+            self.selected_index = self.etfs.iter()
+                .position(|etf| etf.issuer == issuer && etf.isin == isin)
+                .unwrap_or(0);
+        }
+    }
+
+    // Load the holdings of every fund in a background thread
+    fn start_holdings_load(&mut self) {
+        if self.holdings_job.is_some() {
+            return;
+        }
+
+        let (sender, receiver) = mpsc::channel();
+        let cancel = CancelToken::new();
+        let registry = self.registry.clone();
+        let mut etfs = self.etfs.clone();
+        let total = etfs.len();
+
+        let worker_cancel = cancel.clone();
+        std::thread::spawn(move || {
+            let progress_sender = sender.clone();
+            let report = bulk::load_holdings(&registry, &mut etfs, &BulkOptions::default(), &worker_cancel, |progress| {
+                let _ = progress_sender.send(HoldingsMessage::Progress { done: progress.done, total: progress.total });
+            });
+            let _ = sender.send(HoldingsMessage::Finished(etfs, report));
+        });
+
+        self.holdings_job = Some(HoldingsJob { cancel, receiver, done: 0, total });
+    }
+
+    fn cancel_holdings_load(&mut self) {
+        if let Some(job) = &self.holdings_job {
+            job.cancel.cancel();
+        }
+    }
+
+    // Apply the messages received from the background holdings load
+    fn poll_holdings_load(&mut self) {
+        let Some(job) = &mut self.holdings_job else { return };
+
+        let mut finished = None;
+        while let Ok(message) = job.receiver.try_recv() {
+            match message {
+                HoldingsMessage::Progress { done, total } => {
+                    job.done = done;
+                    job.total = total;
+                }
+                HoldingsMessage::Finished(etfs, report) => finished = Some((etfs, report)),
+            }
+        }
+
+        if let Some((loaded, report)) = finished {
+            // The catalog may have been replaced meanwhile, so match funds by ISIN
+            for etf in loaded {
+                if let Some(target) = self.etfs.iter_mut().find(|target| target.isin == etf.isin) {
+                    target.holdings = etf.holdings;
+                    target.holdings_as_of = etf.holdings_as_of;
+                    target.holdings_basis = etf.holdings_basis;
+                }
+            }
+            self.holdings_job = None;
+            self.holdings_report = Some(report);
+
+            if let Some(store) = &mut self.store {
+                match store.save_holdings(&self.etfs) {
+                    Ok(Some(snapshot)) => self.messages.push(format!("Saved holdings snapshot {}", snapshot.id)),
+                    Ok(None) => {}
+                    Err(e) => self.messages.push(format!("Failed to save holdings: {:#}", e)),
+                }
+            }
+        }
+    }
+
+    // Select the next fund after the selected one with `query` as its ISIN or a listing ticker,
+    // Bloomberg or Reuters code. Names containing the query are matched when no code is.
+    fn find(&mut self, query: &str) {
+        let query = query.trim();
+        if query.is_empty() || self.etfs.is_empty() {
+            return;
+        }
+        let lowercase = query.to_lowercase();
+        let order: Vec<usize> = (1..=self.etfs.len()).map(|offset| (self.selected_index + offset) % self.etfs.len()).collect();
+
+        let found = order.iter().copied().find(|&index| self.etfs[index].has_code(query))
+            .or_else(|| order.iter().copied().find(|&index| self.etfs[index].name.to_lowercase().contains(&lowercase)));
+        self.messages = match found {
+            Some(index) => {
+                self.selected_index = index;
+                Vec::new()
+            }
+            None => vec![format!("No fund matches {:?}", query)],
+        };
+        self.last_search = Some(query.to_string());
+    }
+
+    // Keys typed while the search prompt is open
+    fn search_key(&mut self, code: KeyCode) {
+        let Some(query) = &mut self.search else { return };
+        match code {
+            KeyCode::Char(c) => query.push(c),
+            KeyCode::Backspace => {
+                query.pop();
+            }
+            KeyCode::Enter => {
+                let query = query.clone();
+                self.search = None;
+                self.find(&query);
+            }
+            KeyCode::Esc => self.search = None,
+            _ => {}
+        }
+    }
+
+    // Show what changed between the two latest stored holdings of the selected fund
+    fn show_holdings_diff(&mut self) {
+        let (Some(store), Some(etf)) = (&self.store, self.etfs.get(self.selected_index)) else { return };
+        match holdings_diff::diff_between(store, &etf.isin, None, None) {
+            Ok(diff) => self.holdings_diff = Some(diff),
+            Err(e) => self.messages = vec![format!("{:#}", e)],
+        }
+    }
+
+    fn toggle_mark(&mut self) {
+        let Some(etf) = self.etfs.get(self.selected_index) else { return };
+        match self.marked.iter().position(|isin| *isin == etf.isin) {
+            Some(index) => {
+                self.marked.remove(index);
+            }
+            None => self.marked.push(etf.isin.clone()),
+        }
+    }
+
+    // Compare the marked funds, and the selected one when fewer than two are marked
+    fn show_overlap(&mut self) {
+        let mut isins = self.marked.clone();
+        if let Some(etf) = self.etfs.get(self.selected_index).filter(|_| isins.len() < 2) {
+            if !isins.contains(&etf.isin) {
+                isins.push(etf.isin.clone());
+            }
+        }
+        let funds: Vec<&ETF> = isins.iter().filter_map(|isin| self.etfs.iter().find(|etf| etf.isin == *isin)).collect();
+        if funds.len() < 2 {
+            self.messages = vec!["Mark the funds to compare with 'm' first".to_string()];
+            return;
+        }
+        let missing: Vec<&str> = funds.iter().filter(|etf| etf.holdings.is_empty()).map(|etf| etf.isin.as_str()).collect();
+        if !missing.is_empty() {
+            self.messages = vec![format!("No holdings loaded for {}, load them with 'h' first", missing.join(", "))];
+            return;
+        }
+        self.overlap = Some((overlap::pairwise(&funds), 0));
+    }
+
+    // Look through the portfolio in ETF_TOOL_PORTFOLIO with the holdings loaded so far
+    fn show_look_through(&mut self) {
+        match Portfolio::from_env().and_then(|portfolio| portfolio::look_through(&portfolio, &self.etfs)) {
+            Ok(exposure) => self.look_through = Some(exposure),
+            Err(e) => self.messages = vec![format!("{:#}", e)],
+        }
+    }
+
+    fn export(&mut self) {
+        let message = match export::export_etfs_csv(Path::new(EXPORT_FILE), &self.etfs, self.reporting.as_ref(), &self.risk) {
+            Ok(()) => format!("Exported {} funds to {}", self.etfs.len(), EXPORT_FILE),
+            Err(e) => format!("Export failed: {:#}", e),
+        };
+        self.messages = vec![message];
+    }
+
+    // Lines below the issuer reports: notices and the state of the holdings load
+    fn status_lines(&self) -> Vec<String> {
+        let mut lines = self.messages.clone();
+        if let Some(query) = &self.search {
+            lines.push(format!("Find ticker, ISIN or name: {}_ (Enter to jump, Esc to cancel)", query));
+        }
+        lines.extend(self.holdings_status());
+        lines
+    }
+
+    // Status line of the holdings load, if one was started
+    fn holdings_status(&self) -> Option<String> {
+        if let Some(job) = &self.holdings_job {
+            let state = if job.cancel.is_cancelled() { "cancelling" } else { "press c to cancel" };
+            return Some(format!("Holdings: {}/{} funds ({})", job.done, job.total, state));
+        }
+        self.holdings_report.as_ref().map(|report| {
+            format!(
+                "Holdings: {} loaded, {} failed, {} unsupported, {} cancelled in {:.1}s",
+                report.loaded(),
+                report.failures().count(),
+                report.unsupported(),
+                report.cancelled(),
+                report.elapsed.as_secs_f64(),
+            )
         })
     }
 
     fn next(&mut self) {
-        self.selected_index = (self.selected_index + 1).min(self.etfs.len() - 1);
+        self.selected_index = (self.selected_index + 1).min(self.etfs.len().saturating_sub(1));
     }
 
     fn previous(&mut self) {
@@ -45,25 +805,139 @@ impl App {
 
 fn main() -> Result<()> {
     color_eyre::install()?;
+
+    // Subcommands run without the TUI
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("prices") => return prices_command(&args[1..]),
+        Some("refresh") => return refresh_command(&args[1..]),
+        Some("snapshots") => return snapshots_command(),
+        Some("diff") => return diff_command(&args[1..]),
+        Some("changes") => return changes_command(&args[1..]),
+        Some("portfolio") => return portfolio_command(&args[1..]),
+        _ => {}
+    }
+
+    // `--refresh` downloads every issuer file again, whatever the age of the cached copy,
+    // instead of starting from the latest snapshot
+    let refresh = args.iter().any(|arg| arg == "--refresh");
+    if refresh {
+        cache::configure(CacheConfig { max_age: Duration::ZERO, ..CacheConfig::from_env() });
+    }
+
+    // Without rates, figures stay in each fund's currency
+    let (reporting, reporting_error) = match load_reporting(&args) {
+        Ok(reporting) => (reporting, None),
+        Err(e) => (None, Some(format!("Reporting currency unavailable: {:#}", e))),
+    };
+
+    // Load before taking over the terminal so loader progress stays readable
+    let mut app = App::new(reporting, Store::from_env(), refresh, args);
+    app.messages.extend(reporting_error);
+
+    // Loader messages would be drawn over the TUI
+    utils::set_quiet(true);
     let terminal = ratatui::init();
-    let result = run(terminal);
+    let result = run(terminal, app);
     ratatui::restore();
     result
 }
 
-fn run(mut terminal: DefaultTerminal) -> Result<()> {
-    let mut app = App::new()?;
-
+fn run(mut terminal: DefaultTerminal, mut app: App) -> Result<()> {
     loop {
-        terminal.draw(|frame| ui::render(frame, &app.etfs, app.selected_index))?;
+        app.poll_holdings_load();
+        app.poll_catalog_refresh();
+        let status = app.status_lines();
+        let screen = match (&app.holdings_diff, &app.overlap, &app.look_through) {
+            (Some(diff), _, _) => Screen::HoldingsDiff(diff),
+            (None, Some((overlaps, selected)), _) => Screen::Overlap { overlaps, selected: *selected },
+            (None, None, Some(exposure)) => Screen::LookThrough(exposure),
+            (None, None, None) => Screen::Funds { marked: &app.marked },
+        };
+        let view = ui::View {
+            etfs: &app.etfs,
+            selected_index: app.selected_index,
+            reports: &app.reports,
+            status: &status,
+            reporting: app.reporting.as_ref(),
+            risk: &app.risk,
+            screen,
+        };
+        terminal.draw(|frame| ui::render(frame, &view))?;
 
+        // Wake up regularly to show the progress of background loads
+        if !event::poll(Duration::from_millis(200))? {
+            continue;
+        }
         if let Event::Key(key) = event::read()? {
+            if app.search.is_some() {
+                app.search_key(key.code);
+                continue;
+            }
             match key.code {
-                KeyCode::Char('q') | KeyCode::Esc => break Ok(()),
+                // Esc and d close the holdings diff before quitting
+                KeyCode::Esc | KeyCode::Char('d') if app.holdings_diff.is_some() => app.holdings_diff = None,
+                KeyCode::Char('d') => app.show_holdings_diff(),
+                // In the comparison, j and k move between pairs of funds
+                KeyCode::Esc | KeyCode::Char('o') if app.overlap.is_some() => app.overlap = None,
+                KeyCode::Down | KeyCode::Char('j') if app.overlap.is_some() => {
+                    if let Some((overlaps, selected)) = &mut app.overlap {
+                        *selected = (*selected + 1).min(overlaps.len().saturating_sub(1));
+                    }
+                }
+                KeyCode::Up | KeyCode::Char('k') if app.overlap.is_some() => {
+                    if let Some((_, selected)) = &mut app.overlap {
+                        *selected = selected.saturating_sub(1);
+                    }
+                }
+                KeyCode::Char('o') => app.show_overlap(),
+                KeyCode::Esc | KeyCode::Char('p') if app.look_through.is_some() => app.look_through = None,
+                KeyCode::Char('p') => app.show_look_through(),
+                KeyCode::Char('m') => app.toggle_mark(),
+                KeyCode::Char('q') | KeyCode::Esc => {
+                    app.cancel_holdings_load();
+                    break Ok(());
+                }
                 KeyCode::Down | KeyCode::Char('j') => app.next(),
                 KeyCode::Up | KeyCode::Char('k') => app.previous(),
+                KeyCode::Char('h') => app.start_holdings_load(),
+                KeyCode::Char('c') => app.cancel_holdings_load(),
+                KeyCode::Char('s') => app.toggle_sort(),
+                KeyCode::Char('e') => app.export(),
+                KeyCode::Char('r') => app.start_catalog_refresh(),
+                KeyCode::Char('/') => app.search = Some(String::new()),
+                KeyCode::Char('n') => {
+                    if let Some(query) = app.last_search.clone() {
+                        app.find(&query);
+                    }
+                }
                 _ => {}
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(text: &str) -> Vec<String> {
+        text.split_whitespace().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn option_values_come_from_the_given_args() {
+        let args = args("IE00B4L5Y983 --from 2024-01-01 --top=5");
+        assert_eq!(arg_value(&args, "--from").as_deref(), Some("2024-01-01"));
+        assert_eq!(arg_value(&args, "--top").as_deref(), Some("5"));
+        assert_eq!(arg_value(&args, "--to"), None);
+        assert_eq!(date_arg(&args, "--from").unwrap(), chrono::NaiveDate::from_ymd_opt(2024, 1, 1));
+        assert!(date_arg(&self::args("--to someday"), "--to").is_err());
+    }
+
+    #[test]
+    fn positional_args_skip_option_values() {
+        let args = args("import a.csv --isin IE00B4L5Y983 --holdings b.csv --top=3");
+        assert_eq!(positional_args(&args), ["import", "a.csv", "b.csv"]);
+    }
+}
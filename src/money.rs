@@ -0,0 +1,245 @@
+use std::cmp::Ordering;
+use std::fmt;
+use serde::{Deserialize, Serialize};
+use crate::utils;
+
+// An amount in a currency, in units (not thousands or millions)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Money {
+    pub amount: f64,
+    pub currency: String, // ISO 4217 code, empty if unknown
+}
+
+impl Money {
+    pub fn new(amount: f64, currency: &str) -> Self {
+        Self { amount, currency: currency.to_string() }
+    }
+}
+
+// Compact form: "1.23bn USD", "45.6m EUR", "980.00k GBP"
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let abs = self.amount.abs();
+        let amount = if abs >= 1e9 {
+            format!("{:.2}bn", self.amount / 1e9)
+        } else if abs >= 1e6 {
+            format!("{:.2}m", self.amount / 1e6)
+        } else if abs >= 1e3 {
+            format!("{:.2}k", self.amount / 1e3)
+        } else {
+            format!("{:.2}", self.amount)
+        };
+        if self.currency.is_empty() {
+            write!(f, "{}", amount)
+        } else {
+            write!(f, "{} {}", amount, self.currency)
+        }
+    }
+}
+
+// Assets under management as published by the issuer, with the parsed amount when it could be read
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Aum {
+    pub raw: String,          // Cell text as published
+    pub value: Option<Money>, // None when the cell is empty or not a number
+}
+
+impl Aum {
+    // Parse a cell. `currency` applies when the text names none; `scale` multiplies amounts
+    // without a magnitude suffix, for columns published in thousands or millions.
+    pub fn parse(raw: &str, currency: Option<&str>, scale: f64) -> Self {
+        let raw = raw.trim().to_string();
+        let value = parse_money(&raw, scale).map(|(amount, code)| Money {
+            amount,
+            currency: code.or(currency.map(str::to_string)).map_or_else(String::new, |code| currency_code(&code)),
+        });
+        Self { raw, value }
+    }
+
+    pub fn amount(&self) -> Option<f64> {
+        self.value.as_ref().map(|money| money.amount)
+    }
+
+    pub fn currency(&self) -> Option<&str> {
+        self.value.as_ref().map(|money| money.currency.as_str()).filter(|code| !code.is_empty())
+    }
+
+    // Order by amount, funds without an amount first. Amounts are in units whatever magnitude
+    // the issuer published; amounts in different currencies need converting first to compare.
+    pub fn cmp_amount(&self, other: &Self) -> Ordering {
+        match (self.amount(), other.amount()) {
+            (Some(a), Some(b)) => a.total_cmp(&b),
+            (Some(_), None) => Ordering::Greater,
+            (None, Some(_)) => Ordering::Less,
+            (None, None) => Ordering::Equal,
+        }
+    }
+}
+
+// Parsed amount when available, the published text otherwise
+impl fmt::Display for Aum {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.value {
+            Some(money) => write!(f, "{}", money),
+            None => write!(f, "{}", self.raw),
+        }
+    }
+}
+
+// Minor units with their major currency and the number of them in one major unit. The case
+// tells pence ("GBp") from pounds ("GBP"), so these codes are kept as written.
+pub const MINOR_UNITS: [(&str, &str, f64); 3] = [("GBX", "GBP", 100.0), ("GBp", "GBP", 100.0), ("ZAc", "ZAR", 100.0)];
+
+// Currency code in its usual case: minor units as written, other codes uppercased
+pub fn currency_code(code: &str) -> String {
+    let code = code.trim();
+    match MINOR_UNITS.iter().find(|(minor, _, _)| *minor == code) {
+        Some((minor, _, _)) => minor.to_string(),
+        None => code.to_uppercase(),
+    }
+}
+
+const SYMBOLS: [(&str, &str); 5] = [("$", "USD"), ("€", "EUR"), ("£", "GBP"), ("¥", "JPY"), ("₣", "CHF")];
+
+// Magnitude suffixes, longest first so "bn" is not read as "b" followed by garbage
+const MAGNITUDES: [(&str, f64); 10] = [
+    ("billion", 1e9),
+    ("million", 1e6),
+    ("thousand", 1e3),
+    ("bln", 1e9),
+    ("mln", 1e6),
+    ("bn", 1e9),
+    ("mn", 1e6),
+    ("b", 1e9),
+    ("m", 1e6),
+    ("k", 1e3),
+];
+
+// Currency and magnitude named by a column header: "AuM (CHF)", "Net Assets (M)", "AUM EUR (mn)"
+pub fn header_unit(header: &str) -> (Option<String>, f64) {
+    let mut currency = None;
+    let mut scale = 1.0;
+    for word in header.split(|c: char| !c.is_alphanumeric()).filter(|word| !word.is_empty()) {
+        if is_currency_code(word) && currency.is_none() {
+            currency = Some(word.to_string());
+        } else if let Some((_, factor)) = MAGNITUDES.iter().find(|(suffix, _)| word.eq_ignore_ascii_case(suffix)) {
+            scale = *factor;
+        }
+    }
+    (currency, scale)
+}
+
+fn is_currency_code(word: &str) -> bool {
+    MINOR_UNITS.iter().any(|(minor, _, _)| *minor == word)
+        || (word.len() == 3 && word.chars().all(|c| c.is_ascii_uppercase()) && !matches!(word, "AUM" | "TER" | "OCF" | "NAV" | "TAO"))
+}
+
+// Parse an amount such as "1,234,567.89", "1.234.567,89", "1'234", "$1.2bn", "EUR 45.6 mn" or "980k".
+// Returns the amount in units and the currency if the text names one.
+pub fn parse_money(text: &str, scale: f64) -> Option<(f64, Option<String>)> {
+    let mut rest = utils::non_empty(text)?;
+
+    let mut currency = None;
+    for (symbol, code) in SYMBOLS {
+        if rest.contains(symbol) {
+            rest = rest.replace(symbol, "");
+            currency = Some(code.to_string());
+        }
+    }
+    if currency.is_none() {
+        let code = rest.split_whitespace()
+            .find(|word| is_currency_code(word))
+            .map(str::to_string);
+        if let Some(code) = code {
+            rest = rest.replace(&code, "");
+            currency = Some(code);
+        }
+    }
+
+    let mut rest = rest.trim().to_string();
+    let mut factor = scale;
+    let lower = rest.to_lowercase();
+    if let Some((suffix, magnitude)) = MAGNITUDES.iter().find(|(suffix, _)| lower.ends_with(suffix)) {
+        let number = rest[..rest.len() - suffix.len()].trim_end();
+        // Only a suffix right after a digit, so a stray word is not taken for a magnitude
+        if number.ends_with(|c: char| c.is_ascii_digit()) {
+            rest = number.to_string();
+            factor = *magnitude;
+        }
+    }
+
+    parse_amount(&rest).map(|amount| (amount * factor, currency))
+}
+
+// Parse a number with thousands separators, guessing the decimal separator
+fn parse_amount(text: &str) -> Option<f64> {
+    let text: String = text.chars()
+        .filter(|c| !c.is_whitespace() && *c != '\'' && *c != '\u{a0}')
+        .collect();
+
+    let decimal = match (text.rfind('.'), text.rfind(',')) {
+        // Both used: the last one separates the decimals
+        (Some(dot), Some(comma)) => if dot > comma { Some('.') } else { Some(',') },
+        // A single comma followed by anything but three digits is a decimal comma: "12,5"
+        (None, Some(comma)) if text.matches(',').count() == 1 && text.len() - comma - 1 != 3 => Some(','),
+        (None, Some(_)) => None,
+        // Several dots are thousands separators: "1.234.567"
+        (Some(_), None) if text.matches('.').count() > 1 => None,
+        (Some(_), None) => Some('.'),
+        (None, None) => None,
+    };
+
+    let normalized: String = text.chars()
+        .filter_map(|c| match c {
+            '.' | ',' if Some(c) == decimal => Some('.'),
+            '.' | ',' => None,
+            c => Some(c),
+        })
+        .collect();
+    normalized.parse().ok().filter(|amount: &f64| amount.is_finite())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn money(aum: &Aum) -> (f64, &str) {
+        let money = aum.value.as_ref().unwrap();
+        (money.amount, money.currency.as_str())
+    }
+
+    #[test]
+    fn keeps_pence_apart_from_pounds() {
+        assert_eq!(money(&Aum::parse("1,234", Some("GBp"), 1.0)), (1234.0, "GBp"));
+        assert_eq!(money(&Aum::parse("GBp 1,234", None, 1.0)), (1234.0, "GBp"));
+        assert_eq!(money(&Aum::parse("1,234", Some("gbp"), 1.0)), (1234.0, "GBP"));
+    }
+
+    #[test]
+    fn parses_gbx() {
+        assert_eq!(money(&Aum::parse("GBX 1.2bn", None, 1.0)), (1.2e9, "GBX"));
+        assert_eq!(money(&Aum::parse("1.2bn", Some("GBX"), 1.0)), (1.2e9, "GBX"));
+    }
+
+    #[test]
+    fn parses_magnitude_suffixes() {
+        assert_eq!(money(&Aum::parse("1.2bn", Some("usd"), 1.0)), (1.2e9, "USD"));
+        assert_eq!(money(&Aum::parse("$45.6 mn", None, 1.0)), (45.6e6, "USD"));
+        assert_eq!(money(&Aum::parse("980k", Some("EUR"), 1.0)), (980e3, "EUR"));
+    }
+
+    #[test]
+    fn applies_the_column_scale() {
+        let (currency, scale) = header_unit("AUM EUR (mn)");
+        assert_eq!((currency.as_deref(), scale), (Some("EUR"), 1e6));
+        assert_eq!(money(&Aum::parse("1.234,5", Some("EUR"), scale)), (1234.5e6, "EUR"));
+        // A suffix in the cell wins over the column scale
+        assert_eq!(money(&Aum::parse("2bn", Some("EUR"), scale)), (2e9, "EUR"));
+    }
+
+    #[test]
+    fn leaves_empty_cells_unparsed() {
+        assert_eq!(Aum::parse(" - ", Some("EUR"), 1.0).value, None);
+        assert_eq!(Aum::parse("", None, 1.0).value, None);
+    }
+}
@@ -0,0 +1,176 @@
+use crate::etf::{HoldingsBasis, ETF};
+use crate::holdings_diff::{normalized, positions};
+
+// A position held by both funds, with its weight in each, in percent
+#[derive(Debug, Clone, PartialEq)]
+pub struct SharedHolding {
+    pub name: String,
+    pub isin: Option<String>,
+    pub weight_a: f64,
+    pub weight_b: f64,
+}
+
+impl SharedHolding {
+    // Part of the weight both funds hold
+    pub fn overlap(&self) -> f64 {
+        self.weight_a.min(self.weight_b)
+    }
+}
+
+// How much two funds hold the same positions. Weights are scaled to add up to 100 in each fund.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Overlap {
+    pub fund_a: String, // ISIN
+    pub fund_b: String,
+    pub holdings_a: usize, // Number of positions
+    pub holdings_b: usize,
+    pub common: usize,
+    pub weight_overlap: f64, // Sum of the smaller weight of each shared position, 0 to 100
+    pub active_share: f64,   // Half the sum of the weight differences: 100 - overlap
+    pub shared: Vec<SharedHolding>, // Largest overlap first
+    pub substitute_basket: bool,    // One fund holds a swap collateral basket, so the figures say little
+}
+
+impl Overlap {
+    // Part of the positions of fund a that fund b also holds, in percent
+    pub fn common_share_a(&self) -> f64 {
+        if self.holdings_a == 0 { 0.0 } else { self.common as f64 / self.holdings_a as f64 * 100.0 }
+    }
+
+    pub fn common_share_b(&self) -> f64 {
+        if self.holdings_b == 0 { 0.0 } else { self.common as f64 / self.holdings_b as f64 * 100.0 }
+    }
+}
+
+// Overlap of two funds from their holdings. Positions are matched as in `holdings_diff`: by
+// ISIN, then ticker, then name.
+pub fn overlap(a: &ETF, b: &ETF) -> Overlap {
+    let mut positions_a = positions(&a.holdings);
+    let mut positions_b = positions(&b.holdings);
+    normalized(&mut positions_a);
+    normalized(&mut positions_b);
+
+    let mut shared: Vec<SharedHolding> = positions_a.iter()
+        .filter_map(|(key, (name, isin, weight_a))| {
+            let (_, _, weight_b) = positions_b.get(key)?;
+            Some(SharedHolding { name: name.clone(), isin: isin.clone(), weight_a: *weight_a, weight_b: *weight_b })
+        })
+        .collect();
+    shared.sort_by(|x, y| y.overlap().total_cmp(&x.overlap()));
+
+    let weight_overlap: f64 = shared.iter().map(SharedHolding::overlap).sum();
+    let difference: f64 = positions_a.iter()
+        .map(|(key, (_, _, weight))| (weight - positions_b.get(key).map_or(0.0, |(_, _, other)| *other)).abs())
+        .chain(positions_b.iter()
+            .filter(|(key, _)| !positions_a.contains_key(*key))
+            .map(|(_, (_, _, weight))| weight.abs()))
+        .sum();
+
+    Overlap {
+        fund_a: a.isin.clone(),
+        fund_b: b.isin.clone(),
+        holdings_a: positions_a.len(),
+        holdings_b: positions_b.len(),
+        common: shared.len(),
+        weight_overlap,
+        active_share: difference / 2.0,
+        shared,
+        substitute_basket: a.holdings_basis == HoldingsBasis::SubstituteBasket
+            || b.holdings_basis == HoldingsBasis::SubstituteBasket,
+    }
+}
+
+// Overlap of every pair of funds, in the order given: (1, 2), (1, 3), ..., (2, 3), ...
+// Funds without holdings are left out.
+pub fn pairwise(etfs: &[&ETF]) -> Vec<Overlap> {
+    let funds: Vec<&ETF> = etfs.iter().copied().filter(|etf| !etf.holdings.is_empty()).collect();
+    let mut overlaps = Vec::new();
+    for (index, a) in funds.iter().enumerate() {
+        for b in &funds[index + 1..] {
+            overlaps.push(overlap(a, b));
+        }
+    }
+    overlaps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::etf::Holding;
+    use crate::utils::assert_close as close;
+
+    fn holding(name: &str, isin: Option<&str>, weight: f64) -> Holding {
+        Holding { name: name.to_string(), isin: isin.map(str::to_string), weight, ..Holding::default() }
+    }
+
+    #[test]
+    fn partial_overlap_sums_the_smaller_weights() {
+        let a = ETF::for_test("IE00B4L5Y983", vec![
+            holding("Apple", Some("US0378331005"), 50.0),
+            holding("Microsoft", Some("US5949181045"), 30.0),
+            holding("Nestle", Some("CH0038863350"), 20.0),
+        ]);
+        let b = ETF::for_test("IE00B5BMR087", vec![
+            holding("Apple", Some("US0378331005"), 40.0),
+            holding("Microsoft", Some("US5949181045"), 30.0),
+            holding("Toyota", Some("JP3633400001"), 30.0),
+        ]);
+
+        let overlap = overlap(&a, &b);
+        assert_eq!((overlap.common, overlap.holdings_a, overlap.holdings_b), (2, 3, 3));
+        close(overlap.weight_overlap, 70.0);
+        close(overlap.active_share, 30.0);
+        assert_eq!(overlap.shared[0].name, "Apple");
+        close(overlap.shared[0].weight_a, 50.0);
+        close(overlap.shared[0].weight_b, 40.0);
+        close(overlap.common_share_a(), 200.0 / 3.0);
+    }
+
+    #[test]
+    fn weights_are_scaled_to_100() {
+        // Weights published as fractions, or not adding up to 100, compare the same
+        let a = ETF::for_test("IE00B4L5Y983", vec![holding("Apple", Some("US0378331005"), 0.5), holding("Cash", None, 0.5)]);
+        let b = ETF::for_test("IE00B5BMR087", vec![holding("Apple", Some("US0378331005"), 25.0)]);
+        close(overlap(&a, &b).weight_overlap, 50.0);
+    }
+
+    #[test]
+    fn disjoint_funds_do_not_overlap() {
+        let a = ETF::for_test("IE00B4L5Y983", vec![holding("Apple", Some("US0378331005"), 100.0)]);
+        let b = ETF::for_test("IE00B5BMR087", vec![holding("Toyota", Some("JP3633400001"), 100.0)]);
+
+        let overlap = overlap(&a, &b);
+        assert_eq!(overlap.common, 0);
+        assert!(overlap.shared.is_empty());
+        close(overlap.weight_overlap, 0.0);
+        close(overlap.active_share, 100.0);
+    }
+
+    #[test]
+    fn positions_without_an_isin_match_by_name() {
+        let a = ETF::for_test("IE00B4L5Y983", vec![holding("USD Cash", None, 10.0), holding("Apple", Some("US0378331005"), 90.0)]);
+        let b = ETF::for_test("IE00B5BMR087", vec![holding("usd cash", None, 5.0), holding("Toyota", Some("JP3633400001"), 95.0)]);
+
+        let overlap = overlap(&a, &b);
+        assert_eq!(overlap.common, 1);
+        assert_eq!(overlap.shared[0].isin, None);
+        close(overlap.weight_overlap, 5.0);
+    }
+
+    #[test]
+    fn pairwise_skips_funds_without_holdings() {
+        let a = ETF::for_test("IE00B4L5Y983", vec![holding("Apple", Some("US0378331005"), 100.0)]);
+        let b = ETF::for_test("IE00B5BMR087", vec![holding("Apple", Some("US0378331005"), 100.0)]);
+        let c = ETF::for_test("IE00BKM4GZ66", Vec::new());
+        let d = ETF::for_test("IE00B3RBWM25", vec![holding("Toyota", Some("JP3633400001"), 100.0)]);
+
+        let pairs: Vec<(String, String)> = pairwise(&[&a, &b, &c, &d]).into_iter()
+            .map(|overlap| (overlap.fund_a, overlap.fund_b))
+            .collect();
+        assert_eq!(pairs, [
+            ("IE00B4L5Y983".to_string(), "IE00B5BMR087".to_string()),
+            ("IE00B4L5Y983".to_string(), "IE00B3RBWM25".to_string()),
+            ("IE00B5BMR087".to_string(), "IE00B3RBWM25".to_string()),
+        ]);
+    }
+}
@@ -0,0 +1,277 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use chrono::{Datelike, Months, NaiveDate};
+use serde::{Deserialize, Serialize};
+use crate::schema::Record;
+use crate::utils;
+
+// Cumulative return periods, in percent over the whole period (not annualised)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Period {
+    OneMonth,
+    ThreeMonths,
+    SixMonths,
+    YearToDate,
+    OneYear,
+    ThreeYears,
+    FiveYears,
+    TenYears,
+    SinceInception,
+}
+
+impl Period {
+    pub const ALL: [Period; 9] = [
+        Period::OneMonth,
+        Period::ThreeMonths,
+        Period::SixMonths,
+        Period::YearToDate,
+        Period::OneYear,
+        Period::ThreeYears,
+        Period::FiveYears,
+        Period::TenYears,
+        Period::SinceInception,
+    ];
+
+    // Record field holding the fund return, e.g. "performance_1y"
+    pub fn field(self) -> &'static str {
+        match self {
+            Self::OneMonth => "performance_1m",
+            Self::ThreeMonths => "performance_3m",
+            Self::SixMonths => "performance_6m",
+            Self::YearToDate => "performance_ytd",
+            Self::OneYear => "performance_1y",
+            Self::ThreeYears => "performance_3y",
+            Self::FiveYears => "performance_5y",
+            Self::TenYears => "performance_10y",
+            Self::SinceInception => "performance_inception",
+        }
+    }
+
+    // Record field holding the index return, e.g. "index_performance_1y"
+    pub fn index_field(self) -> &'static str {
+        match self {
+            Self::OneMonth => "index_performance_1m",
+            Self::ThreeMonths => "index_performance_3m",
+            Self::SixMonths => "index_performance_6m",
+            Self::YearToDate => "index_performance_ytd",
+            Self::OneYear => "index_performance_1y",
+            Self::ThreeYears => "index_performance_3y",
+            Self::FiveYears => "index_performance_5y",
+            Self::TenYears => "index_performance_10y",
+            Self::SinceInception => "index_performance_inception",
+        }
+    }
+
+    // Start of the period ending on `end`, None since inception
+    pub fn start(self, end: NaiveDate) -> Option<NaiveDate> {
+        let months = |n: u32| end.checked_sub_months(Months::new(n));
+        match self {
+            Self::OneMonth => months(1),
+            Self::ThreeMonths => months(3),
+            Self::SixMonths => months(6),
+            Self::YearToDate => NaiveDate::from_ymd_opt(end.year() - 1, 12, 31),
+            Self::OneYear => months(12),
+            Self::ThreeYears => months(36),
+            Self::FiveYears => months(60),
+            Self::TenYears => months(120),
+            Self::SinceInception => None,
+        }
+    }
+}
+
+impl fmt::Display for Period {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let label = match self {
+            Self::OneMonth => "1M",
+            Self::ThreeMonths => "3M",
+            Self::SixMonths => "6M",
+            Self::YearToDate => "YTD",
+            Self::OneYear => "1Y",
+            Self::ThreeYears => "3Y",
+            Self::FiveYears => "5Y",
+            Self::TenYears => "10Y",
+            Self::SinceInception => "Since Inception",
+        };
+        write!(f, "{}", label)
+    }
+}
+
+// Return over a fixed past period: a calendar year ("2023") or a rolling year ("2/23-2/24")
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DiscreteReturn {
+    pub label: String,
+    pub start: NaiveDate, // Return measured from the close of this day
+    pub end: NaiveDate,   // to the close of this day
+    pub value: f64,       // Percent
+}
+
+// Prefix of the record fields holding discrete returns, followed by the column header
+pub const DISCRETE_PREFIX: &str = "performance_discrete:";
+pub const INDEX_DISCRETE_PREFIX: &str = "index_performance_discrete:";
+
+fn last_day_of_month(year: i32, month: u32) -> Option<NaiveDate> {
+    let (year, month) = if month == 12 { (year + 1, 1) } else { (year, month + 1) };
+    NaiveDate::from_ymd_opt(year, month, 1)?.pred_opt()
+}
+
+// "2/20" -> (2020, 2)
+fn month_year(text: &str) -> Option<(i32, u32)> {
+    let (month, year) = text.trim().split_once('/')?;
+    let month: u32 = month.trim().parse().ok().filter(|month| (1..=12).contains(month))?;
+    let year: i32 = year.trim().parse().ok()?;
+    let year = if year < 100 { 2000 + year } else { year };
+    Some((year, month))
+}
+
+// Period measured by a discrete return column: "2/20-2/21 (%)" runs from the end of February 2020
+// to the end of February 2021, "2023 (%)" or "Calendar year 2023" over the year 2023.
+// Returns the label and the start and end dates.
+pub fn discrete_period(header: &str) -> Option<(String, NaiveDate, NaiveDate)> {
+    let label = header.replace("(%)", "").replace('%', "");
+    let label = label.trim();
+
+    if let Some((from, to)) = label.split_once('-') {
+        let (start_year, start_month) = month_year(from)?;
+        let (end_year, end_month) = month_year(to)?;
+        let start = last_day_of_month(start_year, start_month)?;
+        let end = last_day_of_month(end_year, end_month)?;
+        return (start < end).then(|| (label.to_string(), start, end));
+    }
+
+    // A single year, possibly after words
+    let words: Vec<&str> = label.split_whitespace().collect();
+    let (year, rest) = words.split_last()?;
+    if !rest.iter().all(|word| word.chars().all(char::is_alphabetic)) || year.len() != 4 {
+        return None;
+    }
+    let year: i32 = year.parse().ok().filter(|year| (1900..=2100).contains(year))?;
+    Some((year.to_string(), NaiveDate::from_ymd_opt(year - 1, 12, 31)?, NaiveDate::from_ymd_opt(year, 12, 31)?))
+}
+
+// Returns of the fund or of its index
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Returns {
+    pub cumulative: BTreeMap<Period, f64>,
+    pub discrete: Vec<DiscreteReturn>, // Oldest first
+}
+
+impl Returns {
+    fn from_record(record: &Record, field: impl Fn(Period) -> &'static str, discrete_prefix: &str) -> Self {
+        let cumulative = Period::ALL.iter()
+            .filter_map(|&period| {
+                let value = record.get(field(period)).and_then(|value| utils::parse_number(value))?;
+                Some((period, value))
+            })
+            .collect();
+
+        let mut discrete: Vec<DiscreteReturn> = record.iter()
+            .filter_map(|(key, value)| {
+                let (label, start, end) = discrete_period(key.strip_prefix(discrete_prefix)?)?;
+                Some(DiscreteReturn { label, start, end, value: utils::parse_number(value)? })
+            })
+            .collect();
+        discrete.sort_by_key(|entry| entry.end);
+
+        Self { cumulative, discrete }
+    }
+
+    pub fn get(&self, period: Period) -> Option<f64> {
+        self.cumulative.get(&period).copied()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cumulative.is_empty() && self.discrete.is_empty()
+    }
+
+    // "2/23-2/24: 12.30%; 2/24-2/25: 8.10%"
+    pub fn discrete_summary(&self) -> String {
+        self.discrete.iter()
+            .map(|entry| format!("{}: {:.2}%", entry.label, entry.value))
+            .collect::<Vec<_>>()
+            .join("; ")
+    }
+}
+
+// Published returns of a fund and of the index it tracks
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Performance {
+    pub fund: Returns,
+    pub index: Returns,
+}
+
+impl Performance {
+    pub fn from_record(record: &Record) -> Self {
+        Self {
+            fund: Returns::from_record(record, Period::field, DISCRETE_PREFIX),
+            index: Returns::from_record(record, Period::index_field, INDEX_DISCRETE_PREFIX),
+        }
+    }
+
+    pub fn fund(&self, period: Period) -> Option<f64> {
+        self.fund.get(period)
+    }
+
+    pub fn index(&self, period: Period) -> Option<f64> {
+        self.index.get(period)
+    }
+
+    // Fund return minus index return, in percentage points
+    pub fn tracking_difference(&self, period: Period) -> Option<f64> {
+        Some(self.fund(period)? - self.index(period)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn day(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    #[test]
+    fn reads_discrete_periods_from_headers() {
+        assert_eq!(discrete_period("2/20-2/21 (%)"), Some(("2/20-2/21".to_string(), day(2020, 2, 29), day(2021, 2, 28))));
+        assert_eq!(discrete_period("12/23 - 12/24"), Some(("12/23 - 12/24".to_string(), day(2023, 12, 31), day(2024, 12, 31))));
+        assert_eq!(discrete_period("2023 (%)"), Some(("2023".to_string(), day(2022, 12, 31), day(2023, 12, 31))));
+        assert_eq!(discrete_period("Calendar year 2023"), Some(("2023".to_string(), day(2022, 12, 31), day(2023, 12, 31))));
+
+        for header in ["YTD (%)", "1y (%)", "2/21-2/20 (%)", "13/20-13/21", "123 (%)", "Top 10 2023"] {
+            assert_eq!(discrete_period(header), None, "{}", header);
+        }
+    }
+
+    #[test]
+    fn reads_returns_from_a_record() {
+        let record: Record = [
+            ("performance_1y", "12.5"),
+            ("performance_ytd", "-"),
+            ("index_performance_1y", "12.9"),
+            ("performance_discrete:2/23-2/24", "8.1"),
+            ("performance_discrete:2/22-2/23", "-3.2%"),
+            ("performance_discrete:Total", "1"),
+            ("index_performance_discrete:2/23-2/24", "8.4"),
+        ]
+        .into_iter()
+        .map(|(field, value)| (field.to_string(), value.to_string()))
+        .collect();
+
+        let performance = Performance::from_record(&record);
+        assert_eq!(performance.fund(Period::OneYear), Some(12.5));
+        assert_eq!(performance.fund(Period::YearToDate), None);
+        assert!((performance.tracking_difference(Period::OneYear).unwrap() + 0.4).abs() < 1e-9);
+        assert_eq!(performance.fund.discrete_summary(), "2/22-2/23: -3.20%; 2/23-2/24: 8.10%");
+        assert_eq!(performance.index.discrete.len(), 1);
+    }
+
+    #[test]
+    fn periods_start_on_the_same_day_or_the_month_end() {
+        assert_eq!(Period::OneMonth.start(day(2024, 3, 31)), Some(day(2024, 2, 29)));
+        assert_eq!(Period::ThreeMonths.start(day(2023, 5, 31)), Some(day(2023, 2, 28)));
+        assert_eq!(Period::SixMonths.start(day(2024, 8, 15)), Some(day(2024, 2, 15)));
+        assert_eq!(Period::OneYear.start(day(2024, 2, 29)), Some(day(2023, 2, 28)));
+        assert_eq!(Period::FiveYears.start(day(2029, 2, 28)), Some(day(2024, 2, 28)));
+        assert_eq!(Period::YearToDate.start(day(2024, 1, 1)), Some(day(2023, 12, 31)));
+        assert_eq!(Period::SinceInception.start(day(2024, 1, 1)), None);
+    }
+}
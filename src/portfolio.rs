@@ -0,0 +1,243 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use color_eyre::{Result, eyre::eyre, eyre::WrapErr};
+use serde::Deserialize;
+use crate::etf::{HoldingsBasis, ETF};
+use crate::holdings_diff::{normalized, positions};
+
+const DEFAULT_FILE: &str = "portfolio.toml";
+
+// One fund of a portfolio, given by ISIN or ticker, with either an amount invested or a weight
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PortfolioPosition {
+    pub fund: String,
+    pub amount: Option<f64>, // In one currency for the whole portfolio
+    pub weight: Option<f64>, // Percent, scaled to add up to 100
+}
+
+// Funds held, read from a file like:
+//
+//   [[position]]
+//   fund = "IE00B4L5Y983"
+//   amount = 10000
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Portfolio {
+    #[serde(default, rename = "position")]
+    pub positions: Vec<PortfolioPosition>,
+}
+
+impl Portfolio {
+    pub fn from_toml(text: &str) -> Result<Self> {
+        let portfolio: Self = toml::from_str(text).wrap_err("Invalid portfolio")?;
+        portfolio.weights()?;
+        Ok(portfolio)
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .wrap_err_with(|| format!("Failed to read portfolio {}", path.display()))?;
+        Self::from_toml(&text)
+            .wrap_err_with(|| format!("Invalid portfolio {}", path.display()))
+    }
+
+    // Portfolio in ETF_TOOL_PORTFOLIO, portfolio.toml by default
+    pub fn from_env() -> Result<Self> {
+        let path = std::env::var("ETF_TOOL_PORTFOLIO").unwrap_or_else(|_| DEFAULT_FILE.to_string());
+        Self::from_file(Path::new(&path))
+    }
+
+    // Weight of each position in percent, in the order given. Amounts and weights can't be mixed.
+    pub fn weights(&self) -> Result<Vec<f64>> {
+        let values: Vec<f64> = self.positions.iter()
+            .map(|position| match (position.amount, position.weight) {
+                (Some(value), None) | (None, Some(value)) if value >= 0.0 => Ok(value),
+                (Some(_), Some(_)) => Err(eyre!("{} has both an amount and a weight", position.fund)),
+                (None, None) => Err(eyre!("{} has no amount or weight", position.fund)),
+                _ => Err(eyre!("{} has a negative amount or weight", position.fund)),
+            })
+            .collect::<Result<_>>()?;
+        let amounts = self.positions.iter().filter(|position| position.amount.is_some()).count();
+        if amounts != 0 && amounts != self.positions.len() {
+            return Err(eyre!("Give every position an amount or every position a weight, not both"));
+        }
+
+        let total: f64 = values.iter().sum();
+        if total <= 0.0 {
+            return Err(eyre!("The portfolio is empty"));
+        }
+        Ok(values.iter().map(|value| value / total * 100.0).collect())
+    }
+}
+
+// Portfolio weight of one underlying position
+#[derive(Debug, Clone, PartialEq)]
+pub struct Exposure {
+    pub name: String,
+    pub isin: Option<String>,
+    pub weight: f64,  // Percent of the whole portfolio
+    pub funds: usize, // Number of funds holding it
+}
+
+// Why a fund could not be looked through
+#[derive(Debug, Clone, PartialEq)]
+pub enum Gap {
+    UnknownFund,      // Code matches no fund in the catalog
+    NoHoldings,       // Holdings not loaded
+    SubstituteBasket, // Looked through, but the holdings are a swap collateral basket
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlaggedFund {
+    pub fund: String, // Code as given in the portfolio
+    pub name: Option<String>,
+    pub weight: f64,
+    pub gap: Gap,
+}
+
+// Underlying exposure of a portfolio. Weights are percent of the whole portfolio, so they add
+// up to `covered` rather than 100 when some funds could not be looked through.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LookThrough {
+    pub positions: Vec<Exposure>, // Largest first
+    pub countries: Vec<(String, f64)>,
+    pub sectors: Vec<(String, f64)>,
+    pub currencies: Vec<(String, f64)>,
+    pub securities: usize, // Distinct positions, with or without an ISIN
+    pub covered: f64,      // Weight of the funds looked through
+    pub flagged: Vec<FlaggedFund>,
+}
+
+impl LookThrough {
+    // Largest single positions, up to `count`
+    pub fn top_positions(&self, count: usize) -> &[Exposure] {
+        &self.positions[..count.min(self.positions.len())]
+    }
+}
+
+fn sorted(breakdown: BTreeMap<String, f64>) -> Vec<(String, f64)> {
+    let mut breakdown: Vec<(String, f64)> = breakdown.into_iter().collect();
+    breakdown.sort_by(|a, b| b.1.total_cmp(&a.1));
+    breakdown
+}
+
+// Aggregate the holdings of the funds in `portfolio`, found in `etfs` by ISIN or ticker.
+// Positions are matched across funds as in `holdings_diff`: by ISIN, then ticker, then name.
+pub fn look_through(portfolio: &Portfolio, etfs: &[ETF]) -> Result<LookThrough> {
+    let weights = portfolio.weights()?;
+
+    let mut exposures: BTreeMap<String, Exposure> = BTreeMap::new();
+    let mut countries = BTreeMap::new();
+    let mut sectors = BTreeMap::new();
+    let mut currencies = BTreeMap::new();
+    let mut result = LookThrough::default();
+
+    for (position, fund_weight) in portfolio.positions.iter().zip(weights) {
+        let flag = |gap: Gap, name: Option<&String>| FlaggedFund {
+            fund: position.fund.clone(),
+            name: name.cloned(),
+            weight: fund_weight,
+            gap,
+        };
+        let Some(etf) = etfs.iter().find(|etf| etf.has_code(&position.fund)) else {
+            result.flagged.push(flag(Gap::UnknownFund, None));
+            continue;
+        };
+        let total: f64 = etf.holdings.iter().map(|holding| holding.weight).sum();
+        if etf.holdings.is_empty() || total <= 0.0 {
+            result.flagged.push(flag(Gap::NoHoldings, Some(&etf.name)));
+            continue;
+        }
+        if etf.holdings_basis == HoldingsBasis::SubstituteBasket {
+            result.flagged.push(flag(Gap::SubstituteBasket, Some(&etf.name)));
+        }
+        result.covered += fund_weight;
+
+        // Weights scaled so each fund's holdings add up to its weight in the portfolio
+        let mut fund_positions = positions(&etf.holdings);
+        normalized(&mut fund_positions);
+        for (key, (name, isin, weight)) in fund_positions {
+            let exposure = exposures.entry(key).or_insert_with(|| Exposure { name, isin, weight: 0.0, funds: 0 });
+            exposure.weight += weight * fund_weight / 100.0;
+            exposure.funds += 1;
+        }
+
+        for holding in &etf.holdings {
+            let weight = holding.weight / total * fund_weight;
+            let label = |value: &Option<String>| value.clone().unwrap_or_else(|| "Unknown".to_string());
+            *countries.entry(label(&holding.country)).or_insert(0.0) += weight;
+            *sectors.entry(label(&holding.sector)).or_insert(0.0) += weight;
+            *currencies.entry(label(&holding.currency)).or_insert(0.0) += weight;
+        }
+    }
+
+    result.securities = exposures.len();
+    result.positions = exposures.into_values().collect();
+    result.positions.sort_by(|a, b| b.weight.total_cmp(&a.weight));
+    result.countries = sorted(countries);
+    result.sectors = sorted(sectors);
+    result.currencies = sorted(currencies);
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::etf::Holding;
+    use crate::utils::assert_close as close;
+
+    fn holding(name: &str, isin: Option<&str>, country: &str, weight: f64) -> Holding {
+        Holding {
+            name: name.to_string(),
+            isin: isin.map(str::to_string),
+            country: Some(country.to_string()),
+            weight,
+            ..Holding::default()
+        }
+    }
+
+    #[test]
+    fn aggregates_positions_across_funds() {
+        let etfs = [
+            ETF::for_test("IE00B4L5Y983", vec![
+                holding("Apple", Some("US0378331005"), "US", 50.0),
+                holding("Microsoft", Some("US5949181045"), "US", 50.0),
+            ]),
+            ETF::for_test("IE00B5BMR087", vec![
+                holding("Apple", Some("US0378331005"), "US", 25.0),
+                holding("Toyota", Some("JP3633400001"), "JP", 65.0),
+                holding("JPY Cash", None, "JP", 10.0),
+            ]),
+            ETF::for_test("IE00BKM4GZ66", Vec::new()),
+        ];
+        let portfolio = Portfolio::from_toml(
+            "[[position]]\nfund = \"IE00B4L5Y983\"\namount = 6000\n\
+             [[position]]\nfund = \"IE00B5BMR087\"\namount = 2000\n\
+             [[position]]\nfund = \"IE00BKM4GZ66\"\namount = 2000\n\
+             [[position]]\nfund = \"XX\"\namount = 0\n",
+        ).unwrap();
+
+        let exposure = look_through(&portfolio, &etfs).unwrap();
+        // Cash has no ISIN but is a position of its own
+        assert_eq!(exposure.securities, 4);
+        close(exposure.covered, 80.0);
+        assert_eq!(exposure.positions[0].name, "Apple");
+        assert_eq!(exposure.positions[0].funds, 2);
+        close(exposure.positions[0].weight, 35.0);
+        assert_eq!(exposure.countries[0].0, "US");
+        close(exposure.countries[0].1, 65.0);
+        assert_eq!(exposure.countries[1].0, "JP");
+        close(exposure.countries[1].1, 15.0);
+
+        let gaps: Vec<(&str, &Gap)> = exposure.flagged.iter().map(|fund| (fund.fund.as_str(), &fund.gap)).collect();
+        assert_eq!(gaps, [("IE00BKM4GZ66", &Gap::NoHoldings), ("XX", &Gap::UnknownFund)]);
+    }
+
+    #[test]
+    fn rejects_mixed_amounts_and_weights() {
+        assert!(Portfolio::from_toml("[[position]]\nfund = \"a\"\namount = 1\nweight = 2\n").is_err());
+        assert!(Portfolio::from_toml("[[position]]\nfund = \"a\"\namount = 1\n[[position]]\nfund = \"b\"\nweight = 2\n").is_err());
+        assert!(Portfolio::from_toml("").is_err());
+    }
+}
@@ -0,0 +1,393 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use chrono::NaiveDate;
+use color_eyre::{Result, eyre::eyre, eyre::WrapErr};
+use serde::{Deserialize, Serialize};
+use crate::dates;
+use crate::fx::FxTable;
+use crate::identifiers::{self, Isin};
+use crate::money;
+use crate::performance::Period;
+use crate::schema::{fields, Schema};
+use crate::utils;
+
+const DEFAULT_DIR: &str = "data/prices";
+
+// Closing NAV or market price of one day
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PricePoint {
+    pub date: NaiveDate,
+    pub price: f64,
+    pub distribution: Option<f64>, // Paid per share, the day is the ex-date
+}
+
+// Daily prices of one fund, oldest first, one point per day
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PriceSeries {
+    pub isin: String,
+    pub currency: Option<String>,
+    pub points: Vec<PricePoint>,
+}
+
+impl PriceSeries {
+    pub fn new(isin: &str, currency: Option<&str>) -> Self {
+        Self {
+            isin: isin.to_string(),
+            currency: currency.map(money::currency_code),
+            points: Vec::new(),
+        }
+    }
+
+    // Add a point, replacing the one of the same day
+    pub fn insert(&mut self, point: PricePoint) {
+        match self.points.binary_search_by_key(&point.date, |existing| existing.date) {
+            Ok(index) => self.points[index] = point,
+            Err(index) => self.points.insert(index, point),
+        }
+    }
+
+    // Add the points of `other`, which wins on days both have
+    pub fn merge(&mut self, other: PriceSeries) {
+        if self.currency.is_none() {
+            self.currency = other.currency;
+        }
+        for point in other.points {
+            self.insert(point);
+        }
+    }
+
+    pub fn first_date(&self) -> Option<NaiveDate> {
+        self.points.first().map(|point| point.date)
+    }
+
+    pub fn last_date(&self) -> Option<NaiveDate> {
+        self.points.last().map(|point| point.date)
+    }
+
+    // Index of the last point on or before `date`
+    fn position_on(&self, date: NaiveDate) -> Option<usize> {
+        self.points.partition_point(|point| point.date <= date).checked_sub(1)
+    }
+
+    // Last known price on or before `date`
+    pub fn point_on(&self, date: NaiveDate) -> Option<&PricePoint> {
+        self.position_on(date).map(|index| &self.points[index])
+    }
+
+    // Total return in percent from the close of `start` to the close of `end`, distributions
+    // reinvested at the price of their ex-date. None if the series does not reach back to `start`.
+    pub fn total_return(&self, start: NaiveDate, end: NaiveDate) -> Option<f64> {
+        let from = self.position_on(start)?;
+        let to = self.position_on(end)?;
+        if to <= from {
+            return None;
+        }
+
+        let growth = self.points[from + 1..=to].iter()
+            .filter_map(|point| point.distribution.map(|amount| 1.0 + amount / point.price))
+            .product::<f64>();
+        Some((self.points[to].price / self.points[from].price * growth - 1.0) * 100.0)
+    }
+
+    // Return over `period` up to `end`, the last price by default
+    pub fn period_return(&self, period: Period, end: Option<NaiveDate>) -> Option<f64> {
+        let end = end.or(self.last_date())?;
+        let start = match period.start(end) {
+            Some(start) => start,
+            None => self.first_date()?,
+        };
+        self.total_return(start, end)
+    }
+
+    // Total return as a yearly rate. Periods shorter than a year are not annualised.
+    pub fn annualised_return(&self, start: NaiveDate, end: NaiveDate) -> Option<f64> {
+        let from = self.point_on(start)?.date;
+        let to = self.point_on(end)?.date;
+        let days = (to - from).num_days();
+        if days < 365 {
+            return None;
+        }
+        let years = days as f64 / 365.25;
+        let total = self.total_return(start, end)? / 100.0;
+        Some(((1.0 + total).powf(1.0 / years) - 1.0) * 100.0)
+    }
+
+    // The series in `currency`, each point converted at the rate of its day. Days before the
+    // first rate are left out rather than converted at a later one. None if the currency of the series is unknown.
+    pub fn in_currency(&self, rates: &FxTable, currency: &str) -> Option<PriceSeries> {
+        let from = self.currency.as_deref()?;
+        let mut converted = PriceSeries::new(&self.isin, Some(currency));
+        converted.points = self.points.iter()
+            .filter_map(|point| {
+                let rate = rates.rate_on(from, currency, point.date)?;
+                Some(PricePoint {
+                    date: point.date,
+                    price: point.price * rate,
+                    distribution: point.distribution.map(|amount| amount * rate),
+                })
+            })
+            .collect();
+        Some(converted)
+    }
+}
+
+fn price_schema() -> Schema {
+    Schema::new("price history")
+        .required(fields::DATE, &["Date", "As Of", "As of date", "NAV Date", "Valuation Date"])
+        .required(fields::PRICE, &["NAV", "NAV per Share", "Close", "Price", "Last", "Adj Close"])
+        .optional(fields::ISIN, &["ISIN"])
+        .optional(fields::CURRENCY, &["Currency", "NAV Currency"])
+        .optional(fields::DISTRIBUTION, &["Distribution", "Ex-Dividends", "Dividend", "Dividends"])
+}
+
+// Parse price rows: a header with at least date and price columns, then one row per day.
+// Rows without an ISIN column belong to `isin`. Rows without a date or a price are skipped, and
+// so are rows whose ISIN is invalid, since the ISIN names the file the prices are saved to.
+pub fn parse_price_rows(rows: &[Vec<String>], isin: Option<&str>, currency: Option<&str>) -> Result<Vec<PriceSeries>> {
+    let (header_row, columns) = price_schema().find_header(rows)?;
+    let isin = isin.map(Isin::parse).transpose()?;
+
+    let mut series: BTreeMap<String, PriceSeries> = BTreeMap::new();
+    let mut invalid = 0;
+    for row in &rows[header_row + 1..] {
+        let Some(date) = columns.get(row, fields::DATE).and_then(dates::parse_date) else { continue };
+        let Some(price) = columns.number(row, fields::PRICE).filter(|price| *price > 0.0) else { continue };
+
+        let row_isin = match columns.text(row, fields::ISIN) {
+            Some(text) => match Isin::parse(&text) {
+                Ok(row_isin) => row_isin,
+                Err(_) => {
+                    invalid += 1;
+                    continue;
+                }
+            },
+            None => isin.clone().ok_or_else(|| eyre!("Price history has no ISIN column and no ISIN was given"))?,
+        };
+        let row_currency = columns.text(row, fields::CURRENCY);
+        let entry = series.entry(row_isin.to_string())
+            .or_insert_with_key(|isin| PriceSeries::new(isin, row_currency.as_deref().or(currency)));
+        entry.insert(PricePoint {
+            date,
+            price,
+            distribution: columns.number(row, fields::DISTRIBUTION).filter(|amount| *amount != 0.0),
+        });
+    }
+    if invalid > 0 {
+        utils::log(&format!("Skipped {} prices with an invalid ISIN", invalid));
+    }
+    Ok(series.into_values().collect())
+}
+
+// Rows of a CSV, XLSX or XML spreadsheet file
+fn read_rows(path: &Path) -> Result<Vec<Vec<String>>> {
+    let is_csv = path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("csv"));
+    if is_csv {
+        let text = std::fs::read_to_string(path)
+            .wrap_err_with(|| format!("Failed to read {}", path.display()))?;
+        utils::read_csv(&text)
+    } else {
+        let path = path.to_str().ok_or_else(|| eyre!("Invalid path {}", path.display()))?;
+        utils::read_sheet(path, None)
+    }
+}
+
+// Price histories of many funds, kept as one `<ISIN>.csv` file per fund
+#[derive(Debug, Clone, Default)]
+pub struct PriceStore {
+    dir: PathBuf,
+    series: BTreeMap<String, PriceSeries>,
+}
+
+impl PriceStore {
+    // Open the store in `dir`, reading the histories saved there. A missing directory is an empty store.
+    pub fn open(dir: &Path) -> Result<Self> {
+        let mut store = Self { dir: dir.to_path_buf(), series: BTreeMap::new() };
+        if !dir.is_dir() {
+            return Ok(store);
+        }
+
+        let entries = std::fs::read_dir(dir)
+            .wrap_err_with(|| format!("Failed to read price histories from {}", dir.display()))?;
+        for path in entries.filter_map(|entry| entry.ok().map(|entry| entry.path())) {
+            let Some(isin) = path.file_stem().and_then(|stem| stem.to_str()).filter(|stem| identifiers::is_valid_isin(stem)) else {
+                continue;
+            };
+            let rows = read_rows(&path)?;
+            for series in parse_price_rows(&rows, Some(isin), None)
+                .wrap_err_with(|| format!("Invalid price history {}", path.display()))?
+            {
+                store.add(series);
+            }
+        }
+        Ok(store)
+    }
+
+    // Store in ETF_TOOL_PRICES_DIR, data/prices by default
+    pub fn from_env() -> Result<Self> {
+        let dir = std::env::var("ETF_TOOL_PRICES_DIR").unwrap_or_else(|_| DEFAULT_DIR.to_string());
+        Self::open(Path::new(&dir))
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn get(&self, isin: &str) -> Option<&PriceSeries> {
+        self.series.get(&isin.to_uppercase())
+    }
+
+    pub fn series(&self) -> impl Iterator<Item = &PriceSeries> {
+        self.series.values()
+    }
+
+    // Add prices, merged with the ones already known for the fund
+    pub fn add(&mut self, series: PriceSeries) {
+        match self.series.get_mut(&series.isin) {
+            Some(existing) => existing.merge(series),
+            None => {
+                self.series.insert(series.isin.clone(), series);
+            }
+        }
+    }
+
+    // Import a price file. A file without an ISIN column is for `isin`, or for the fund its
+    // name is the ISIN of. Returns the number of prices read.
+    pub fn import_file(&mut self, path: &Path, isin: Option<&str>, currency: Option<&str>) -> Result<usize> {
+        let stem = path.file_stem().and_then(|stem| stem.to_str()).filter(|stem| identifiers::is_valid_isin(stem));
+        let rows = read_rows(path)?;
+        let imported = parse_price_rows(&rows, isin.or(stem), currency)
+            .wrap_err_with(|| format!("Invalid price file {}", path.display()))?;
+
+        let count = imported.iter().map(|series| series.points.len()).sum();
+        for series in imported {
+            self.add(series);
+        }
+        Ok(count)
+    }
+
+    // Write every history to `<dir>/<ISIN>.csv`
+    pub fn save(&self) -> Result<()> {
+        std::fs::create_dir_all(&self.dir)
+            .wrap_err_with(|| format!("Failed to create {}", self.dir.display()))?;
+
+        for series in self.series.values() {
+            let path = self.dir.join(format!("{}.csv", series.isin));
+            let mut csv = csv::Writer::from_path(&path)
+                .wrap_err_with(|| format!("Failed to create {}", path.display()))?;
+            csv.write_record(["Date", "Price", "Currency", "Distribution"])?;
+            for point in &series.points {
+                csv.write_record([
+                    point.date.to_string(),
+                    point.price.to_string(),
+                    series.currency.clone().unwrap_or_default(),
+                    point.distribution.map(|amount| amount.to_string()).unwrap_or_default(),
+                ])?;
+            }
+            csv.flush().wrap_err_with(|| format!("Failed to write {}", path.display()))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::assert_close;
+
+    fn rows(lines: &[&str]) -> Vec<Vec<String>> {
+        lines.iter().map(|line| line.split(',').map(str::to_string).collect()).collect()
+    }
+
+    fn day(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    fn series(points: &[(NaiveDate, f64, Option<f64>)]) -> PriceSeries {
+        let mut series = PriceSeries::new("IE00B4L5Y983", Some("USD"));
+        for &(date, price, distribution) in points {
+            series.insert(PricePoint { date, price, distribution });
+        }
+        series
+    }
+
+    #[test]
+    fn reinvests_distributions_on_their_ex_date() {
+        let series = series(&[
+            (day(2024, 1, 2), 100.0, None),
+            (day(2024, 6, 3), 95.0, Some(5.0)),
+            (day(2024, 12, 31), 100.0, None),
+        ]);
+        // The price ends where it started, plus 5 reinvested at 95
+        assert_close(series.total_return(day(2024, 1, 2), day(2024, 12, 31)).unwrap(), 500.0 / 95.0);
+        // The distribution makes up for the fall of the price on the ex-date
+        assert_close(series.total_return(day(2024, 1, 2), day(2024, 6, 3)).unwrap(), 0.0);
+        // From the close of the ex-date, the distribution is not earned
+        assert_close(series.total_return(day(2024, 6, 3), day(2024, 12, 31)).unwrap(), 500.0 / 95.0);
+        // Six months before the end falls on June 30th: the last price on or before it is used
+        assert_close(series.period_return(Period::SixMonths, None).unwrap(), 500.0 / 95.0);
+    }
+
+    #[test]
+    fn no_return_before_the_first_price() {
+        let series = series(&[(day(2024, 1, 2), 100.0, None), (day(2024, 12, 31), 110.0, None)]);
+        assert_eq!(series.total_return(day(2024, 1, 1), day(2024, 12, 31)), None);
+        assert_eq!(series.period_return(Period::OneYear, None), None);
+        assert_eq!(series.total_return(day(2024, 12, 31), day(2024, 12, 31)), None);
+        assert_close(series.period_return(Period::SinceInception, None).unwrap(), 10.0);
+    }
+
+    #[test]
+    fn annualises_periods_of_a_year_or_more() {
+        let series = series(&[
+            (day(2023, 1, 1), 100.0, None),
+            (day(2023, 12, 31), 110.0, None),
+            (day(2024, 1, 1), 110.0, None),
+        ]);
+        // 364 days
+        assert_eq!(series.annualised_return(day(2023, 1, 1), day(2023, 12, 31)), None);
+        // 365 days is a little less than a year of 365.25
+        let annualised = series.annualised_return(day(2023, 1, 1), day(2024, 1, 1)).unwrap();
+        assert_close(annualised, (1.1f64.powf(365.25 / 365.0) - 1.0) * 100.0);
+    }
+
+    #[test]
+    fn converts_each_day_at_its_rate() {
+        let rates = FxTable::from_csv("date,base,quote,rate\n2024-01-02,EUR,USD,1.25\n2024-01-04,EUR,USD,1.0\n").unwrap();
+        let series = series(&[
+            (day(2024, 1, 1), 100.0, None),
+            (day(2024, 1, 2), 100.0, None),
+            (day(2024, 1, 3), 110.0, Some(1.0)),
+            (day(2024, 1, 4), 120.0, None),
+        ]);
+
+        let euros = series.in_currency(&rates, "EUR").unwrap();
+        assert_eq!(euros.currency.as_deref(), Some("EUR"));
+        // No rate is known on the first day, and the second rate applies from the 4th
+        let points: Vec<(NaiveDate, f64, Option<f64>)> = euros.points.iter().map(|point| (point.date, point.price, point.distribution)).collect();
+        assert_eq!(points, [(day(2024, 1, 2), 80.0, None), (day(2024, 1, 3), 88.0, Some(0.8)), (day(2024, 1, 4), 120.0, None)]);
+
+        assert_eq!(PriceSeries::new("IE00B4L5Y983", None).in_currency(&rates, "EUR"), None);
+    }
+
+    #[test]
+    fn skips_rows_with_an_invalid_isin() {
+        let rows = rows(&[
+            "Date,ISIN,NAV",
+            "2024-01-02,IE00B4L5Y983,100",
+            "2024-01-02,../x,100",
+            "2024-01-02,IE00B4L5Y984,100",
+            "2024-01-03,ie00b4l5y983,101",
+        ]);
+        let series = parse_price_rows(&rows, None, Some("USD")).unwrap();
+        assert_eq!(series.len(), 1);
+        assert_eq!(series[0].isin, "IE00B4L5Y983");
+        assert_eq!(series[0].points.len(), 2);
+    }
+
+    #[test]
+    fn rejects_an_invalid_isin_argument() {
+        let rows = rows(&["Date,NAV", "2024-01-02,100"]);
+        assert!(parse_price_rows(&rows, Some("../x"), None).is_err());
+        assert_eq!(parse_price_rows(&rows, Some("IE00B4L5Y983"), None).unwrap()[0].isin, "IE00B4L5Y983");
+    }
+}
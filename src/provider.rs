@@ -0,0 +1,178 @@
+use std::path::Path;
+use chrono::NaiveDate;
+use color_eyre::Result;
+use crate::definition::{self, DefinitionProvider};
+use crate::etf::ETF;
+use crate::identifiers;
+use crate::invesco::InvescoProvider;
+use crate::ishares::ISharesProvider;
+use crate::prices::PriceSeries;
+use crate::xtrackers::XtrackersProvider;
+
+// What an issuer provider is able to do
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities {
+    pub list_etfs: bool,
+    pub holdings: bool,
+    pub price_history: bool,
+}
+
+// Outcome of a holdings request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HoldingsStatus {
+    Loaded(usize), // Number of holdings loaded
+    Unsupported,   // The issuer does not provide holdings
+}
+
+// A source of ETF data for a single issuer.
+// Adding an issuer means implementing this trait and registering it in a `ProviderRegistry`.
+pub trait IssuerProvider: Send + Sync {
+    // Issuer name, matched against `ETF::issuer`
+    fn name(&self) -> &str;
+
+    fn capabilities(&self) -> Capabilities;
+
+    // List all funds offered by the issuer
+    fn list_etfs(&self) -> Result<Vec<ETF>>;
+
+    // Fill `etf.holdings`. Providers without holdings support keep the default.
+    fn load_holdings(&self, _etf: &mut ETF) -> Result<HoldingsStatus> {
+        Ok(HoldingsStatus::Unsupported)
+    }
+
+    // Daily NAV history published by the issuer, None if it publishes none
+    fn load_price_history(&self, _etf: &ETF) -> Result<Option<PriceSeries>> {
+        Ok(None)
+    }
+}
+
+// Outcome of listing the funds of one issuer
+#[derive(Debug, Clone)]
+pub struct IssuerReport {
+    pub issuer: String,
+    pub etf_count: usize,
+    pub as_of: Option<NaiveDate>, // Latest date the issuer's figures are valid for
+    pub issues: Vec<String>,      // Malformed or conflicting identifiers, the funds are still loaded
+    pub error: Option<String>,
+}
+
+impl IssuerReport {
+    pub fn is_ok(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+// Funds of every issuer that could be loaded, with a report per issuer
+#[derive(Debug, Default)]
+pub struct CatalogLoad {
+    pub etfs: Vec<ETF>,
+    pub reports: Vec<IssuerReport>,
+}
+
+impl CatalogLoad {
+    pub fn failures(&self) -> impl Iterator<Item = &IssuerReport> {
+        self.reports.iter().filter(|report| !report.is_ok())
+    }
+}
+
+#[derive(Default)]
+pub struct ProviderRegistry {
+    providers: Vec<Box<dyn IssuerProvider>>,
+}
+
+impl ProviderRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Registry with all issuers shipped with the crate
+    pub fn with_builtin() -> Self {
+        let mut registry = Self::new();
+        registry.register(XtrackersProvider);
+        registry.register(ISharesProvider);
+        registry.register(InvescoProvider);
+        registry
+    }
+
+    // Register a provider. A provider with the same name replaces the existing one.
+    pub fn register<P: IssuerProvider + 'static>(&mut self, provider: P) {
+        self.providers.retain(|p| p.name() != provider.name());
+        self.providers.push(Box::new(provider));
+    }
+
+    // Register a `DefinitionProvider` for every issuer definition file in `dir`
+    pub fn load_definitions(&mut self, dir: &Path) -> Result<usize> {
+        let definitions = definition::load_definitions(dir)?;
+        let count = definitions.len();
+        for definition in definitions {
+            self.register(DefinitionProvider::new(definition));
+        }
+        Ok(count)
+    }
+
+    pub fn get(&self, issuer: &str) -> Option<&dyn IssuerProvider> {
+        self.providers.iter()
+            .find(|p| p.name() == issuer)
+            .map(|p| p.as_ref())
+    }
+
+    pub fn providers(&self) -> impl Iterator<Item = &dyn IssuerProvider> {
+        self.providers.iter().map(|p| p.as_ref())
+    }
+
+    // List the funds of every registered issuer, in registration order.
+    // An issuer that fails is reported and skipped; the others are still loaded.
+    pub fn load_catalog(&self) -> CatalogLoad {
+        let mut catalog = CatalogLoad::default();
+        for provider in self.providers() {
+            if !provider.capabilities().list_etfs {
+                continue;
+            }
+            let report = match provider.list_etfs() {
+                Ok(etfs) => {
+                    let etf_count = etfs.len();
+                    let as_of = etfs.iter().filter_map(|etf| etf.as_of).max();
+                    let issues = identifiers::check_etfs(&etfs);
+                    catalog.etfs.extend(etfs);
+                    IssuerReport { issuer: provider.name().to_string(), etf_count, as_of, issues, error: None }
+                }
+                Err(e) => IssuerReport {
+                    issuer: provider.name().to_string(),
+                    etf_count: 0,
+                    as_of: None,
+                    issues: Vec::new(),
+                    error: Some(format!("{:#}", e)),
+                },
+            };
+            catalog.reports.push(report);
+        }
+        catalog
+    }
+
+    // List the funds of every registered issuer, failing if any issuer fails
+    pub fn list_all_etfs(&self) -> Result<Vec<ETF>> {
+        let mut all_etfs = Vec::new();
+        for provider in self.providers() {
+            if provider.capabilities().list_etfs {
+                all_etfs.extend(provider.list_etfs()?);
+            }
+        }
+        Ok(all_etfs)
+    }
+
+    // Load holdings through the provider registered for `etf.issuer`
+    pub fn load_holdings(&self, etf: &mut ETF) -> Result<HoldingsStatus> {
+        match self.get(&etf.issuer) {
+            Some(provider) if provider.capabilities().holdings => provider.load_holdings(etf),
+            _ => Ok(HoldingsStatus::Unsupported),
+        }
+    }
+
+    // Download the NAV history of `etf` through the provider registered for its issuer
+    pub fn load_price_history(&self, etf: &ETF) -> Result<Option<PriceSeries>> {
+        match self.get(&etf.issuer) {
+            Some(provider) if provider.capabilities().price_history => provider.load_price_history(etf),
+            _ => Ok(None),
+        }
+    }
+}
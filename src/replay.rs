@@ -0,0 +1,171 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use color_eyre::{Result, eyre::eyre, eyre::WrapErr};
+use serde::{Deserialize, Serialize};
+use crate::http::{Request, Response, Transport};
+use crate::utils;
+
+const DEFAULT_FIXTURES_DIR: &str = "fixtures";
+
+// How issuer network traffic is handled
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HttpMode {
+    Live,
+    Record(PathBuf), // Go to the network and save every response in the directory
+    Replay(PathBuf), // Serve responses saved by `Record`, never touching the network
+}
+
+impl HttpMode {
+    // ETF_TOOL_HTTP_MODE=live|record|replay, fixtures in ETF_TOOL_FIXTURES_DIR (default "fixtures")
+    pub fn from_env() -> Self {
+        let dir = std::env::var("ETF_TOOL_FIXTURES_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(|_| PathBuf::from(DEFAULT_FIXTURES_DIR));
+
+        match std::env::var("ETF_TOOL_HTTP_MODE").as_deref() {
+            Ok("record") => HttpMode::Record(dir),
+            Ok("replay") => HttpMode::Replay(dir),
+            _ => HttpMode::Live,
+        }
+    }
+
+    // Wrap a network transport according to the mode
+    pub fn wrap(&self, transport: Arc<dyn Transport>) -> Arc<dyn Transport> {
+        match self {
+            HttpMode::Live => transport,
+            HttpMode::Record(dir) => Arc::new(RecordingTransport::new(transport, dir)),
+            HttpMode::Replay(dir) => Arc::new(ReplayTransport::new(dir)),
+        }
+    }
+}
+
+// Metadata of a saved response. The body is stored next to it, as is, in `<name>.body`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Fixture {
+    url: String,
+    status: u16,
+    headers: Vec<(String, String)>,
+}
+
+// FNV-1a, stable across runs and Rust versions
+fn fnv1a(text: &str) -> u64 {
+    text.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+// File name of the fixture of a URL, prefixed with the host for readability
+fn fixture_name(url: &str) -> String {
+    let host = reqwest::Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_string))
+        .unwrap_or_else(|| "local".to_string());
+    format!("{}-{:016x}", host, fnv1a(url))
+}
+
+fn fixture_paths(dir: &Path, url: &str) -> (PathBuf, PathBuf) {
+    let name = fixture_name(url);
+    (dir.join(format!("{}.json", name)), dir.join(format!("{}.body", name)))
+}
+
+// Sends requests through another transport and saves every response
+pub struct RecordingTransport {
+    inner: Arc<dyn Transport>,
+    dir: PathBuf,
+}
+
+impl RecordingTransport {
+    pub fn new(inner: Arc<dyn Transport>, dir: &Path) -> Self {
+        Self { inner, dir: dir.to_path_buf() }
+    }
+
+    fn save(&self, request: &Request, response: &Response) -> Result<()> {
+        std::fs::create_dir_all(&self.dir)
+            .wrap_err_with(|| format!("Failed to create fixtures directory {}", self.dir.display()))?;
+
+        let (metadata_path, body_path) = fixture_paths(&self.dir, &request.url);
+        let fixture = Fixture {
+            url: request.url.clone(),
+            status: response.status,
+            headers: response.headers.clone(),
+        };
+        std::fs::write(&body_path, &response.body).wrap_err("Failed to write fixture body")?;
+        std::fs::write(&metadata_path, serde_json::to_string_pretty(&fixture)?)
+            .wrap_err("Failed to write fixture")?;
+        Ok(())
+    }
+}
+
+impl Transport for RecordingTransport {
+    fn send(&self, request: &Request) -> Result<Response> {
+        let response = self.inner.send(request)?;
+        if let Err(e) = self.save(request, &response) {
+            utils::log(&format!("Failed to record {}: {:#}", request.url, e));
+        }
+        Ok(response)
+    }
+}
+
+// Serves responses saved by a `RecordingTransport`
+pub struct ReplayTransport {
+    dir: PathBuf,
+}
+
+impl ReplayTransport {
+    pub fn new(dir: &Path) -> Self {
+        Self { dir: dir.to_path_buf() }
+    }
+}
+
+impl Transport for ReplayTransport {
+    fn send(&self, request: &Request) -> Result<Response> {
+        let (metadata_path, body_path) = fixture_paths(&self.dir, &request.url);
+        if !metadata_path.exists() {
+            return Err(eyre!("No recorded response for {} in {}", request.url, self.dir.display()));
+        }
+
+        let fixture: Fixture = serde_json::from_str(&std::fs::read_to_string(&metadata_path)?)
+            .wrap_err_with(|| format!("Invalid fixture {}", metadata_path.display()))?;
+        let body = std::fs::read(&body_path)
+            .wrap_err_with(|| format!("Missing fixture body {}", body_path.display()))?;
+
+        Ok(Response {
+            status: fixture.status,
+            headers: fixture.headers,
+            body,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::CannedTransport;
+
+    const URL: &str = "https://issuer.example/funds.xlsx?lang=en";
+
+    fn request(url: &str) -> Request {
+        Request { url: url.to_string(), headers: Vec::new() }
+    }
+
+    #[test]
+    fn replays_recorded_responses() {
+        let dir = utils::test_dir("replay");
+        let canned = CannedTransport::new().with_response(URL, Response {
+            status: 200,
+            headers: vec![("Content-Type".to_string(), "application/vnd.ms-excel".to_string())],
+            body: vec![0x50, 0x4b, 0x03, 0x04],
+        });
+        let recorded = HttpMode::Record(dir.clone()).wrap(Arc::new(canned)).send(&request(URL)).unwrap();
+
+        let replay = HttpMode::Replay(dir.clone()).wrap(Arc::new(CannedTransport::new()));
+        let replayed = replay.send(&request(URL)).unwrap();
+        assert_eq!(replayed.status, recorded.status);
+        assert_eq!(replayed.headers, recorded.headers);
+        assert_eq!(replayed.body, recorded.body);
+
+        let error = replay.send(&request("https://issuer.example/other.xlsx")).unwrap_err();
+        assert!(error.to_string().starts_with("No recorded response for https://issuer.example/other.xlsx"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,337 @@
+use std::collections::{BTreeMap, HashMap};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use crate::fx::Reporting;
+use crate::performance::Period;
+use crate::prices::{PriceSeries, PriceStore};
+
+// How risk metrics are computed
+#[derive(Debug, Clone, PartialEq)]
+pub struct RiskConfig {
+    pub risk_free_rate: f64,       // Annual rate in percent, for Sharpe and Sortino ratios
+    pub benchmark: Option<String>, // ISIN of the fund beta is measured against
+    pub window: Option<Period>,    // Prices used, up to the last one. The whole history if None.
+    pub rolling_window: Period,    // Length of the rolling returns
+}
+
+impl Default for RiskConfig {
+    fn default() -> Self {
+        Self {
+            risk_free_rate: 0.0,
+            benchmark: None,
+            window: Some(Period::ThreeYears),
+            rolling_window: Period::OneYear,
+        }
+    }
+}
+
+impl RiskConfig {
+    // Default config with ETF_TOOL_RISK_FREE_RATE and ETF_TOOL_BENCHMARK applied
+    pub fn from_env() -> Self {
+        let mut config = Self::default();
+        if let Some(rate) = std::env::var("ETF_TOOL_RISK_FREE_RATE").ok().and_then(|rate| rate.trim().parse().ok()) {
+            config.risk_free_rate = rate;
+        }
+        if let Ok(benchmark) = std::env::var("ETF_TOOL_BENCHMARK") {
+            config.benchmark = Some(benchmark.trim().to_uppercase());
+        }
+        config
+    }
+
+    pub fn with_risk_free_rate(mut self, rate: f64) -> Self {
+        self.risk_free_rate = rate;
+        self
+    }
+
+    pub fn with_benchmark(mut self, isin: &str) -> Self {
+        self.benchmark = Some(isin.to_uppercase());
+        self
+    }
+
+    pub fn with_window(mut self, window: Option<Period>) -> Self {
+        self.window = window;
+        self
+    }
+
+    pub fn with_rolling_window(mut self, window: Period) -> Self {
+        self.rolling_window = window;
+        self
+    }
+}
+
+// Spread of the returns over every window of a given length in the history
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RollingSummary {
+    pub window: Period,
+    pub count: usize,
+    pub worst: f64,   // Percent
+    pub average: f64,
+    pub best: f64,
+}
+
+// Percentages are annualised where noted. A metric is None when the history is too short.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RiskMetrics {
+    pub start: Option<NaiveDate>,
+    pub end: Option<NaiveDate>,
+    pub annualised_return: Option<f64>,
+    pub volatility: Option<f64>,         // Annualised standard deviation of returns
+    pub max_drawdown: Option<f64>,       // Largest fall from a previous high, as a negative percent
+    pub downside_deviation: Option<f64>, // Annualised deviation of returns below the risk-free rate
+    pub sharpe: Option<f64>,
+    pub sortino: Option<f64>,
+    pub beta: Option<f64>,               // Against `RiskConfig::benchmark`
+    pub rolling: Option<RollingSummary>,
+}
+
+// Value of one unit invested at the first price, distributions reinvested, by date
+pub fn total_return_index(series: &PriceSeries) -> BTreeMap<NaiveDate, f64> {
+    let mut index = BTreeMap::new();
+    let mut value = 1.0;
+    let mut previous: Option<f64> = None;
+    for point in &series.points {
+        if let Some(previous) = previous {
+            value *= (point.price + point.distribution.unwrap_or_default()) / previous;
+        }
+        index.insert(point.date, value);
+        previous = Some(point.price);
+    }
+    index
+}
+
+// Returns from one date of `index` to the next, as fractions, dated by the later day
+fn period_returns(index: &BTreeMap<NaiveDate, f64>) -> Vec<(NaiveDate, f64)> {
+    index.iter()
+        .zip(index.iter().skip(1))
+        .map(|((_, previous), (date, value))| (*date, value / previous - 1.0))
+        .collect()
+}
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+// Sample standard deviation
+fn std_dev(values: &[f64]) -> Option<f64> {
+    if values.len() < 2 {
+        return None;
+    }
+    let mean = mean(values);
+    let variance = values.iter().map(|value| (value - mean).powi(2)).sum::<f64>() / (values.len() - 1) as f64;
+    Some(variance.sqrt())
+}
+
+// Part of `index` from `window` before its last date
+fn windowed(index: BTreeMap<NaiveDate, f64>, window: Option<Period>) -> BTreeMap<NaiveDate, f64> {
+    let start = window.zip(index.keys().next_back()).and_then(|(window, last)| window.start(*last));
+    match start {
+        // Keep the last value on or before the start, so the first return starts there
+        Some(start) => {
+            let from = index.range(..=start).next_back().map_or(start, |(date, _)| *date);
+            index.range(from..).map(|(date, value)| (*date, *value)).collect()
+        }
+        None => index,
+    }
+}
+
+// Returns over every `window` ending on a date of the history, in percent
+pub fn rolling_returns(series: &PriceSeries, window: Period) -> Vec<(NaiveDate, f64)> {
+    let index = total_return_index(series);
+    index.iter()
+        .filter_map(|(date, value)| {
+            let start = window.start(*date)?;
+            let (first, _) = index.first_key_value()?;
+            if start < *first {
+                return None;
+            }
+            let (_, start_value) = index.range(..=start).next_back()?;
+            Some((*date, (value / start_value - 1.0) * 100.0))
+        })
+        .collect()
+}
+
+fn rolling_summary(series: &PriceSeries, window: Period) -> Option<RollingSummary> {
+    let returns: Vec<f64> = rolling_returns(series, window).into_iter().map(|(_, value)| value).collect();
+    if returns.is_empty() {
+        return None;
+    }
+    Some(RollingSummary {
+        window,
+        count: returns.len(),
+        worst: returns.iter().copied().fold(f64::INFINITY, f64::min),
+        average: mean(&returns),
+        best: returns.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+    })
+}
+
+// Slope of the fund's returns on the benchmark's, over the days both have a price
+fn beta(index: &BTreeMap<NaiveDate, f64>, benchmark: &BTreeMap<NaiveDate, f64>) -> Option<f64> {
+    let common = |index: &BTreeMap<NaiveDate, f64>, other: &BTreeMap<NaiveDate, f64>| -> BTreeMap<NaiveDate, f64> {
+        index.iter().filter(|(date, _)| other.contains_key(date)).map(|(date, value)| (*date, *value)).collect()
+    };
+    // Both sides have the same dates, so their returns line up
+    let pairs: Vec<(f64, f64)> = period_returns(&common(index, benchmark)).into_iter()
+        .zip(period_returns(&common(benchmark, index)))
+        .map(|((_, fund), (_, benchmark))| (fund, benchmark))
+        .collect();
+    if pairs.len() < 2 {
+        return None;
+    }
+    let fund_mean = mean(&pairs.iter().map(|(value, _)| *value).collect::<Vec<_>>());
+    let benchmark_mean = mean(&pairs.iter().map(|(_, value)| *value).collect::<Vec<_>>());
+    let covariance: f64 = pairs.iter().map(|(a, b)| (a - fund_mean) * (b - benchmark_mean)).sum();
+    let variance: f64 = pairs.iter().map(|(_, b)| (b - benchmark_mean).powi(2)).sum();
+    (variance > 0.0).then(|| covariance / variance)
+}
+
+// Risk metrics of a price history. Returns are annualised with the observed number of prices
+// per year, so daily, weekly and monthly histories all work.
+pub fn compute(series: &PriceSeries, benchmark: Option<&PriceSeries>, config: &RiskConfig) -> RiskMetrics {
+    let index = windowed(total_return_index(series), config.window);
+    let (Some((&start, &first)), Some((&end, &last))) = (index.first_key_value(), index.last_key_value()) else {
+        return RiskMetrics::default();
+    };
+
+    let returns: Vec<f64> = period_returns(&index).into_iter().map(|(_, value)| value).collect();
+    let years = (end - start).num_days() as f64 / 365.25;
+    let mut metrics = RiskMetrics {
+        start: Some(start),
+        end: Some(end),
+        rolling: rolling_summary(series, config.rolling_window),
+        ..RiskMetrics::default()
+    };
+    if returns.len() < 2 || years <= 0.0 {
+        return metrics;
+    }
+
+    let per_year = returns.len() as f64 / years;
+    let risk_free = config.risk_free_rate / 100.0;
+    let risk_free_per_period = (1.0 + risk_free).powf(1.0 / per_year) - 1.0;
+
+    let annualised_return = (last / first).powf(1.0 / years) - 1.0;
+    let volatility = std_dev(&returns).map(|deviation| deviation * per_year.sqrt());
+    let shortfalls: Vec<f64> = returns.iter().map(|value| (value - risk_free_per_period).min(0.0).powi(2)).collect();
+    let downside_deviation = mean(&shortfalls).sqrt() * per_year.sqrt();
+
+    let mut peak = f64::MIN;
+    let max_drawdown = index.values()
+        .map(|value| {
+            peak = peak.max(*value);
+            value / peak - 1.0
+        })
+        .fold(0.0, f64::min);
+
+    let excess = annualised_return - risk_free;
+    metrics.annualised_return = Some(annualised_return * 100.0);
+    metrics.volatility = volatility.map(|volatility| volatility * 100.0);
+    metrics.max_drawdown = Some(max_drawdown * 100.0);
+    metrics.downside_deviation = Some(downside_deviation * 100.0);
+    metrics.sharpe = volatility.filter(|volatility| *volatility > 0.0).map(|volatility| excess / volatility);
+    metrics.sortino = (downside_deviation > 0.0).then(|| excess / downside_deviation);
+    metrics.beta = benchmark.and_then(|benchmark| beta(&index, &windowed(total_return_index(benchmark), config.window)));
+    metrics
+}
+
+// Metrics of every fund in `store`, by ISIN. With a reporting currency, prices are converted
+// first so funds listed in different currencies compare on the same footing; funds whose prices
+// cannot be converted (currency unknown) are left out rather than mixed in unconverted.
+pub fn compute_store(store: &PriceStore, config: &RiskConfig, reporting: Option<&Reporting>) -> HashMap<String, RiskMetrics> {
+    let convert = |series: &PriceSeries| match reporting {
+        Some(reporting) => series.in_currency(&reporting.rates, &reporting.currency),
+        None => Some(series.clone()),
+    };
+    let benchmark = config.benchmark.as_deref().and_then(|isin| store.get(isin)).and_then(convert);
+
+    store.series()
+        .filter_map(|series| Some((series.isin.clone(), compute(&convert(series)?, benchmark.as_ref(), config))))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fx::FxTable;
+    use crate::prices::PricePoint;
+
+    // Yearly prices over exactly four years of 365.25 days, so each return is one year's
+    fn series(isin: &str, currency: Option<&str>, prices: [f64; 5]) -> PriceSeries {
+        let mut series = PriceSeries::new(isin, currency);
+        for (year, price) in (2020..).zip(prices) {
+            series.insert(PricePoint { date: NaiveDate::from_ymd_opt(year, 1, 1).unwrap(), price, distribution: None });
+        }
+        series
+    }
+
+    // Returns of 10%, -10%, 20% and 0%
+    fn fund() -> PriceSeries {
+        series("IE00B4L5Y983", Some("USD"), [100.0, 110.0, 99.0, 118.8, 118.8])
+    }
+
+    // Half the returns of the fund
+    fn benchmark() -> PriceSeries {
+        series("US0378331005", Some("USD"), [100.0, 105.0, 99.75, 109.725, 109.725])
+    }
+
+    fn close(actual: Option<f64>, expected: f64) {
+        let actual = actual.unwrap();
+        assert!((actual - expected).abs() < 1e-6, "{} != {}", actual, expected);
+    }
+
+    #[test]
+    fn computes_metrics_of_a_known_series() {
+        let config = RiskConfig::default().with_window(None);
+        let metrics = compute(&fund(), Some(&benchmark()), &config);
+
+        let annualised = 1.188f64.powf(0.25) - 1.0;
+        let volatility = (0.05f64 / 3.0).sqrt();
+        let downside = 0.05; // Only the -10% year falls short: sqrt(0.01 / 4)
+        close(metrics.annualised_return, annualised * 100.0);
+        close(metrics.volatility, volatility * 100.0);
+        close(metrics.max_drawdown, -10.0);
+        close(metrics.downside_deviation, downside * 100.0);
+        close(metrics.sharpe, annualised / volatility);
+        close(metrics.sortino, annualised / downside);
+        close(metrics.beta, 2.0);
+    }
+
+    #[test]
+    fn risk_free_rate_lowers_the_ratios() {
+        let config = RiskConfig::default().with_window(None).with_risk_free_rate(2.0);
+        let metrics = compute(&fund(), None, &config);
+        let annualised = 1.188f64.powf(0.25) - 1.0;
+        close(metrics.sharpe, (annualised - 0.02) / (0.05f64 / 3.0).sqrt());
+        assert_eq!(metrics.beta, None);
+    }
+
+    #[test]
+    fn summarises_rolling_returns() {
+        let rolling = compute(&fund(), None, &RiskConfig::default().with_window(None)).rolling.unwrap();
+        assert_eq!(rolling.count, 4);
+        assert!((rolling.worst + 10.0).abs() < 1e-9);
+        assert!((rolling.best - 20.0).abs() < 1e-9);
+        assert!((rolling.average - 5.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn window_keeps_the_recent_prices() {
+        let metrics = compute(&fund(), None, &RiskConfig::default());
+        assert_eq!(metrics.start, NaiveDate::from_ymd_opt(2021, 1, 1));
+        close(metrics.annualised_return, ((118.8f64 / 110.0).powf(365.25 / 1095.0) - 1.0) * 100.0);
+    }
+
+    #[test]
+    fn leaves_out_funds_that_cannot_be_converted() {
+        let mut store = PriceStore::default();
+        store.add(fund());
+        store.add(series("US0378331005", None, [1.0, 2.0, 3.0, 4.0, 5.0]));
+        let rates = FxTable::from_csv("base,quote,rate\nUSD,EUR,0.5\n").unwrap();
+        let reporting = Reporting::new("EUR", rates);
+
+        let metrics = compute_store(&store, &RiskConfig::default().with_window(None), Some(&reporting));
+        assert_eq!(metrics.len(), 1);
+        // A constant rate changes no return
+        close(metrics["IE00B4L5Y983"].max_drawdown, -10.0);
+
+        assert_eq!(compute_store(&store, &RiskConfig::default(), None).len(), 2);
+    }
+}
@@ -0,0 +1,384 @@
+use std::collections::HashMap;
+use color_eyre::{Result, eyre::eyre};
+use crate::utils;
+
+// Field names shared by all issuer schemas
+pub mod fields {
+    pub const NAME: &str = "name";
+    pub const ISIN: &str = "isin";
+    pub const ASSET_CLASS: &str = "asset_class";
+    pub const TER: &str = "ter";
+    pub const CURRENCY: &str = "currency";
+    pub const AUM: &str = "aum";
+    pub const AUM_CURRENCY: &str = "aum_currency"; // Currency of `aum`, the fund currency by default
+    pub const AUM_SCALE: &str = "aum_scale";       // Multiplier of `aum`, e.g. 1000000 for "AUM (M)"
+    pub const CURRENCY_EXPOSURE: &str = "currency_exposure";
+    pub const DISTRIBUTION_POLICY: &str = "distribution_policy";
+    pub const REPLICATION: &str = "replication";
+    pub const HEDGING: &str = "hedging";
+    pub const LAUNCH_DATE: &str = "launch_date";
+    pub const AS_OF: &str = "as_of"; // Date the figures of the row are valid for
+    pub const BLOOMBERG: &str = "bloomberg";
+    pub const REUTERS: &str = "reuters";
+    pub const WKN: &str = "wkn";
+    pub const SEDOL: &str = "sedol";
+    pub const CUSIP: &str = "cusip";
+    pub const TRADING_CURRENCY: &str = "trading_currency"; // Currency of the listing, with `exchange`
+    // Performance fields are named by `performance::Period::field` and `index_field`
+
+    // Holdings files
+    pub const TICKER: &str = "ticker";
+    pub const SECTOR: &str = "sector";
+    pub const COUNTRY: &str = "country";
+    pub const ASSET_TYPE: &str = "asset_type";
+    pub const EXCHANGE: &str = "exchange"; // Also the exchange of a product list row's listing
+    pub const MARKET_VALUE: &str = "market_value";
+    pub const SHARES: &str = "shares";
+    pub const WEIGHT: &str = "weight";
+
+    // Price history files
+    pub const DATE: &str = "date";
+    pub const PRICE: &str = "price";
+    pub const DISTRIBUTION: &str = "distribution"; // Paid per share on the ex-date
+}
+
+// A column looked up by its header. Aliases are tried in order.
+#[derive(Debug, Clone)]
+pub struct Column {
+    pub field: String,
+    pub aliases: Vec<String>,
+    pub required: bool,
+}
+
+// Describes the columns an issuer spreadsheet is expected to have
+#[derive(Debug, Clone)]
+pub struct Schema {
+    pub issuer: String,
+    pub columns: Vec<Column>,
+    // Headers split over two rows (a group row above the column names).
+    // Empty column names are taken from the group row.
+    pub group_header_row: bool,
+    // Cell text the header row must contain, on top of the required columns
+    pub marker: Option<String>,
+}
+
+// Values of a row by field name. Empty cells are left out.
+pub type Record = HashMap<String, String>;
+
+// Header normalisation: case, surrounding and repeated whitespace are ignored
+fn normalize(header: &str) -> String {
+    header.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
+
+impl Schema {
+    pub fn new(issuer: &str) -> Self {
+        Self {
+            issuer: issuer.to_string(),
+            columns: Vec::new(),
+            group_header_row: false,
+            marker: None,
+        }
+    }
+
+    pub fn required(mut self, field: &str, aliases: &[&str]) -> Self {
+        self.columns.push(Column {
+            field: field.to_string(),
+            aliases: aliases.iter().map(|alias| alias.to_string()).collect(),
+            required: true,
+        });
+        self
+    }
+
+    pub fn optional(mut self, field: &str, aliases: &[&str]) -> Self {
+        self.columns.push(Column {
+            field: field.to_string(),
+            aliases: aliases.iter().map(|alias| alias.to_string()).collect(),
+            required: false,
+        });
+        self
+    }
+
+    pub fn with_group_header_row(mut self) -> Self {
+        self.group_header_row = true;
+        self
+    }
+
+    pub fn with_marker(mut self, marker: &str) -> Self {
+        self.marker = Some(marker.to_string());
+        self
+    }
+
+    fn has_marker(&self, header: &[String]) -> bool {
+        self.marker.as_ref().is_none_or(|marker| {
+            let marker = normalize(marker);
+            header.iter().any(|cell| normalize(cell) == marker)
+        })
+    }
+
+    // Map every known field to its column index and header text in `header`
+    fn match_columns(&self, header: &[String]) -> HashMap<String, (usize, String)> {
+        let normalized: Vec<String> = header.iter().map(|cell| normalize(cell)).collect();
+
+        self.columns.iter()
+            .filter_map(|column| {
+                column.aliases.iter()
+                    .find_map(|alias| {
+                        let alias = normalize(alias);
+                        normalized.iter().position(|cell| *cell == alias)
+                    })
+                    .map(|index| (column.field.clone(), (index, header[index].trim().to_string())))
+            })
+            .collect()
+    }
+
+    fn missing_columns(&self, indices: &HashMap<String, (usize, String)>) -> Vec<&Column> {
+        self.columns.iter()
+            .filter(|column| column.required && !indices.contains_key(&column.field))
+            .collect()
+    }
+
+    // Header of row `index`, completed from the group row above it when enabled
+    pub fn header_at(&self, rows: &[Vec<String>], index: usize) -> Vec<String> {
+        let mut header = rows[index].clone();
+        if self.group_header_row && index > 0 {
+            for (col, cell) in header.iter_mut().enumerate() {
+                if cell.trim().is_empty() {
+                    if let Some(group) = rows[index - 1].get(col) {
+                        cell.clone_from(group);
+                    }
+                }
+            }
+        }
+        header
+    }
+
+    // Resolve the columns of a header row, failing if a required column is missing
+    pub fn resolve(&self, header: &[String]) -> Result<ColumnMap> {
+        let indices = self.match_columns(header);
+        let missing = self.missing_columns(&indices);
+
+        if !missing.is_empty() {
+            let expected: Vec<String> = missing.iter()
+                .map(|column| format!("{} (one of {:?})", column.field, column.aliases))
+                .collect();
+            let found: Vec<&str> = header.iter()
+                .map(|cell| cell.trim())
+                .filter(|cell| !cell.is_empty())
+                .collect();
+            return Err(eyre!(
+                "Missing required columns in {} file: expected {}; found headers {:?}",
+                self.issuer,
+                expected.join(", "),
+                found
+            ));
+        }
+
+        if !self.has_marker(header) {
+            return Err(eyre!(
+                "Header row of {} file has no {:?} column; found headers {:?}",
+                self.issuer,
+                self.marker.as_deref().unwrap_or_default(),
+                header.iter().map(|cell| cell.trim()).filter(|cell| !cell.is_empty()).collect::<Vec<_>>()
+            ));
+        }
+
+        Ok(ColumnMap { indices })
+    }
+
+    // Find the header row: the first row that has every required column.
+    // Returns the index of the header row and its column map.
+    pub fn find_header(&self, rows: &[Vec<String>]) -> Result<(usize, ColumnMap)> {
+        let mut best: Option<(usize, usize)> = None; // (row index, matched columns)
+
+        for index in 0..rows.len() {
+            let header = self.header_at(rows, index);
+            let indices = self.match_columns(&header);
+
+            if self.missing_columns(&indices).is_empty() && self.has_marker(&header) {
+                return Ok((index, ColumnMap { indices }));
+            }
+            if best.is_none_or(|(_, matched)| indices.len() > matched) {
+                best = Some((index, indices.len()));
+            }
+        }
+
+        // Report against the row that looked most like a header
+        match best {
+            Some((index, _)) => self.resolve(&self.header_at(rows, index)).map(|columns| (index, columns)),
+            None => Err(eyre!("{} file is empty", self.issuer)),
+        }
+    }
+
+    // Find every section of a sheet that repeats its header before each block of rows
+    // (e.g. one block per asset class). A section runs until the next header row.
+    pub fn find_sections(&self, rows: &[Vec<String>]) -> Result<Vec<Section>> {
+        let mut headers = Vec::new();
+        for index in 0..rows.len() {
+            let header = self.header_at(rows, index);
+            let indices = self.match_columns(&header);
+            if self.missing_columns(&indices).is_empty() && self.has_marker(&header) {
+                headers.push((index, ColumnMap { indices }));
+            }
+        }
+
+        if headers.is_empty() {
+            // Fails with the list of expected and found headers
+            self.find_header(rows)?;
+        }
+
+        let starts: Vec<usize> = headers.iter().map(|(index, _)| *index).collect();
+        Ok(headers.into_iter()
+            .enumerate()
+            .map(|(i, (header_row, columns))| Section {
+                header_row,
+                end: starts.get(i + 1).copied().unwrap_or(rows.len()),
+                columns,
+            })
+            .collect())
+    }
+}
+
+// A block of rows sharing one header
+#[derive(Debug, Clone)]
+pub struct Section {
+    pub header_row: usize,
+    pub end: usize, // Exclusive
+    pub columns: ColumnMap,
+}
+
+impl Section {
+    // Data rows of the section, after its header
+    pub fn rows<'a>(&self, rows: &'a [Vec<String>]) -> &'a [Vec<String>] {
+        &rows[self.header_row + 1..self.end]
+    }
+}
+
+// Column indices of a resolved header
+#[derive(Debug, Clone, Default)]
+pub struct ColumnMap {
+    indices: HashMap<String, (usize, String)>,
+}
+
+impl ColumnMap {
+    pub fn index(&self, field: &str) -> Option<usize> {
+        self.indices.get(field).map(|(index, _)| *index)
+    }
+
+    // Header text the field was found under
+    pub fn header(&self, field: &str) -> Option<&str> {
+        self.indices.get(field).map(|(_, header)| header.as_str())
+    }
+
+    // Raw cell of `field` in `row`
+    pub fn get<'a>(&self, row: &'a [String], field: &str) -> Option<&'a str> {
+        self.index(field)
+            .and_then(|index| row.get(index))
+            .map(|cell| cell.as_str())
+    }
+
+    // Trimmed cell of `field`, None if empty or a placeholder
+    pub fn text(&self, row: &[String], field: &str) -> Option<String> {
+        self.get(row, field).and_then(utils::non_empty)
+    }
+
+    pub fn number(&self, row: &[String], field: &str) -> Option<f64> {
+        self.get(row, field).and_then(utils::parse_number)
+    }
+
+    // Non-empty values of every resolved field
+    pub fn record(&self, row: &[String]) -> Record {
+        self.indices.keys()
+            .filter_map(|field| self.text(row, field).map(|value| (field.clone(), value)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rows(rows: &[&[&str]]) -> Vec<Vec<String>> {
+        rows.iter().map(|row| row.iter().map(|cell| cell.to_string()).collect()).collect()
+    }
+
+    fn schema() -> Schema {
+        Schema::new("Test")
+            .required(fields::NAME, &["Fund Name", "Name"])
+            .required(fields::ISIN, &["ISIN"])
+            .optional(fields::TER, &["TER (%)", "OCF (%)"])
+    }
+
+    #[test]
+    fn missing_columns_list_the_expected_and_found_headers() {
+        let error = schema().find_header(&rows(&[
+            &["Spreadsheet created on 28 Mar 25"],
+            &["Fund Name", "Ticker", "", "TER (%)"],
+        ])).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            r#"Missing required columns in Test file: expected isin (one of ["ISIN"]); found headers ["Fund Name", "Ticker", "TER (%)"]"#,
+        );
+        assert_eq!(schema().find_header(&[]).unwrap_err().to_string(), "Test file is empty");
+    }
+
+    #[test]
+    fn aliases_are_tried_in_order() {
+        // "Fund Name" wins over "Name" wherever they are, and headers match loosely
+        let (index, columns) = schema().find_header(&rows(&[
+            &["Name", "isin", " fund   name ", "OCF (%)"],
+        ])).unwrap();
+        assert_eq!(index, 0);
+        assert_eq!(columns.index(fields::NAME), Some(2));
+        assert_eq!(columns.header(fields::NAME), Some("fund   name"));
+        assert_eq!(columns.header(fields::TER), Some("OCF (%)"));
+    }
+
+    #[test]
+    fn group_rows_name_empty_headers() {
+        let rows = rows(&[
+            &["Fund Name", "Identifiers", ""],
+            &["", "ISIN", "TER (%)"],
+            &["iShares Core S&P 500", "IE00B5BMR087", "0.07"],
+        ]);
+        assert!(schema().find_header(&rows).is_err());
+
+        let schema = schema().with_group_header_row();
+        let (index, columns) = schema.find_header(&rows).unwrap();
+        assert_eq!(index, 1);
+        assert_eq!(columns.text(&rows[2], fields::NAME).as_deref(), Some("iShares Core S&P 500"));
+        assert_eq!(columns.number(&rows[2], fields::TER), Some(0.07));
+    }
+
+    #[test]
+    fn marker_picks_the_header_row() {
+        let rows = rows(&[
+            &["Name", "ISIN"],
+            &["Name", "ISIN", "Bloomberg"],
+        ]);
+        assert_eq!(schema().find_header(&rows).unwrap().0, 0);
+        assert_eq!(schema().with_marker("bloomberg").find_header(&rows).unwrap().0, 1);
+
+        let error = schema().with_marker("Bloomberg").resolve(&rows[0]).unwrap_err();
+        assert_eq!(error.to_string(), r#"Header row of Test file has no "Bloomberg" column; found headers ["Name", "ISIN"]"#);
+    }
+
+    #[test]
+    fn sections_run_to_the_next_header() {
+        let rows = rows(&[
+            &["Equity"],
+            &["Name", "ISIN"],
+            &["Fund A", "IE00B5BMR087"],
+            &["Fund B", "IE00B4L5Y983"],
+            &["ISIN", "Name"],
+            &["IE00B3YCGJ38", "Fund C"],
+        ]);
+        let sections = schema().find_sections(&rows).unwrap();
+        assert_eq!(sections.len(), 2);
+        assert_eq!(sections[0].rows(&rows).len(), 2);
+        assert_eq!(sections[1].rows(&rows).len(), 1);
+        assert_eq!(sections[1].columns.text(&rows[5], fields::NAME).as_deref(), Some("Fund C"));
+
+        let error = schema().find_sections(&rows[..1]).unwrap_err();
+        assert!(error.to_string().starts_with("Missing required columns in Test file"));
+    }
+}
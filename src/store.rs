@@ -0,0 +1,586 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use chrono::{DateTime, NaiveDate, Utc};
+use color_eyre::{Result, eyre::eyre, eyre::WrapErr};
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use crate::etf::{ETF, Holding, HoldingsBasis};
+use crate::provider::IssuerReport;
+
+const DEFAULT_PATH: &str = "etf_tool.db";
+
+// Every refresh adds a snapshot; nothing is updated in place, so the history of each fund is kept.
+// Funds are stored as JSON next to the columns they are looked up by. Securities hold the latest
+// known metadata of every holding with an ISIN.
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS snapshots (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    taken_at TEXT NOT NULL,
+    kind TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS issuer_reports (
+    snapshot_id INTEGER NOT NULL REFERENCES snapshots(id),
+    issuer TEXT NOT NULL,
+    etf_count INTEGER NOT NULL,
+    as_of TEXT,
+    issues TEXT NOT NULL,
+    error TEXT
+);
+CREATE TABLE IF NOT EXISTS etfs (
+    snapshot_id INTEGER NOT NULL REFERENCES snapshots(id),
+    position INTEGER NOT NULL,
+    issuer TEXT NOT NULL,
+    isin TEXT NOT NULL,
+    name TEXT NOT NULL,
+    as_of TEXT,
+    data TEXT NOT NULL,
+    PRIMARY KEY (snapshot_id, position)
+);
+CREATE INDEX IF NOT EXISTS etfs_isin ON etfs (isin, snapshot_id);
+CREATE TABLE IF NOT EXISTS holdings_sets (
+    snapshot_id INTEGER NOT NULL REFERENCES snapshots(id),
+    fund_isin TEXT NOT NULL,
+    issuer TEXT NOT NULL,
+    as_of TEXT,
+    basis TEXT NOT NULL,
+    PRIMARY KEY (snapshot_id, fund_isin)
+);
+CREATE INDEX IF NOT EXISTS holdings_sets_fund ON holdings_sets (fund_isin, snapshot_id);
+CREATE TABLE IF NOT EXISTS holdings (
+    snapshot_id INTEGER NOT NULL,
+    fund_isin TEXT NOT NULL,
+    position INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    isin TEXT,
+    ticker TEXT,
+    sector TEXT,
+    country TEXT,
+    currency TEXT,
+    asset_type TEXT,
+    exchange TEXT,
+    market_value REAL,
+    shares REAL,
+    weight REAL NOT NULL,
+    PRIMARY KEY (snapshot_id, fund_isin, position)
+);
+CREATE TABLE IF NOT EXISTS securities (
+    isin TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    ticker TEXT,
+    sector TEXT,
+    country TEXT,
+    currency TEXT,
+    asset_type TEXT,
+    exchange TEXT,
+    first_seen INTEGER NOT NULL,
+    last_seen INTEGER NOT NULL
+);
+";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnapshotKind {
+    Catalog,  // Fund lists of every issuer
+    Holdings, // Holdings of the funds that were loaded
+}
+
+impl SnapshotKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Catalog => "catalog",
+            Self::Holdings => "holdings",
+        }
+    }
+
+    fn parse(text: &str) -> Result<Self> {
+        match text {
+            "catalog" => Ok(Self::Catalog),
+            "holdings" => Ok(Self::Holdings),
+            _ => Err(eyre!("Unknown snapshot kind {:?}", text)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    pub id: i64,
+    pub taken_at: DateTime<Utc>,
+    pub kind: SnapshotKind,
+    pub funds: usize, // Funds in a catalog snapshot, funds with holdings in a holdings snapshot
+}
+
+// A catalog as it was stored, with the holdings of each fund known at the time (none when
+// read by `Store::catalog_funds`)
+#[derive(Debug, Clone)]
+pub struct StoredCatalog {
+    pub snapshot: Snapshot,
+    pub etfs: Vec<ETF>,
+    pub reports: Vec<IssuerReport>,
+}
+
+// Holdings of one fund in one snapshot
+#[derive(Debug, Clone)]
+pub struct StoredHoldings {
+    pub snapshot: Snapshot,
+    pub fund_isin: String,
+    pub as_of: Option<NaiveDate>,
+    pub basis: HoldingsBasis,
+    pub holdings: Vec<Holding>,
+}
+
+// Latest known metadata of a security held by a fund
+#[derive(Debug, Clone, PartialEq)]
+pub struct Security {
+    pub isin: String,
+    pub name: String,
+    pub ticker: Option<String>,
+    pub sector: Option<String>,
+    pub country: Option<String>,
+    pub currency: Option<String>,
+    pub asset_type: Option<String>,
+    pub exchange: Option<String>,
+    pub first_seen: i64, // Snapshot ids
+    pub last_seen: i64,
+}
+
+fn basis_name(basis: HoldingsBasis) -> &'static str {
+    match basis {
+        HoldingsBasis::Portfolio => "portfolio",
+        HoldingsBasis::SubstituteBasket => "substitute_basket",
+    }
+}
+
+fn parse_basis(text: &str) -> HoldingsBasis {
+    match text {
+        "substitute_basket" => HoldingsBasis::SubstituteBasket,
+        _ => HoldingsBasis::Portfolio,
+    }
+}
+
+fn parse_date(text: Option<String>) -> Option<NaiveDate> {
+    text.and_then(|text| text.parse().ok())
+}
+
+fn holding_from_row(row: &Row) -> rusqlite::Result<Holding> {
+    Ok(Holding {
+        name: row.get("name")?,
+        isin: row.get("isin")?,
+        ticker: row.get("ticker")?,
+        sector: row.get("sector")?,
+        country: row.get("country")?,
+        currency: row.get("currency")?,
+        asset_type: row.get("asset_type")?,
+        exchange: row.get("exchange")?,
+        market_value: row.get("market_value")?,
+        shares: row.get("shares")?,
+        weight: row.get("weight")?,
+    })
+}
+
+// Snapshots of catalogs and holdings in a SQLite database
+pub struct Store {
+    connection: Connection,
+    path: Option<PathBuf>, // None in memory
+}
+
+impl Store {
+    pub fn open(path: &Path) -> Result<Self> {
+        let connection = Connection::open(path)
+            .wrap_err_with(|| format!("Failed to open database {}", path.display()))?;
+        Self::init(connection, Some(path.to_path_buf()))
+    }
+
+    pub fn open_in_memory() -> Result<Self> {
+        Self::init(Connection::open_in_memory()?, None)
+    }
+
+    // Database in ETF_TOOL_DB, etf_tool.db by default
+    pub fn from_env() -> Result<Self> {
+        let path = std::env::var("ETF_TOOL_DB").unwrap_or_else(|_| DEFAULT_PATH.to_string());
+        Self::open(Path::new(&path))
+    }
+
+    fn init(connection: Connection, path: Option<PathBuf>) -> Result<Self> {
+        connection.execute_batch(SCHEMA).wrap_err("Failed to create the database tables")?;
+        Ok(Self { connection, path })
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    fn insert_snapshot(connection: &Connection, kind: SnapshotKind) -> Result<i64> {
+        connection.execute(
+            "INSERT INTO snapshots (taken_at, kind) VALUES (?1, ?2)",
+            params![Utc::now().to_rfc3339(), kind.as_str()],
+        )?;
+        Ok(connection.last_insert_rowid())
+    }
+
+    // Store the funds and issuer reports of a catalog load. Holdings are stored by `save_holdings`.
+    pub fn save_catalog(&mut self, etfs: &[ETF], reports: &[IssuerReport]) -> Result<Snapshot> {
+        let transaction = self.connection.transaction()?;
+        let id = Self::insert_snapshot(&transaction, SnapshotKind::Catalog)?;
+
+        for report in reports {
+            transaction.execute(
+                "INSERT INTO issuer_reports (snapshot_id, issuer, etf_count, as_of, issues, error)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    id,
+                    report.issuer,
+                    report.etf_count,
+                    report.as_of.map(|date| date.to_string()),
+                    serde_json::to_string(&report.issues)?,
+                    report.error,
+                ],
+            )?;
+        }
+
+        for (position, etf) in etfs.iter().enumerate() {
+            let data = serde_json::to_string(&ETF { holdings: Vec::new(), ..etf.clone() })?;
+            transaction.execute(
+                "INSERT INTO etfs (snapshot_id, position, issuer, isin, name, as_of, data)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![id, position, etf.issuer, etf.isin, etf.name, etf.as_of.map(|date| date.to_string()), data],
+            )?;
+        }
+
+        transaction.commit().wrap_err("Failed to save the catalog")?;
+        self.snapshot(id)?.ok_or_else(|| eyre!("Snapshot {} was not saved", id))
+    }
+
+    // Store the holdings of every fund that has some. None if no fund has holdings.
+    pub fn save_holdings(&mut self, etfs: &[ETF]) -> Result<Option<Snapshot>> {
+        let funds: Vec<&ETF> = etfs.iter().filter(|etf| !etf.holdings.is_empty()).collect();
+        if funds.is_empty() {
+            return Ok(None);
+        }
+
+        let transaction = self.connection.transaction()?;
+        let id = Self::insert_snapshot(&transaction, SnapshotKind::Holdings)?;
+
+        for etf in funds {
+            transaction.execute(
+                "INSERT OR REPLACE INTO holdings_sets (snapshot_id, fund_isin, issuer, as_of, basis)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![id, etf.isin, etf.issuer, etf.holdings_as_of.map(|date| date.to_string()), basis_name(etf.holdings_basis)],
+            )?;
+            for (position, holding) in etf.holdings.iter().enumerate() {
+                transaction.execute(
+                    "INSERT OR REPLACE INTO holdings (snapshot_id, fund_isin, position, name, isin, ticker, sector, country,
+                         currency, asset_type, exchange, market_value, shares, weight)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+                    params![
+                        id, etf.isin, position, holding.name, holding.isin, holding.ticker, holding.sector, holding.country,
+                        holding.currency, holding.asset_type, holding.exchange, holding.market_value, holding.shares,
+                        holding.weight,
+                    ],
+                )?;
+
+                // Newer metadata replaces older, gaps are filled from what was known
+                let Some(isin) = &holding.isin else { continue };
+                transaction.execute(
+                    "INSERT INTO securities (isin, name, ticker, sector, country, currency, asset_type, exchange, first_seen, last_seen)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?9)
+                     ON CONFLICT (isin) DO UPDATE SET
+                         name = excluded.name,
+                         ticker = coalesce(excluded.ticker, ticker),
+                         sector = coalesce(excluded.sector, sector),
+                         country = coalesce(excluded.country, country),
+                         currency = coalesce(excluded.currency, currency),
+                         asset_type = coalesce(excluded.asset_type, asset_type),
+                         exchange = coalesce(excluded.exchange, exchange),
+                         last_seen = excluded.last_seen",
+                    params![
+                        isin, holding.name, holding.ticker, holding.sector, holding.country, holding.currency,
+                        holding.asset_type, holding.exchange, id,
+                    ],
+                )?;
+            }
+        }
+
+        transaction.commit().wrap_err("Failed to save holdings")?;
+        self.snapshot(id)
+    }
+
+    fn snapshot_from_row(row: &Row) -> rusqlite::Result<(i64, String, String, usize)> {
+        Ok((row.get("id")?, row.get("taken_at")?, row.get("kind")?, row.get("funds")?))
+    }
+
+    fn to_snapshot((id, taken_at, kind, funds): (i64, String, String, usize)) -> Result<Snapshot> {
+        Ok(Snapshot {
+            id,
+            taken_at: DateTime::parse_from_rfc3339(&taken_at)
+                .wrap_err_with(|| format!("Invalid date of snapshot {}", id))?
+                .with_timezone(&Utc),
+            kind: SnapshotKind::parse(&kind)?,
+            funds,
+        })
+    }
+
+    const SNAPSHOT_QUERY: &'static str = "
+        SELECT id, taken_at, kind,
+            CASE kind
+                WHEN 'catalog' THEN (SELECT count(*) FROM etfs WHERE snapshot_id = snapshots.id)
+                ELSE (SELECT count(*) FROM holdings_sets WHERE snapshot_id = snapshots.id)
+            END AS funds
+        FROM snapshots";
+
+    // Every snapshot, newest first
+    pub fn snapshots(&self) -> Result<Vec<Snapshot>> {
+        let mut statement = self.connection.prepare(&format!("{} ORDER BY id DESC", Self::SNAPSHOT_QUERY))?;
+        let rows = statement.query_map([], Self::snapshot_from_row)?;
+        rows.map(|row| Self::to_snapshot(row?)).collect()
+    }
+
+    pub fn snapshot(&self, id: i64) -> Result<Option<Snapshot>> {
+        let row = self.connection
+            .query_row(&format!("{} WHERE id = ?1", Self::SNAPSHOT_QUERY), [id], Self::snapshot_from_row)
+            .optional()?;
+        row.map(Self::to_snapshot).transpose()
+    }
+
+    pub fn latest_snapshot(&self, kind: SnapshotKind) -> Result<Option<Snapshot>> {
+        let row = self.connection
+            .query_row(
+                &format!("{} WHERE kind = ?1 ORDER BY id DESC LIMIT 1", Self::SNAPSHOT_QUERY),
+                [kind.as_str()],
+                Self::snapshot_from_row,
+            )
+            .optional()?;
+        row.map(Self::to_snapshot).transpose()
+    }
+
+    // The catalog stored last before snapshot `id`
+    pub fn previous_catalog(&self, id: i64) -> Result<Option<Snapshot>> {
+        let row = self.connection
+            .query_row(
+                &format!("{} WHERE kind = ?1 AND id < ?2 ORDER BY id DESC LIMIT 1", Self::SNAPSHOT_QUERY),
+                params![SnapshotKind::Catalog.as_str(), id],
+                Self::snapshot_from_row,
+            )
+            .optional()?;
+        row.map(Self::to_snapshot).transpose()
+    }
+
+    // Funds and reports of a catalog snapshot, without holdings, for comparing fund lists
+    pub fn catalog_funds(&self, snapshot_id: i64) -> Result<StoredCatalog> {
+        let snapshot = self.snapshot(snapshot_id)?
+            .filter(|snapshot| snapshot.kind == SnapshotKind::Catalog)
+            .ok_or_else(|| eyre!("No catalog snapshot {}", snapshot_id))?;
+
+        let mut statement = self.connection.prepare("SELECT data FROM etfs WHERE snapshot_id = ?1 ORDER BY position")?;
+        let rows = statement.query_map([snapshot_id], |row| row.get::<_, String>(0))?;
+        let mut etfs = Vec::new();
+        for data in rows {
+            etfs.push(serde_json::from_str(&data?).wrap_err("Invalid fund in the database")?);
+        }
+
+        let mut statement = self.connection.prepare(
+            "SELECT issuer, etf_count, as_of, issues, error FROM issuer_reports WHERE snapshot_id = ?1 ORDER BY rowid",
+        )?;
+        let rows = statement.query_map([snapshot_id], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, usize>(1)?, row.get::<_, Option<String>>(2)?, row.get::<_, String>(3)?, row.get::<_, Option<String>>(4)?))
+        })?;
+        let mut reports = Vec::new();
+        for row in rows {
+            let (issuer, etf_count, as_of, issues, error) = row?;
+            reports.push(IssuerReport {
+                issuer,
+                etf_count,
+                as_of: parse_date(as_of),
+                issues: serde_json::from_str(&issues).unwrap_or_default(),
+                error,
+            });
+        }
+
+        Ok(StoredCatalog { snapshot, etfs, reports })
+    }
+
+    // Funds and reports of a catalog snapshot, with the latest holdings of each fund stored
+    // before the next catalog: the holdings known while this catalog was the current one
+    pub fn catalog(&self, snapshot_id: i64) -> Result<StoredCatalog> {
+        let mut catalog = self.catalog_funds(snapshot_id)?;
+        let next_catalog: Option<i64> = self.connection.query_row(
+            "SELECT min(id) FROM snapshots WHERE kind = ?1 AND id > ?2",
+            params![SnapshotKind::Catalog.as_str(), snapshot_id],
+            |row| row.get(0),
+        )?;
+
+        // The holdings of every fund of the catalog in one query, grouped by fund
+        let mut statement = self.connection.prepare(
+            "SELECT holdings.*, sets.as_of AS set_as_of, sets.basis AS set_basis
+             FROM holdings_sets AS sets
+             JOIN holdings ON holdings.snapshot_id = sets.snapshot_id AND holdings.fund_isin = sets.fund_isin
+             WHERE sets.fund_isin IN (SELECT isin FROM etfs WHERE snapshot_id = ?1)
+               AND sets.snapshot_id = (
+                   SELECT max(latest.snapshot_id) FROM holdings_sets AS latest
+                   WHERE latest.fund_isin = sets.fund_isin AND latest.snapshot_id < ?2
+               )
+             ORDER BY holdings.fund_isin, holdings.position",
+        )?;
+        let rows = statement.query_map(params![snapshot_id, next_catalog.unwrap_or(i64::MAX)], |row| {
+            Ok((
+                row.get::<_, String>("fund_isin")?,
+                row.get::<_, Option<String>>("set_as_of")?,
+                row.get::<_, String>("set_basis")?,
+                holding_from_row(row)?,
+            ))
+        })?;
+        let mut holdings: HashMap<String, (Option<NaiveDate>, HoldingsBasis, Vec<Holding>)> = HashMap::new();
+        for row in rows {
+            let (fund_isin, as_of, basis, holding) = row?;
+            holdings.entry(fund_isin)
+                .or_insert_with(|| (parse_date(as_of), parse_basis(&basis), Vec::new()))
+                .2.push(holding);
+        }
+
+        for etf in &mut catalog.etfs {
+            if let Some((as_of, basis, fund_holdings)) = holdings.get(&etf.isin) {
+                etf.holdings = fund_holdings.clone();
+                etf.holdings_as_of = *as_of;
+                etf.holdings_basis = *basis;
+            }
+        }
+        Ok(catalog)
+    }
+
+    // The most recent catalog, None if none was stored yet
+    pub fn latest_catalog(&self) -> Result<Option<StoredCatalog>> {
+        match self.latest_snapshot(SnapshotKind::Catalog)? {
+            Some(snapshot) => self.catalog(snapshot.id).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    // Holdings of a fund in the latest snapshot up to `snapshot_id`, the latest of all if None
+    pub fn holdings(&self, fund_isin: &str, snapshot_id: Option<i64>) -> Result<Option<StoredHoldings>> {
+        let set = self.connection
+            .query_row(
+                "SELECT snapshot_id, as_of, basis FROM holdings_sets
+                 WHERE fund_isin = ?1 AND snapshot_id <= ?2 ORDER BY snapshot_id DESC LIMIT 1",
+                params![fund_isin, snapshot_id.unwrap_or(i64::MAX)],
+                |row| Ok((row.get::<_, i64>(0)?, row.get::<_, Option<String>>(1)?, row.get::<_, String>(2)?)),
+            )
+            .optional()?;
+        let Some((id, as_of, basis)) = set else { return Ok(None) };
+
+        let mut statement = self.connection.prepare(
+            "SELECT * FROM holdings WHERE snapshot_id = ?1 AND fund_isin = ?2 ORDER BY position",
+        )?;
+        let holdings = statement.query_map(params![id, fund_isin], holding_from_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        let snapshot = self.snapshot(id)?.ok_or_else(|| eyre!("Snapshot {} is missing", id))?;
+
+        Ok(Some(StoredHoldings {
+            snapshot,
+            fund_isin: fund_isin.to_string(),
+            as_of: parse_date(as_of),
+            basis: parse_basis(&basis),
+            holdings,
+        }))
+    }
+
+    // Holdings of a fund as they were on `date`: the last ones dated on or before it, by the
+    // issuer's date or, without one, the day they were stored
+    pub fn holdings_on(&self, fund_isin: &str, date: NaiveDate) -> Result<Option<StoredHoldings>> {
+        let id = self.connection
+            .query_row(
+                "SELECT holdings_sets.snapshot_id FROM holdings_sets JOIN snapshots ON snapshots.id = holdings_sets.snapshot_id
+                 WHERE fund_isin = ?1 AND coalesce(as_of, substr(taken_at, 1, 10)) <= ?2
+                 ORDER BY coalesce(as_of, substr(taken_at, 1, 10)) DESC, snapshot_id DESC LIMIT 1",
+                params![fund_isin, date.to_string()],
+                |row| row.get::<_, i64>(0),
+            )
+            .optional()?;
+        match id {
+            Some(id) => self.holdings(fund_isin, Some(id)),
+            None => Ok(None),
+        }
+    }
+
+    // Holdings snapshots of a fund, newest first
+    pub fn holdings_snapshots(&self, fund_isin: &str) -> Result<Vec<Snapshot>> {
+        let mut statement = self.connection.prepare(&format!(
+            "{} WHERE id IN (SELECT snapshot_id FROM holdings_sets WHERE fund_isin = ?1) ORDER BY id DESC",
+            Self::SNAPSHOT_QUERY,
+        ))?;
+        let rows = statement.query_map([fund_isin], Self::snapshot_from_row)?;
+        rows.map(|row| Self::to_snapshot(row?)).collect()
+    }
+
+    // Every stored version of a fund, oldest first
+    pub fn fund_history(&self, isin: &str) -> Result<Vec<(Snapshot, ETF)>> {
+        let mut statement = self.connection.prepare(
+            "SELECT snapshot_id, data FROM etfs WHERE isin = ?1 ORDER BY snapshot_id",
+        )?;
+        let rows = statement.query_map([isin], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)))?;
+
+        let mut history = Vec::new();
+        for row in rows {
+            let (id, data) = row?;
+            let snapshot = self.snapshot(id)?.ok_or_else(|| eyre!("Snapshot {} is missing", id))?;
+            history.push((snapshot, serde_json::from_str(&data).wrap_err("Invalid fund in the database")?));
+        }
+        Ok(history)
+    }
+
+    pub fn security(&self, isin: &str) -> Result<Option<Security>> {
+        let security = self.connection
+            .query_row("SELECT * FROM securities WHERE isin = ?1", [isin], |row| {
+                Ok(Security {
+                    isin: row.get("isin")?,
+                    name: row.get("name")?,
+                    ticker: row.get("ticker")?,
+                    sector: row.get("sector")?,
+                    country: row.get("country")?,
+                    currency: row.get("currency")?,
+                    asset_type: row.get("asset_type")?,
+                    exchange: row.get("exchange")?,
+                    first_seen: row.get("first_seen")?,
+                    last_seen: row.get("last_seen")?,
+                })
+            })
+            .optional()?;
+        Ok(security)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn etf(isin: &str) -> ETF {
+        ETF::for_test(isin, Vec::new())
+    }
+
+    fn with_holding(isin: &str, holding: &str) -> ETF {
+        ETF::for_test(isin, vec![Holding { name: holding.to_string(), weight: 100.0, ..Holding::default() }])
+    }
+
+    fn holding_names(catalog: &StoredCatalog) -> Vec<Vec<String>> {
+        catalog.etfs.iter()
+            .map(|etf| etf.holdings.iter().map(|holding| holding.name.clone()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn old_catalogs_keep_the_holdings_of_their_time() {
+        let mut store = Store::open_in_memory().unwrap();
+        let first = store.save_catalog(&[etf("IE00B4L5Y983"), etf("US0378331005")], &[]).unwrap();
+        store.save_holdings(&[with_holding("IE00B4L5Y983", "Old")]).unwrap();
+        let second = store.save_catalog(&[etf("IE00B4L5Y983")], &[]).unwrap();
+        store.save_holdings(&[with_holding("IE00B4L5Y983", "New")]).unwrap();
+
+        assert_eq!(holding_names(&store.catalog(first.id).unwrap()), vec![vec!["Old".to_string()], vec![]]);
+        assert_eq!(holding_names(&store.catalog(second.id).unwrap()), vec![vec!["New".to_string()]]);
+        assert_eq!(holding_names(&store.latest_catalog().unwrap().unwrap()), vec![vec!["New".to_string()]]);
+    }
+
+    #[test]
+    fn catalogs_keep_holdings_stored_before_them() {
+        let mut store = Store::open_in_memory().unwrap();
+        store.save_holdings(&[with_holding("IE00B4L5Y983", "Earlier")]).unwrap();
+        let catalog = store.save_catalog(&[etf("IE00B4L5Y983")], &[]).unwrap();
+        assert_eq!(holding_names(&store.catalog(catalog.id).unwrap()), vec![vec!["Earlier".to_string()]]);
+    }
+}
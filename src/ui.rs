@@ -1,23 +1,102 @@
+use std::collections::HashMap;
 use ratatui::{
     layout::{Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
-    widgets::{Block, Borders, Cell, Paragraph, Row, Table},
+    text::Line,
+    widgets::{Block, Borders, Cell, Paragraph, Row, Table, TableState},
     Frame,
 };
 
 use crate::etf::ETF;
+use crate::fx::Reporting;
+use crate::holdings_diff::{HoldingsDiff, WeightChange};
+use crate::overlap::Overlap;
+use crate::portfolio::{Gap, LookThrough};
+use crate::performance::Period;
+use crate::provider::IssuerReport;
+use crate::risk::RiskMetrics;
 
-pub fn render(frame: &mut Frame, etfs: &[ETF], selected_index: usize) {
+// What the main area shows
+pub enum Screen<'a> {
+    Funds { marked: &'a [String] }, // ISINs of the funds marked for comparison
+    HoldingsDiff(&'a HoldingsDiff), // Changes in the holdings of one fund
+    Overlap { overlaps: &'a [Overlap], selected: usize }, // Comparison of funds, pair by pair
+    LookThrough(&'a LookThrough),   // Underlying exposure of the portfolio
+}
+
+// Lines of the status area shown at most. Older lines scroll out of view.
+const MAX_STATUS_LINES: usize = 8;
+
+// Everything drawn in one frame
+pub struct View<'a> {
+    pub etfs: &'a [ETF],
+    pub selected_index: usize,
+    pub reports: &'a [IssuerReport],
+    pub status: &'a [String], // Notices shown below the issuer reports
+    pub reporting: Option<&'a Reporting>,
+    pub risk: &'a HashMap<String, RiskMetrics>, // By ISIN, for funds with a price history
+    pub screen: Screen<'a>,
+}
+
+pub fn render(frame: &mut Frame, view: &View) {
+    let status_lines = (view.reports.len() + view.status.len()).min(MAX_STATUS_LINES);
     let main_layout = Layout::default()
         .direction(Direction::Vertical)
         .constraints([
-            Constraint::Length(3),  // Title
-            Constraint::Min(0),     // Content
+            Constraint::Length(3),                         // Title
+            Constraint::Min(0),                            // Content
+            Constraint::Length(status_lines as u16 + 2),   // Status
         ])
         .split(frame.area());
 
     render_title(frame, main_layout[0]);
-    render_etf_table(frame, main_layout[1], etfs, selected_index);
+    let etfs = view.etfs;
+    match &view.screen {
+        Screen::Funds { marked } => {
+            render_etf_table(frame, main_layout[1], etfs, view.selected_index, marked, view.reporting, view.risk)
+        }
+        Screen::HoldingsDiff(diff) => render_holdings_diff(frame, main_layout[1], etfs, diff),
+        Screen::Overlap { overlaps, selected } => render_overlap(frame, main_layout[1], etfs, overlaps, *selected),
+        Screen::LookThrough(exposure) => render_look_through(frame, main_layout[1], exposure),
+    }
+    render_status(frame, main_layout[2], view.reports, view.status);
+}
+
+fn render_status(frame: &mut Frame, area: Rect, reports: &[IssuerReport], status: &[String]) {
+    let mut lines: Vec<Line> = reports.iter().map(|report| {
+        match &report.error {
+            None => Line::styled(
+                {
+                    let mut line = format!("{}: {} funds", report.issuer, report.etf_count);
+                    if let Some(as_of) = report.as_of {
+                        line.push_str(&format!(", as of {}", as_of));
+                    }
+                    if !report.issues.is_empty() {
+                        line.push_str(&format!(", {} identifier issues", report.issues.len()));
+                    }
+                    line
+                },
+                Style::default().fg(Color::Green),
+            ),
+            Some(error) => Line::styled(
+                format!("{}: failed: {}", report.issuer, error),
+                Style::default().fg(Color::Red),
+            ),
+        }
+    }).collect();
+    lines.extend(status.iter().map(|line| Line::styled(line.clone(), Style::default().fg(Color::Cyan))));
+
+
+    // Keep the latest lines in view
+    let hidden = lines.len().saturating_sub(area.height.saturating_sub(2) as usize);
+    let title = match hidden {
+        0 => "Issuers".to_string(),
+        hidden => format!("Issuers ({} earlier lines hidden)", hidden),
+    };
+    let status = Paragraph::new(lines)
+        .scroll((hidden as u16, 0))
+        .block(Block::default().borders(Borders::ALL).title(title));
+    frame.render_widget(status, area);
 }
 
 fn render_title(frame: &mut Frame, area: Rect) {
@@ -27,10 +106,22 @@ fn render_title(frame: &mut Frame, area: Rect) {
     frame.render_widget(title, area);
 }
 
-fn render_etf_table(frame: &mut Frame, area: Rect, etfs: &[ETF], selected_index: usize) {
-    let header_cells = ["Name", "ISIN", "Asset Class", "TER", "Currency", "AUM", "1Y Perf", "YTD Perf"]
-        .iter()
-        .map(|h| Cell::from(*h).style(Style::default().fg(Color::Yellow)));
+fn render_etf_table(
+    frame: &mut Frame,
+    area: Rect,
+    etfs: &[ETF],
+    selected_index: usize,
+    marked: &[String],
+    reporting: Option<&Reporting>,
+    risk: &HashMap<String, RiskMetrics>,
+) {
+    let aum_header = match reporting {
+        Some(reporting) => format!("AUM ({})", reporting.currency),
+        None => "AUM".to_string(),
+    };
+    let header_cells = ["Name", "ISIN", "Ticker", "Asset Class", "TER", "Currency", &aum_header, "1Y Perf", "YTD Perf", "As Of", "Vol", "Max DD", "Sharpe", "Sortino", "Beta"]
+        .into_iter()
+        .map(|h| Cell::from(h.to_string()).style(Style::default().fg(Color::Yellow)));
 
     let rows: Vec<Row> = etfs.iter().enumerate().map(|(idx, etf)| {
         let style = if idx == selected_index {
@@ -39,33 +130,57 @@ fn render_etf_table(frame: &mut Frame, area: Rect, etfs: &[ETF], selected_index:
             Style::default()
         };
 
-        let perf_1y = etf.performance_1y.map_or("N/A".to_string(), |p| format!("{:.2}%", p));
-        let perf_ytd = etf.performance_ytd.map_or("N/A".to_string(), |p| format!("{:.2}%", p));
+        let perf_1y = etf.performance.fund(Period::OneYear).map_or("N/A".to_string(), |p| format!("{:.2}%", p));
+        let perf_ytd = etf.performance.fund(Period::YearToDate).map_or("N/A".to_string(), |p| format!("{:.2}%", p));
+        // Funds whose AUM cannot be converted keep their own currency
+        let aum = reporting
+            .and_then(|reporting| reporting.convert(etf.aum.value.as_ref()?, etf.as_of))
+            .map_or_else(|| etf.aum.to_string(), |money| money.to_string());
+        let ticker = etf.listings.first().and_then(|listing| listing.ticker.as_deref())
+            .or(etf.identifiers.ticker.as_deref());
+        let metrics = risk.get(&etf.isin);
+        let percent = |value: Option<f64>| value.map_or("N/A".to_string(), |value| format!("{:.1}%", value));
+        let ratio = |value: Option<f64>| value.map_or("N/A".to_string(), |value| format!("{:.2}", value));
         // let url = etf.product_url.as_deref().unwrap_or("N/A");
 
+        let name = if marked.contains(&etf.isin) { format!("* {}", etf.name) } else { etf.name.clone() };
+
         Row::new(vec![
-            Cell::from(etf.name.clone()),
+            Cell::from(name),
             Cell::from(etf.isin.clone()),
-            Cell::from(etf.asset_class.clone()),
+            Cell::from(ticker.unwrap_or("N/A").to_string()),
+            Cell::from(etf.asset_class.as_ref().map_or("N/A".to_string(), |asset_class| asset_class.to_string())),
             Cell::from(format!("{:.2}%", etf.ter)),
             Cell::from(etf.currency.clone()),
-            Cell::from(etf.aum.clone()),
+            Cell::from(aum),
             Cell::from(perf_1y),
             Cell::from(perf_ytd),
+            Cell::from(etf.as_of.map_or("N/A".to_string(), |date| date.to_string())),
+            Cell::from(percent(metrics.and_then(|metrics| metrics.volatility))),
+            Cell::from(percent(metrics.and_then(|metrics| metrics.max_drawdown))),
+            Cell::from(ratio(metrics.and_then(|metrics| metrics.sharpe))),
+            Cell::from(ratio(metrics.and_then(|metrics| metrics.sortino))),
+            Cell::from(ratio(metrics.and_then(|metrics| metrics.beta))),
             // Cell::from(url),
         ]).style(style)
     }).collect();
 
     let widths = [
-        Constraint::Percentage(20),
-        Constraint::Percentage(12),
-        Constraint::Percentage(12),
-        Constraint::Percentage(6),
-        Constraint::Percentage(6),
-        Constraint::Percentage(10),
-        Constraint::Percentage(6),
-        Constraint::Percentage(6),
-        Constraint::Percentage(22),
+        Constraint::Percentage(17), // Name
+        Constraint::Percentage(10), // ISIN
+        Constraint::Percentage(5),  // Ticker
+        Constraint::Percentage(8),  // Asset Class
+        Constraint::Percentage(5),  // TER
+        Constraint::Percentage(4),  // Currency
+        Constraint::Percentage(9),  // AUM
+        Constraint::Percentage(6),  // 1Y Perf
+        Constraint::Percentage(6),  // YTD Perf
+        Constraint::Percentage(8),  // As Of
+        Constraint::Percentage(5),  // Vol
+        Constraint::Percentage(5),  // Max DD
+        Constraint::Percentage(4),  // Sharpe
+        Constraint::Percentage(4),  // Sortino
+        Constraint::Percentage(4),  // Beta
     ];
 
     let table = Table::new(rows, widths)
@@ -74,5 +189,239 @@ fn render_etf_table(frame: &mut Frame, area: Rect, etfs: &[ETF], selected_index:
         .column_spacing(1)
         .row_highlight_style(Style::default().bg(Color::DarkGray));
 
+    // Scrolls the table so the selected fund stays visible
+    let mut state = TableState::default().with_selected(Some(selected_index));
+    frame.render_stateful_widget(table, area, &mut state);
+}
+
+// Rows shown per group of changes
+const DIFF_ROWS: usize = 15;
+
+fn render_holdings_diff(frame: &mut Frame, area: Rect, etfs: &[ETF], diff: &HoldingsDiff) {
+    let name = etfs.iter().find(|etf| etf.isin == diff.fund_isin).map_or(diff.fund_isin.as_str(), |etf| etf.name.as_str());
+    let date = |date: Option<chrono::NaiveDate>| date.map_or("N/A".to_string(), |date| date.to_string());
+    let title = format!(
+        "{}: {} to {}, {} added, {} removed, turnover ~{:.1}% (d to close)",
+        name,
+        date(diff.from),
+        date(diff.to),
+        diff.added.len(),
+        diff.removed.len(),
+        diff.turnover,
+    );
+
+    let header_cells = ["", "Name", "ISIN", "Before", "After", "Change"]
+        .into_iter()
+        .map(|h| Cell::from(h.to_string()).style(Style::default().fg(Color::Yellow)));
+
+    let row = |label: &str, color: Color, change: &WeightChange| {
+        Row::new(vec![
+            Cell::from(label.to_string()).style(Style::default().fg(color)),
+            Cell::from(change.name.clone()),
+            Cell::from(change.isin.clone().unwrap_or_else(|| "N/A".to_string())),
+            Cell::from(format!("{:.2}%", change.before)),
+            Cell::from(format!("{:.2}%", change.after)),
+            Cell::from(format!("{:+.2}", change.change())),
+        ])
+    };
+    let rows: Vec<Row> = diff.added.iter().take(DIFF_ROWS).map(|change| row("Added", Color::Green, change))
+        .chain(diff.removed.iter().take(DIFF_ROWS).map(|change| row("Removed", Color::Red, change)))
+        .chain(diff.top_changes(DIFF_ROWS).iter().map(|change| row("Changed", Color::Cyan, change)))
+        .collect();
+
+    let widths = [
+        Constraint::Percentage(10),
+        Constraint::Percentage(40),
+        Constraint::Percentage(15),
+        Constraint::Percentage(10),
+        Constraint::Percentage(10),
+        Constraint::Percentage(10),
+    ];
+    let table = Table::new(rows, widths)
+        .header(Row::new(header_cells))
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .column_spacing(1);
+
+    frame.render_widget(table, area);
+}
+
+fn fund_name<'a>(etfs: &'a [ETF], isin: &'a str) -> &'a str {
+    etfs.iter().find(|etf| etf.isin == isin).map_or(isin, |etf| etf.name.as_str())
+}
+
+fn render_overlap(frame: &mut Frame, area: Rect, etfs: &[ETF], overlaps: &[Overlap], selected: usize) {
+    let layout = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(overlaps.len().min(8) as u16 + 3), // Pairs
+            Constraint::Min(0),                                  // Shared holdings of the selected pair
+        ])
+        .split(area);
+
+    let header_cells = ["Fund A", "Fund B", "Overlap", "Common", "Common A/B", "Active Share"]
+        .into_iter()
+        .map(|h| Cell::from(h.to_string()).style(Style::default().fg(Color::Yellow)));
+    let rows: Vec<Row> = overlaps.iter().map(|overlap| {
+        // Figures of a substitute basket are not about the index exposure
+        let style = if overlap.substitute_basket { Style::default().fg(Color::DarkGray) } else { Style::default() };
+        Row::new(vec![
+            Cell::from(fund_name(etfs, &overlap.fund_a).to_string()),
+            Cell::from(fund_name(etfs, &overlap.fund_b).to_string()),
+            Cell::from(format!("{:.1}%", overlap.weight_overlap)),
+            Cell::from(format!("{} of {}/{}", overlap.common, overlap.holdings_a, overlap.holdings_b)),
+            Cell::from(format!("{:.0}%/{:.0}%", overlap.common_share_a(), overlap.common_share_b())),
+            Cell::from(format!("{:.1}%", overlap.active_share)),
+        ]).style(style)
+    }).collect();
+    let widths = [
+        Constraint::Percentage(30), // Fund A
+        Constraint::Percentage(30), // Fund B
+        Constraint::Percentage(8),  // Overlap
+        Constraint::Percentage(12), // Common
+        Constraint::Percentage(10), // Common A/B
+        Constraint::Percentage(10), // Active Share
+    ];
+    let pairs = Table::new(rows, widths)
+        .header(Row::new(header_cells))
+        .block(Block::default().borders(Borders::ALL).title("Overlap (o to close)"))
+        .column_spacing(1)
+        .row_highlight_style(Style::default().bg(Color::DarkGray));
+    let mut state = TableState::default().with_selected(Some(selected));
+    frame.render_stateful_widget(pairs, layout[0], &mut state);
+
+    let Some(overlap) = overlaps.get(selected) else { return };
+    let title = format!(
+        "Shared holdings{}",
+        if overlap.substitute_basket { " (substitute basket, not the index exposure)" } else { "" },
+    );
+    let header_cells = ["Name", "ISIN", "Weight A", "Weight B", "Overlap"]
+        .into_iter()
+        .map(|h| Cell::from(h.to_string()).style(Style::default().fg(Color::Yellow)));
+    let rows: Vec<Row> = overlap.shared.iter().map(|holding| {
+        Row::new(vec![
+            Cell::from(holding.name.clone()),
+            Cell::from(holding.isin.clone().unwrap_or_else(|| "N/A".to_string())),
+            Cell::from(format!("{:.2}%", holding.weight_a)),
+            Cell::from(format!("{:.2}%", holding.weight_b)),
+            Cell::from(format!("{:.2}%", holding.overlap())),
+        ])
+    }).collect();
+    let widths = [
+        Constraint::Percentage(45), // Name
+        Constraint::Percentage(19), // ISIN
+        Constraint::Percentage(12), // Weight A
+        Constraint::Percentage(12), // Weight B
+        Constraint::Percentage(12), // Overlap
+    ];
+    let shared = Table::new(rows, widths)
+        .header(Row::new(header_cells))
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .column_spacing(1);
+    frame.render_widget(shared, layout[1]);
+}
+
+fn render_breakdown(frame: &mut Frame, area: Rect, title: &str, breakdown: &[(String, f64)]) {
+    let rows: Vec<Row> = breakdown.iter().map(|(label, weight)| {
+        Row::new(vec![Cell::from(label.clone()), Cell::from(format!("{:.2}%", weight))])
+    }).collect();
+    let table = Table::new(rows, [Constraint::Percentage(70), Constraint::Percentage(30)])
+        .block(Block::default().borders(Borders::ALL).title(title.to_string()))
+        .column_spacing(1);
     frame.render_widget(table, area);
-} 
\ No newline at end of file
+}
+
+fn render_look_through(frame: &mut Frame, area: Rect, exposure: &LookThrough) {
+    let layout = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(exposure.flagged.len() as u16 + 2), // Funds not looked through
+            Constraint::Min(0),                                    // Positions and breakdowns
+        ])
+        .split(area);
+
+    let lines: Vec<Line> = exposure.flagged.iter().map(|fund| {
+        let (note, color) = match fund.gap {
+            Gap::UnknownFund => ("not in the catalog", Color::Red),
+            Gap::NoHoldings => ("no holdings loaded, press h on the fund", Color::Red),
+            Gap::SubstituteBasket => ("holdings are a substitute basket", Color::Yellow),
+        };
+        Line::styled(
+            format!("{} ({:.1}%): {}", fund.name.as_deref().unwrap_or(&fund.fund), fund.weight, note),
+            Style::default().fg(color),
+        )
+    }).collect();
+    let title = format!(
+        "Portfolio: {} securities, {:.1}% looked through (p to close)",
+        exposure.securities,
+        exposure.covered,
+    );
+    frame.render_widget(Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(title)), layout[0]);
+
+    let columns = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(55), Constraint::Percentage(45)])
+        .split(layout[1]);
+    let breakdowns = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Ratio(1, 3), Constraint::Ratio(1, 3), Constraint::Ratio(1, 3)])
+        .split(columns[1]);
+
+    let header_cells = ["Name", "ISIN", "Weight", "Funds"]
+        .into_iter()
+        .map(|h| Cell::from(h.to_string()).style(Style::default().fg(Color::Yellow)));
+    let rows: Vec<Row> = exposure.positions.iter().map(|position| {
+        Row::new(vec![
+            Cell::from(position.name.clone()),
+            Cell::from(position.isin.clone().unwrap_or_else(|| "N/A".to_string())),
+            Cell::from(format!("{:.2}%", position.weight)),
+            Cell::from(position.funds.to_string()),
+        ])
+    }).collect();
+    let widths = [
+        Constraint::Percentage(50), // Name
+        Constraint::Percentage(26), // ISIN
+        Constraint::Percentage(14), // Weight
+        Constraint::Percentage(10), // Funds
+    ];
+    let positions = Table::new(rows, widths)
+        .header(Row::new(header_cells))
+        .block(Block::default().borders(Borders::ALL).title("Largest positions"))
+        .column_spacing(1);
+    frame.render_widget(positions, columns[0]);
+
+    render_breakdown(frame, breakdowns[0], "Countries", &exposure.countries);
+    render_breakdown(frame, breakdowns[1], "Sectors", &exposure.sectors);
+    render_breakdown(frame, breakdowns[2], "Currencies", &exposure.currencies);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::{backend::TestBackend, Terminal};
+
+    #[test]
+    fn status_area_keeps_the_latest_lines() {
+        let status: Vec<String> = (1..=20).map(|n| format!("Message {}", n)).collect();
+        let view = View {
+            etfs: &[],
+            selected_index: 0,
+            reports: &[],
+            status: &status,
+            reporting: None,
+            risk: &HashMap::new(),
+            screen: Screen::Funds { marked: &[] },
+        };
+        let mut terminal = Terminal::new(TestBackend::new(60, 30)).unwrap();
+        terminal.draw(|frame| render(frame, &view)).unwrap();
+
+        let rows: Vec<String> = terminal.backend().buffer().content()
+            .chunks(60)
+            .map(|row| row.iter().map(|cell| cell.symbol()).collect())
+            .collect();
+        let shown = |text: &str| rows.iter().any(|row| row.contains(&format!("{} ", text)));
+        assert!(shown("Message 20"));
+        assert!(shown("Message 13"));
+        assert!(!shown("Message 12"));
+        assert!(rows[30 - MAX_STATUS_LINES - 2].contains("12 earlier lines hidden"));
+    }
+}
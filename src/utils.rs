@@ -1,33 +1,66 @@
 use std::fs::File;
-use std::io::{copy, BufReader};
+use std::io::{BufRead, BufReader};
 use std::path::Path;
-use reqwest::blocking::Client;
+use std::sync::atomic::{AtomicBool, Ordering};
+use crate::http;
 use calamine::{open_workbook, Reader, Xlsx};
 use color_eyre::{Result, eyre::eyre, eyre::WrapErr};
 use quick_xml::events::Event;
 
-pub fn download_xlsx(url: &str, file_path: &str) -> Result<()> {
-    let client = Client::new();
-    let mut response = client.get(url)
-        .header("User-Agent", "Mozilla/5.0")
-        .send()
-        .wrap_err("Failed to send request")?;
-
-    // Check if the request was successful
-    if !response.status().is_success() {
-        return Err(eyre!("Failed to download file: HTTP {}", response.status()));
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+// Silence loader progress messages, e.g. while the TUI owns the terminal
+pub fn set_quiet(quiet: bool) {
+    QUIET.store(quiet, Ordering::Relaxed);
+}
+
+// Print a loader progress message unless quiet
+pub fn log(message: &str) {
+    if !QUIET.load(Ordering::Relaxed) {
+        println!("{}", message);
     }
+}
 
-    // Create a file to write to
-    let mut file = File::create(file_path).wrap_err("Failed to create file")?;
+// Parse a number as written in issuer files ("1,234.56", "0.35%", "-" for missing)
+pub fn parse_number(text: &str) -> Option<f64> {
+    text.trim().replace(['%', ','], "").parse().ok()
+}
+
+// Assert that two computed figures are equal up to rounding
+#[cfg(test)]
+pub fn assert_close(actual: f64, expected: f64) {
+    assert!((actual - expected).abs() < 1e-9, "{} != {}", actual, expected);
+}
+
+// Empty directory for the files of one test, under the system temporary directory
+#[cfg(test)]
+pub fn test_dir(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("etf-tool-test-{}-{}", std::process::id(), name));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
 
-    // Copy the response body to the file
-    copy(&mut response, &mut file).wrap_err("Failed to write response to file")?;
+// Trimmed copy of a cell, or None if it is empty or a placeholder
+pub fn non_empty(text: &str) -> Option<String> {
+    let text = text.trim();
+    if text.is_empty() || text == "-" || text == "N/A" {
+        None
+    } else {
+        Some(text.to_string())
+    }
+}
 
-    Ok(())
+pub fn download_xlsx(url: &str, file_path: &str) -> Result<()> {
+    http::client().download(url, Path::new(file_path))
 }
 
 pub fn read_xlsx(file_path: &str) -> Result<Vec<Vec<String>>> {
+    read_sheet(file_path, None)
+}
+
+// Read the rows of a worksheet, the first one if `sheet` is None
+pub fn read_sheet(file_path: &str, sheet: Option<&str>) -> Result<Vec<Vec<String>>> {
     // Open the workbook
     let path = Path::new(file_path);
     let extension = path.extension()
@@ -39,8 +72,11 @@ pub fn read_xlsx(file_path: &str) -> Result<Vec<Vec<String>>> {
     match extension.to_lowercase().as_str() {
         "xlsx" => {
             let mut workbook: Xlsx<_> = open_workbook(path).wrap_err("Failed to open XLSX workbook")?;
-            let sheet_name = workbook.sheet_names().get(0).cloned()
-                .ok_or_else(|| eyre!("No sheets found in workbook"))?;
+            let sheet_name = match sheet {
+                Some(sheet) => sheet.to_string(),
+                None => workbook.sheet_names().first().cloned()
+                    .ok_or_else(|| eyre!("No sheets found in workbook"))?,
+            };
 
             let sheet = workbook.worksheet_range(&sheet_name)
                 .wrap_err("Failed to get worksheet")?;
@@ -55,82 +91,88 @@ pub fn read_xlsx(file_path: &str) -> Result<Vec<Vec<String>>> {
         "xls" => {
             // For XML-based Excel files
             let file = File::open(path).wrap_err("Failed to open XML file")?;
-            let reader = BufReader::new(file);
-            let mut xml_reader = quick_xml::Reader::from_reader(reader);
-            xml_reader.trim_text(true);
-
-            let mut buf = Vec::new();
-            let mut current_row: Vec<String> = Vec::new();
-            let mut in_cell = false;
-            let mut in_data = false;
-            let mut cell_content = String::new();
-            let mut found_header = false;
-            let mut header_row_count = 0;
-
-            loop {
-                match xml_reader.read_event_into(&mut buf) {
-                    Ok(Event::Start(ref e)) => {
-                        match e.name().as_ref() {
-                            b"Row" => {
-                                current_row = Vec::new();
-                            }
-                            b"Cell" => {
-                                in_cell = true;
-                                cell_content = String::new();
-                            }
-                            b"Data" => {
-                                in_data = true;
-                            }
-                            _ => {}
-                        }
+            data = read_xml_spreadsheet(BufReader::new(file), sheet)?;
+        }
+        _ => return Err(eyre!("Unsupported file format: {}", extension)),
+    }
+
+    Ok(data)
+}
+
+// Read the rows of an XML Spreadsheet 2003 document (the ".xls" files iShares serves).
+// Rows of every worksheet are returned unless `sheet` names one.
+pub fn read_xml_spreadsheet<R: BufRead>(reader: R, sheet: Option<&str>) -> Result<Vec<Vec<String>>> {
+    let mut xml_reader = quick_xml::Reader::from_reader(reader);
+    xml_reader.trim_text(true);
+
+    let mut rows = Vec::new();
+    let mut buf = Vec::new();
+    let mut current_row: Vec<String> = Vec::new();
+    let mut in_data = false;
+    let mut cell_content = String::new();
+    let mut in_sheet = sheet.is_none();
+
+    loop {
+        match xml_reader.read_event_into(&mut buf) {
+            Ok(Event::Start(ref e)) => {
+                match e.name().as_ref() {
+                    b"Worksheet" if sheet.is_some() => {
+                        let name = e.try_get_attribute("ss:Name")?
+                            .map(|attr| attr.decode_and_unescape_value(&xml_reader).map(|value| value.to_string()))
+                            .transpose()?;
+                        in_sheet = name.as_deref() == sheet;
+                    }
+                    b"Row" => {
+                        current_row = Vec::new();
+                    }
+                    b"Cell" => {
+                        cell_content = String::new();
+                    }
+                    b"Data" => {
+                        in_data = true;
+                    }
+                    _ => {}
+                }
+            }
+            Ok(Event::Text(e)) if in_data => {
+                cell_content = e.unescape()?.to_string();
+            }
+            Ok(Event::End(ref e)) => {
+                match e.name().as_ref() {
+                    b"Row" if in_sheet && !current_row.is_empty() => {
+                        rows.push(std::mem::take(&mut current_row));
                     }
-                    Ok(Event::Text(e)) => {
-                        if in_data {
-                            cell_content = e.unescape()?.to_string();
-                        }
+                    b"Cell" => {
+                        current_row.push(cell_content.clone());
                     }
-                    Ok(Event::End(ref e)) => {
-                        match e.name().as_ref() {
-                            b"Row" => {
-                                if !current_row.is_empty() {
-                                    // Skip the header rows
-                                    if !found_header {
-                                        if current_row[1] == "Fund Name" {
-                                            found_header = true;
-                                        }
-                                        header_row_count += 1;
-                                    } else {
-                                        // Skip rows that don't look like ETF data
-                                        if current_row.len() >= 4 && 
-                                           !current_row[1].is_empty() && // Fund Name
-                                           !current_row[3].is_empty() && // Fund type
-                                           !current_row[1].contains("TER / OCF") &&
-                                           !current_row[1].contains("AUM") &&
-                                           !current_row[1].contains("As Of") {
-                                            data.push(current_row.clone());
-                                        }
-                                    }
-                                }
-                            }
-                            b"Cell" => {
-                                in_cell = false;
-                                current_row.push(cell_content.clone());
-                            }
-                            b"Data" => {
-                                in_data = false;
-                            }
-                            _ => {}
-                        }
+                    b"Data" => {
+                        in_data = false;
                     }
-                    Ok(Event::Eof) => break,
-                    Err(e) => return Err(eyre!("Error parsing XML: {}", e)),
                     _ => {}
                 }
-                buf.clear();
             }
+            Ok(Event::Eof) => break,
+            Err(e) => return Err(eyre!("Error parsing XML: {}", e)),
+            _ => {}
         }
-        _ => return Err(eyre!("Unsupported file format: {}", extension)),
+        buf.clear();
     }
 
-    Ok(data)
-} 
\ No newline at end of file
+    Ok(rows)
+}
+
+// Read every record of a CSV document. Records may have different lengths.
+pub fn read_csv(content: &str) -> Result<Vec<Vec<String>>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(content.trim_start_matches('\u{feff}').as_bytes());
+
+    let mut rows = Vec::new();
+    for record in reader.records() {
+        let record = record.wrap_err("Failed to parse CSV record")?;
+        rows.push(record.iter().map(|cell| cell.trim().to_string()).collect());
+    }
+
+    Ok(rows)
+}
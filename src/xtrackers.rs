@@ -1,28 +1,179 @@
-use std::path::Path;
 use color_eyre::Result;
-use crate::etf::ETF;
+use crate::dates;
+use crate::definition;
+use crate::etf::{ETF, Holding, HoldingsBasis};
+use crate::provider::{Capabilities, HoldingsStatus, IssuerProvider};
+use crate::schema::{fields, Schema};
 use crate::utils;
 
 const ISSUER: &str = "Xtrackers";
 
 pub fn get_xtrackers_etfs() -> Result<Vec<ETF>> {
-    let file_path = "data/xtrackers_etfs.xlsx";
+    definition::builtin(definition::XTRACKERS).load_etfs()
+}
 
-    if !Path::new(file_path).exists() {
-        panic!("File does not exist at: {}", file_path);
-    }
+fn constituent_schema() -> Schema {
+    Schema::new(ISSUER)
+        .required(fields::NAME, &["Name"])
+        .optional(fields::ISIN, &["ISIN"])
+        .optional(fields::COUNTRY, &["Country"])
+        .optional(fields::CURRENCY, &["Currency"])
+        .optional(fields::EXCHANGE, &["Exchange"])
+        .optional(fields::ASSET_TYPE, &["Type of Security"])
+        .optional(fields::SECTOR, &["Industry Classification"])
+        .required(fields::WEIGHT, &["Weighting", "Weight", "Weighting (%)", "Weight (%)"])
+}
+
+// Parse the rows of an Xtrackers constituent spreadsheet into the holdings of `etf`.
+// The sheet starts with a few rows describing the fund, followed by the header row
+// ("Row", "Name", "ISIN", "Country", ..., "Weighting") and the constituents.
+fn parse_constituent_rows(etf: &mut ETF, rows: &[Vec<String>]) -> Result<()> {
+    let (header_row_index, columns) = constituent_schema().find_header(rows)?;
+
+    let preamble = &rows[..header_row_index];
+    let preamble_cells = || preamble.iter().flatten().map(|cell| cell.trim()).filter(|cell| !cell.is_empty());
 
-    let raw_data = utils::read_xlsx(file_path)?;
-    
-    let header_row_index = raw_data.iter()
-        .position(|row| row.get(0).map_or(false, |cell| cell == "Name"))
-        .unwrap_or(6);
+    // The date is given as "As of: 27.03.2025" or as a cell following "As of"
+    let as_of = dates::find_as_of(preamble);
 
-    // Process data starting from the row after headers
-    let etfs: Vec<ETF> = raw_data.iter()
-        .skip(header_row_index + 1)
-        .filter_map(|row| ETF::from_row(row, ISSUER.to_string()))
+    let constituent_rows = &rows[header_row_index + 1..];
+    let mut holdings: Vec<Holding> = constituent_rows.iter()
+        .filter_map(|row| Holding::from_columns(row, &columns))
         .collect();
 
-    Ok(etfs)
-} 
\ No newline at end of file
+    // Swap-based funds publish the substitute basket they hold, together with the swap itself
+    let is_substitute_basket = preamble_cells().any(|cell| cell.to_lowercase().contains("substitute basket"))
+        || holdings.iter().any(|holding| {
+            holding.asset_type.as_deref().is_some_and(|asset_type| asset_type.to_lowercase().contains("swap"))
+        });
+
+    // Weights are exported as fractions of 1 unless the header or the cells are in percent.
+    // No single fraction is above 1, whereas the total is no guide on a partial list.
+    let in_percent = columns.header(fields::WEIGHT).is_some_and(|header| header.contains('%'))
+        || constituent_rows.iter().any(|row| columns.get(row, fields::WEIGHT).is_some_and(|cell| cell.contains('%')))
+        || holdings.iter().any(|holding| holding.weight.abs() > 1.0);
+    if !in_percent {
+        for holding in &mut holdings {
+            holding.weight *= 100.0;
+        }
+    }
+
+    etf.holdings = holdings;
+    etf.holdings_as_of = as_of;
+    etf.holdings_basis = if is_substitute_basket {
+        HoldingsBasis::SubstituteBasket
+    } else {
+        HoldingsBasis::Portfolio
+    };
+
+    Ok(())
+}
+
+// Load holdings for Xtrackers ETFs from the constituent spreadsheet published for each ISIN
+pub fn load_xtrackers_holdings(etf: &mut ETF) -> Result<HoldingsStatus> {
+    let url = format!(
+        "https://etf.dws.com/etfdata/export/GBR/ENG/excel/product/constituent/{}/",
+        etf.isin
+    );
+    let file_path = std::env::temp_dir().join(format!("xtrackers_constituents_{}.xlsx", etf.isin));
+    let file_path = file_path.to_string_lossy();
+
+    utils::log(&format!("Fetching holdings from URL: {}", url));
+
+    utils::download_xlsx(&url, &file_path)?;
+    let rows = utils::read_xlsx(&file_path);
+    let _ = std::fs::remove_file(file_path.as_ref());
+
+    parse_constituent_rows(etf, &rows?)?;
+
+    utils::log(&format!("Successfully loaded {} holdings", etf.holdings.len()));
+    if etf.holdings_basis == HoldingsBasis::SubstituteBasket {
+        utils::log(&format!("{} is swap-based: holdings are the substitute basket, not the index", etf.isin));
+    }
+
+    Ok(HoldingsStatus::Loaded(etf.holdings.len()))
+}
+
+pub struct XtrackersProvider;
+
+impl IssuerProvider for XtrackersProvider {
+    fn name(&self) -> &str {
+        ISSUER
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities { list_etfs: true, holdings: true, price_history: false }
+    }
+
+    fn list_etfs(&self) -> Result<Vec<ETF>> {
+        get_xtrackers_etfs()
+    }
+
+    fn load_holdings(&self, etf: &mut ETF) -> Result<HoldingsStatus> {
+        load_xtrackers_holdings(etf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn rows(rows: &[&[&str]]) -> Vec<Vec<String>> {
+        rows.iter().map(|row| row.iter().map(|cell| cell.to_string()).collect()).collect()
+    }
+
+    fn weights(etf: &ETF) -> Vec<f64> {
+        etf.holdings.iter().map(|holding| holding.weight).collect()
+    }
+
+    #[test]
+    fn reads_fractions_as_percent() {
+        let mut etf = ETF::for_test("IE00BJ0KDQ92", Vec::new());
+        parse_constituent_rows(&mut etf, &rows(&[
+            &["Xtrackers MSCI World UCITS ETF 1C"],
+            &["As of:", "27.03.2025"],
+            &["Row", "Name", "ISIN", "Type of Security", "Weighting"],
+            &["1", "APPLE INC", "US0378331005", "Equity", "0.05"],
+            &["2", "MICROSOFT CORP", "US5949181045", "Equity", "0.045"],
+        ])).unwrap();
+
+        assert_eq!(weights(&etf), [5.0, 4.5]);
+        assert_eq!(etf.holdings_as_of, NaiveDate::from_ymd_opt(2025, 3, 27));
+        assert_eq!(etf.holdings_basis, HoldingsBasis::Portfolio);
+    }
+
+    #[test]
+    fn keeps_weights_given_in_percent() {
+        // A truncated list whose percent weights add up to less than 1.5
+        let small = |header: &str, cells: [&str; 2]| {
+            let mut etf = ETF::for_test("IE00BJ0KDQ92", Vec::new());
+            parse_constituent_rows(&mut etf, &rows(&[
+                &["Name", "ISIN", header],
+                &["APPLE INC", "US0378331005", cells[0]],
+                &["MICROSOFT CORP", "US5949181045", cells[1]],
+            ])).unwrap();
+            weights(&etf)
+        };
+        assert_eq!(small("Weighting (%)", ["0.8", "0.5"]), [0.8, 0.5]);
+        assert_eq!(small("Weighting", ["0.8%", "0.5%"]), [0.8, 0.5]);
+        assert_eq!(small("Weighting", ["1.2", "0.1"]), [1.2, 0.1]);
+    }
+
+    #[test]
+    fn detects_substitute_baskets() {
+        let basis = |preamble: &str, asset_type: &str| {
+            let mut etf = ETF::for_test("LU0274208692", Vec::new());
+            parse_constituent_rows(&mut etf, &rows(&[
+                &[preamble],
+                &["Name", "ISIN", "Type of Security", "Weighting"],
+                &["SIEMENS AG", "DE0007236101", "Equity", "0.6"],
+                &["MSCI WORLD SWAP", "", asset_type, "0.4"],
+            ])).unwrap();
+            etf.holdings_basis
+        };
+        assert_eq!(basis("Holdings of the substitute basket", "Other"), HoldingsBasis::SubstituteBasket);
+        assert_eq!(basis("Xtrackers MSCI World Swap UCITS ETF", "Total Return Swap"), HoldingsBasis::SubstituteBasket);
+        assert_eq!(basis("Xtrackers MSCI World Swap UCITS ETF", "Other"), HoldingsBasis::Portfolio);
+    }
+}